@@ -0,0 +1,585 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use datafusion::{
+    catalog::{CatalogProvider, CatalogProviderList, SchemaProvider, TableProvider},
+    error::DataFusionError,
+};
+use datafusion_iceberg::DataFusionTable;
+use iceberg_rust::{
+    catalog::{
+        commit::{CommitTable, CommitView},
+        create::{CreateMaterializedView, CreateTable, CreateView},
+        identifier::Identifier,
+        namespace::Namespace,
+        tabular::Tabular,
+        Catalog, CatalogList,
+    },
+    error::Error,
+    materialized_view::MaterializedView,
+    table::Table,
+    view::View,
+};
+
+use crate::{
+    metadata_tables::{MetadataTable, MetadataTableProvider},
+    time_travel::TableVersion,
+};
+
+/// Catalog list that mounts several catalog lists side by side
+///
+/// Catalogs that are not mounted explicitly are resolved by the default catalog list.
+#[derive(Debug, Default)]
+pub struct MultiCatalogList {
+    catalogs: HashMap<String, (String, Arc<dyn CatalogList>)>,
+    default: Option<Arc<dyn CatalogList>>,
+}
+
+impl MultiCatalogList {
+    /// Create a new catalog list with an optional default catalog list
+    pub fn new(default: Option<Arc<dyn CatalogList>>) -> Self {
+        Self {
+            catalogs: HashMap::new(),
+            default,
+        }
+    }
+
+    /// Mount a catalog of the catalog list under the given name
+    ///
+    /// If the catalog list only provides a single catalog with a different name, like the Glue
+    /// or the REST catalog, that catalog is mounted under the given name. A catalog list with
+    /// several catalogs has to provide a catalog with the given name.
+    pub async fn mount(
+        &mut self,
+        name: &str,
+        catalog_list: Arc<dyn CatalogList>,
+    ) -> Result<(), Error> {
+        let catalogs = catalog_list.list_catalogs().await;
+        let inner = match catalogs.as_slice() {
+            [inner] => inner.clone(),
+            catalogs if catalogs.is_empty() || catalogs.iter().any(|x| x == name) => {
+                name.to_owned()
+            }
+            catalogs => {
+                return Err(Error::NotFound(format!(
+                    "Catalog {name} among the catalogs {}",
+                    catalogs.join(", ")
+                )))
+            }
+        };
+        self.catalogs.insert(name.to_owned(), (inner, catalog_list));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CatalogList for MultiCatalogList {
+    fn catalog(&self, name: &str) -> Option<Arc<dyn Catalog>> {
+        match self.catalogs.get(name) {
+            Some((inner, catalog_list)) => catalog_list.catalog(inner),
+            None => self.default.as_ref()?.catalog(name),
+        }
+    }
+
+    async fn list_catalogs(&self) -> Vec<String> {
+        let mut catalogs = match &self.default {
+            Some(default) => default.list_catalogs().await,
+            None => Vec::new(),
+        };
+        catalogs.retain(|name| !self.catalogs.contains_key(name));
+        catalogs.extend(self.catalogs.keys().cloned());
+        catalogs
+    }
+}
+
+/// Catalog operations that some of the bundled catalogs don't implement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogOperation {
+    DropNamespace,
+    LoadNamespace,
+    UpdateNamespace,
+    NamespaceExists,
+    DropTable,
+    DropView,
+    DropMaterializedView,
+    RegisterTable,
+}
+
+impl Display for CatalogOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CatalogOperation::DropNamespace => "dropping namespaces",
+            CatalogOperation::LoadNamespace => "loading namespaces",
+            CatalogOperation::UpdateNamespace => "updating namespace properties",
+            CatalogOperation::NamespaceExists => "checking if a namespace exists",
+            CatalogOperation::DropTable => "dropping tables",
+            CatalogOperation::DropView => "dropping views",
+            CatalogOperation::DropMaterializedView => "dropping materialized views",
+            CatalogOperation::RegisterTable => "registering tables",
+        })
+    }
+}
+
+/// Catalog list whose catalogs fail with [`Error::NotSupported`] on the operations that the
+/// inner catalogs leave unimplemented, instead of panicking
+#[derive(Debug)]
+pub struct SupportedCatalogList {
+    inner: Arc<dyn CatalogList>,
+    unsupported: &'static [CatalogOperation],
+}
+
+impl SupportedCatalogList {
+    pub fn new(inner: Arc<dyn CatalogList>, unsupported: &'static [CatalogOperation]) -> Self {
+        Self { inner, unsupported }
+    }
+}
+
+#[async_trait]
+impl CatalogList for SupportedCatalogList {
+    fn catalog(&self, name: &str) -> Option<Arc<dyn Catalog>> {
+        Some(Arc::new(SupportedCatalog {
+            inner: self.inner.catalog(name)?,
+            unsupported: self.unsupported,
+        }))
+    }
+
+    async fn list_catalogs(&self) -> Vec<String> {
+        self.inner.list_catalogs().await
+    }
+}
+
+#[derive(Debug)]
+struct SupportedCatalog {
+    inner: Arc<dyn Catalog>,
+    unsupported: &'static [CatalogOperation],
+}
+
+impl SupportedCatalog {
+    fn supports(&self, operation: CatalogOperation) -> Result<(), Error> {
+        if self.unsupported.contains(&operation) {
+            Err(Error::NotSupported(format!(
+                "{operation} in catalog {}",
+                self.inner.name()
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl Catalog for SupportedCatalog {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    async fn create_namespace(
+        &self,
+        namespace: &Namespace,
+        properties: Option<HashMap<String, String>>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.inner.create_namespace(namespace, properties).await
+    }
+    async fn drop_namespace(&self, namespace: &Namespace) -> Result<(), Error> {
+        self.supports(CatalogOperation::DropNamespace)?;
+        self.inner.drop_namespace(namespace).await
+    }
+    async fn load_namespace(
+        &self,
+        namespace: &Namespace,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.supports(CatalogOperation::LoadNamespace)?;
+        self.inner.load_namespace(namespace).await
+    }
+    async fn update_namespace(
+        &self,
+        namespace: &Namespace,
+        updates: Option<HashMap<String, String>>,
+        removals: Option<Vec<String>>,
+    ) -> Result<(), Error> {
+        self.supports(CatalogOperation::UpdateNamespace)?;
+        self.inner
+            .update_namespace(namespace, updates, removals)
+            .await
+    }
+    async fn namespace_exists(&self, namespace: &Namespace) -> Result<bool, Error> {
+        self.supports(CatalogOperation::NamespaceExists)?;
+        self.inner.namespace_exists(namespace).await
+    }
+    async fn list_tabulars(&self, namespace: &Namespace) -> Result<Vec<Identifier>, Error> {
+        self.inner.list_tabulars(namespace).await
+    }
+    async fn list_namespaces(&self, parent: Option<&str>) -> Result<Vec<Namespace>, Error> {
+        self.inner.list_namespaces(parent).await
+    }
+    async fn tabular_exists(&self, identifier: &Identifier) -> Result<bool, Error> {
+        self.inner.tabular_exists(identifier).await
+    }
+    async fn drop_table(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.supports(CatalogOperation::DropTable)?;
+        self.inner.drop_table(identifier).await
+    }
+    async fn drop_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.supports(CatalogOperation::DropView)?;
+        self.inner.drop_view(identifier).await
+    }
+    async fn drop_materialized_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.supports(CatalogOperation::DropMaterializedView)?;
+        self.inner.drop_materialized_view(identifier).await
+    }
+    async fn load_tabular(self: Arc<Self>, identifier: &Identifier) -> Result<Tabular, Error> {
+        self.inner.clone().load_tabular(identifier).await
+    }
+    async fn create_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_table: CreateTable,
+    ) -> Result<Table, Error> {
+        self.inner
+            .clone()
+            .create_table(identifier, create_table)
+            .await
+    }
+    async fn create_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateView<Option<()>>,
+    ) -> Result<View, Error> {
+        self.inner
+            .clone()
+            .create_view(identifier, create_view)
+            .await
+    }
+    async fn create_materialized_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateMaterializedView,
+    ) -> Result<MaterializedView, Error> {
+        self.inner
+            .clone()
+            .create_materialized_view(identifier, create_view)
+            .await
+    }
+    async fn update_table(self: Arc<Self>, commit: CommitTable) -> Result<Table, Error> {
+        self.inner.clone().update_table(commit).await
+    }
+    async fn update_view(self: Arc<Self>, commit: CommitView<Option<()>>) -> Result<View, Error> {
+        self.inner.clone().update_view(commit).await
+    }
+    async fn update_materialized_view(
+        self: Arc<Self>,
+        commit: CommitView<Identifier>,
+    ) -> Result<MaterializedView, Error> {
+        self.inner.clone().update_materialized_view(commit).await
+    }
+    async fn register_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        metadata_location: &str,
+    ) -> Result<Table, Error> {
+        self.supports(CatalogOperation::RegisterTable)?;
+        self.inner
+            .clone()
+            .register_table(identifier, metadata_location)
+            .await
+    }
+}
+
+/// DataFusion catalog list that resolves the table names of
+/// [`rewrite_table_references`](crate::sql::rewrite_table_references)
+///
+/// Versioned names created with [`TableVersion::table_name`] are read at the snapshot, names like
+/// `t$snapshots` are the metadata tables of `t` unless there is a table with that name. Metadata
+/// tables referenced as `db.t.snapshots` are resolved in the default catalog if there is no
+/// catalog `db`.
+#[derive(Debug)]
+pub struct FrostbowCatalogList {
+    inner: Arc<dyn CatalogProviderList>,
+    default_catalog: String,
+}
+
+impl FrostbowCatalogList {
+    pub fn new(inner: Arc<dyn CatalogProviderList>, default_catalog: &str) -> Self {
+        Self {
+            inner,
+            default_catalog: default_catalog.to_owned(),
+        }
+    }
+}
+
+impl CatalogProviderList for FrostbowCatalogList {
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    fn register_catalog(
+        &self,
+        name: String,
+        catalog: Arc<dyn CatalogProvider>,
+    ) -> Option<Arc<dyn CatalogProvider>> {
+        self.inner.register_catalog(name, catalog)
+    }
+
+    fn catalog_names(&self) -> Vec<String> {
+        self.inner.catalog_names()
+    }
+
+    fn catalog(&self, name: &str) -> Option<Arc<dyn CatalogProvider>> {
+        if let Some(catalog) = self.inner.catalog(name) {
+            return Some(Arc::new(FrostbowCatalog(catalog)));
+        }
+        let schema = self.inner.catalog(&self.default_catalog)?.schema(name)?;
+        Some(Arc::new(MetadataCatalog(Arc::new(FrostbowSchema(schema)))))
+    }
+}
+
+#[derive(Debug)]
+struct FrostbowCatalog(Arc<dyn CatalogProvider>);
+
+impl CatalogProvider for FrostbowCatalog {
+    fn as_any(&self) -> &dyn Any {
+        self.0.as_any()
+    }
+
+    fn schema_names(&self) -> Vec<String> {
+        self.0.schema_names()
+    }
+
+    fn schema(&self, name: &str) -> Option<Arc<dyn SchemaProvider>> {
+        self.0
+            .schema(name)
+            .map(|schema| Arc::new(FrostbowSchema(schema)) as Arc<dyn SchemaProvider>)
+    }
+
+    fn register_schema(
+        &self,
+        name: &str,
+        schema: Arc<dyn SchemaProvider>,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>, DataFusionError> {
+        self.0.register_schema(name, schema)
+    }
+
+    fn deregister_schema(
+        &self,
+        name: &str,
+        cascade: bool,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>, DataFusionError> {
+        self.0.deregister_schema(name, cascade)
+    }
+}
+
+#[derive(Debug)]
+struct FrostbowSchema(Arc<dyn SchemaProvider>);
+
+#[async_trait]
+impl SchemaProvider for FrostbowSchema {
+    fn owner_name(&self) -> Option<&str> {
+        self.0.owner_name()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.0.as_any()
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        self.0.table_names()
+    }
+
+    async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        let (name, version) = match TableVersion::from_table_name(name) {
+            Some((name, version)) => (name, Some(version)),
+            None => (name, None),
+        };
+        let (table, metadata_table) = match MetadataTable::from_table_name(name) {
+            Some((table, metadata_table)) => (table, Some(metadata_table)),
+            None => (name, None),
+        };
+        if version.is_none() {
+            // A table whose name contains `$` takes precedence over a metadata table
+            let provider = self.0.table(name).await?;
+            if provider.is_some() || metadata_table.is_none() {
+                return Ok(provider);
+            }
+        }
+        let Some(provider) = self.0.table(table).await? else {
+            // DataFusion would report the versioned name, which contains a NUL character
+            return match version {
+                Some(_) => Err(DataFusionError::Plan(format!(
+                    "Table {table} doesn't exist."
+                ))),
+                None => Ok(None),
+            };
+        };
+        let Some(provider) = provider.as_any().downcast_ref::<DataFusionTable>() else {
+            return Err(DataFusionError::Plan(format!(
+                "Time travel and metadata tables are only supported for Iceberg tables, {table} is not an Iceberg table."
+            )));
+        };
+        let tabular = provider
+            .tabular
+            .read()
+            .map_err(|_| {
+                DataFusionError::Execution(format!(
+                    "Table {table} can't be read, a writer of its metadata panicked."
+                ))
+            })?
+            .clone();
+        let snapshot_id = version
+            .map(|version| version.snapshot_id(&tabular))
+            .transpose()?;
+        match (metadata_table, tabular) {
+            (None, tabular) => Ok(Some(Arc::new(DataFusionTable::new(
+                tabular,
+                None,
+                snapshot_id,
+                None,
+            )))),
+            (Some(metadata_table), Tabular::Table(table)) => Ok(Some(Arc::new(
+                MetadataTableProvider::new(metadata_table, table, snapshot_id),
+            ))),
+            (Some(_), tabular) => Err(DataFusionError::Plan(format!(
+                "Metadata tables are only supported for tables, {} is a view.",
+                tabular.identifier()
+            ))),
+        }
+    }
+
+    fn register_table(
+        &self,
+        name: String,
+        table: Arc<dyn TableProvider>,
+    ) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        self.0.register_table(name, table)
+    }
+
+    fn deregister_table(
+        &self,
+        name: &str,
+    ) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        self.0.deregister_table(name)
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        let name = TableVersion::from_table_name(name).map_or(name, |(name, _)| name);
+        self.0.table_exist(name)
+            || MetadataTable::from_table_name(name)
+                .is_some_and(|(name, _)| self.0.table_exist(name))
+    }
+}
+
+/// Catalog of a schema of the default catalog whose schemas are the tables of that schema and
+/// whose tables are their metadata tables
+#[derive(Debug)]
+struct MetadataCatalog(Arc<FrostbowSchema>);
+
+impl CatalogProvider for MetadataCatalog {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema_names(&self) -> Vec<String> {
+        self.0.table_names()
+    }
+
+    fn schema(&self, name: &str) -> Option<Arc<dyn SchemaProvider>> {
+        self.0.table_exist(name).then(|| {
+            Arc::new(MetadataSchema {
+                schema: self.0.clone(),
+                table: name.to_owned(),
+            }) as Arc<dyn SchemaProvider>
+        })
+    }
+}
+
+#[derive(Debug)]
+struct MetadataSchema {
+    schema: Arc<FrostbowSchema>,
+    table: String,
+}
+
+#[async_trait]
+impl SchemaProvider for MetadataSchema {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        MetadataTable::ALL
+            .iter()
+            .map(|metadata_table| metadata_table.name().to_owned())
+            .collect()
+    }
+
+    async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        // The version of `db.t.snapshots FOR VERSION AS OF ...` is part of the name
+        let (metadata_table, version) = match TableVersion::from_table_name(name) {
+            Some((metadata_table, version)) => (metadata_table, Some(version)),
+            None => (name, None),
+        };
+        let Some(metadata_table) = MetadataTable::from_name(metadata_table) else {
+            return Ok(None);
+        };
+        let name = metadata_table.table_name(&self.table);
+        let name = match version {
+            Some(version) => version.table_name(&name),
+            None => name,
+        };
+        self.schema.table(&name).await
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        let name = TableVersion::from_table_name(name).map_or(name, |(name, _)| name);
+        MetadataTable::from_name(name).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Catalogs(&'static [&'static str]);
+
+    #[async_trait]
+    impl CatalogList for Catalogs {
+        fn catalog(&self, _name: &str) -> Option<Arc<dyn Catalog>> {
+            None
+        }
+
+        async fn list_catalogs(&self) -> Vec<String> {
+            self.0.iter().map(|name| name.to_string()).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn mount_resolves_inner_catalog() {
+        let mut catalog_list = MultiCatalogList::new(None);
+        for (name, catalogs, expected) in [
+            ("lake", &[][..], "lake"),
+            ("lake", &["glue"][..], "glue"),
+            ("lake", &["lake", "other"][..], "lake"),
+        ] {
+            catalog_list
+                .mount(name, Arc::new(Catalogs(catalogs)))
+                .await
+                .unwrap();
+            assert_eq!(
+                catalog_list.catalogs.get(name).map(|(x, _)| x.as_str()),
+                Some(expected)
+            );
+        }
+
+        let err = catalog_list
+            .mount("lake", Arc::new(Catalogs(&["a", "b"])))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Catalog lake among the catalogs a, b not found."
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use iceberg_rust::error::Error;
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::{
+    credentials::AwsConfigOptions, memory::MemoryOptions, registry::RestOptions,
+    storage::StorageOptions, Args, CatalogArg,
+};
+
+/// Configuration file for the frostbow cli
+///
+/// The top-level settings apply to every invocation, the settings of a profile
+/// selected with `--profile` are applied on top of them.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[serde(flatten)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub profiles: HashMap<String, Settings>,
+}
+
+/// Settings that can be declared in the configuration file
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Settings {
+    /// The URL of the catalog
+    pub catalog_url: Option<String>,
+    /// Catalogs mounted by name
+    #[serde(default)]
+    pub catalogs: BTreeMap<String, CatalogSettings>,
+    /// The storage backend to use
+    pub storage: Option<String>,
+    /// Options of the storage backends
+    #[serde(flatten)]
+    pub storage_options: StorageOptions,
+    /// Options of the AWS Sdk
+    #[serde(default)]
+    pub aws: AwsConfigOptions,
+    /// Options of the REST catalogs
+    #[serde(default)]
+    pub rest: RestOptions,
+    /// Options of the memory pool and the spill files
+    #[serde(flatten)]
+    pub memory: MemoryOptions,
+    /// DataFusion configuration options
+    #[serde(default)]
+    pub datafusion: HashMap<String, String>,
+    /// Keys that are not settings
+    ///
+    /// serde doesn't support `deny_unknown_fields` together with `flatten`, so they are collected
+    /// here and rejected when the file is loaded.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+/// Catalog declared in the configuration file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CatalogSettings {
+    /// The URL of the catalog
+    pub url: String,
+    /// The storage backend of the catalog
+    pub storage: Option<String>,
+}
+
+impl Settings {
+    /// Apply the values of `other` on top of these settings
+    fn merge(mut self, other: Settings) -> Self {
+        self.catalog_url = other.catalog_url.or(self.catalog_url);
+        self.catalogs.extend(other.catalogs);
+        self.storage = other.storage.or(self.storage);
+        self.storage_options = other.storage_options.or(self.storage_options);
+        self.aws = other.aws.or(self.aws);
+        self.rest = other.rest.or(self.rest);
+        self.memory = other.memory.or(self.memory);
+        self.datafusion.extend(other.datafusion);
+        self
+    }
+}
+
+impl Config {
+    /// Read the configuration from a TOML or YAML file
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        let yaml = matches!(
+            path.extension().and_then(|x| x.to_str()),
+            Some("yaml") | Some("yml")
+        );
+        Self::parse(&content, yaml)
+            .map_err(|err| Error::InvalidFormat(format!("{}: {err}", path.display())))
+    }
+
+    fn parse(content: &str, yaml: bool) -> Result<Self, String> {
+        let config: Config = if yaml {
+            serde_yaml::from_str(content).map_err(|err| err.to_string())?
+        } else {
+            toml::from_str(content).map_err(|err| err.to_string())?
+        };
+        config.deny_unknown_fields()?;
+        Ok(config)
+    }
+
+    /// Reject the keys that are not settings, like misspelled options
+    fn deny_unknown_fields(&self) -> Result<(), String> {
+        let mut unknown: Vec<_> = self
+            .settings
+            .unknown
+            .keys()
+            .cloned()
+            .chain(self.profiles.iter().flat_map(|(name, settings)| {
+                settings
+                    .unknown
+                    .keys()
+                    .map(move |key| format!("profiles.{name}.{key}"))
+            }))
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        let hint = if unknown.iter().any(|key| key.contains("datafusion.")) {
+            ", DataFusion options go into the datafusion table"
+        } else {
+            ""
+        };
+        Err(format!(
+            "unknown settings {}{hint}",
+            unknown
+                .iter()
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+
+    /// Location of the default configuration file in `~/.config/frostbow`
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?
+            .join("frostbow");
+        ["config.toml", "config.yaml", "config.yml"]
+            .into_iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.is_file())
+    }
+
+    /// Get the settings of a profile merged with the top-level settings
+    pub fn profile(self, profile: Option<&str>) -> Result<Settings, Error> {
+        let Config {
+            settings,
+            mut profiles,
+        } = self;
+        match profile {
+            Some(name) => profiles
+                .remove(name)
+                .map(|profile| settings.merge(profile))
+                .ok_or(Error::NotFound(format!("Profile {name}"))),
+            None => Ok(settings),
+        }
+    }
+}
+
+impl Args {
+    /// Load the configuration file and fill in all values not set on the command line
+    pub fn with_config_file(self) -> Result<Self, Error> {
+        let path = match &self.config {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Config::default_path(),
+        };
+        let config = match path {
+            Some(path) => {
+                tracing::info!("Loading configuration from {}", path.display());
+                Config::from_file(&path)?
+            }
+            None if self.profile.is_some() => {
+                return Err(Error::NotFound("Configuration file".to_owned()))
+            }
+            None => return Ok(self),
+        };
+        let settings = config.profile(self.profile.as_deref())?;
+        Ok(self.with_settings(settings))
+    }
+
+    /// Fill in all values not set on the command line from the given settings
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.catalog_url = self.catalog_url.or(settings.catalog_url);
+        for (name, catalog) in settings.catalogs {
+            if !self.catalogs.iter().any(|x| x.name == name) {
+                self.catalogs.push(CatalogArg {
+                    name,
+                    url: catalog.url,
+                    storage: catalog.storage,
+                });
+            }
+        }
+        self.storage = self.storage.or(settings.storage);
+        self.storage_options = self.storage_options.or(settings.storage_options);
+        self.aws = self.aws.or(settings.aws);
+        self.rest = self.rest.or(settings.rest);
+        self.memory = self.memory.or(settings.memory);
+        self.datafusion = settings.datafusion;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CONFIG: &str = r#"
+catalog-url = "s3://bucket/warehouse"
+memory = "4GiB"
+
+[catalogs.lake]
+url = "sqlite://lake.db"
+
+[s3]
+endpoint = "http://localhost:9000"
+allow-http = true
+
+[aws]
+region = "eu-central-1"
+
+[datafusion]
+"datafusion.execution.batch_size" = "8192"
+"datafusion.catalog.information_schema" = "true"
+
+[profiles.dev]
+catalog-url = "http://localhost:8181"
+spill-dir = "/tmp/spill"
+
+[profiles.dev.catalogs.dev]
+url = "sqlite://dev.db"
+
+[profiles.dev.s3]
+endpoint = "http://minio:9000"
+
+[profiles.dev.datafusion]
+"datafusion.execution.batch_size" = "1024"
+"#;
+
+    #[test]
+    fn profile_is_merged_with_top_level_settings() {
+        let config = Config::parse(CONFIG, false).unwrap();
+
+        let settings = config.clone().profile(None).unwrap();
+        assert_eq!(
+            settings.catalog_url.as_deref(),
+            Some("s3://bucket/warehouse")
+        );
+        assert_eq!(settings.memory.spill_dir, None);
+
+        let settings = config.clone().profile(Some("dev")).unwrap();
+        assert_eq!(
+            settings.catalog_url.as_deref(),
+            Some("http://localhost:8181")
+        );
+        assert_eq!(
+            settings.catalogs.keys().collect::<Vec<_>>(),
+            ["dev", "lake"]
+        );
+        assert_eq!(
+            settings.storage_options.s3.endpoint.as_deref(),
+            Some("http://minio:9000")
+        );
+        assert_eq!(settings.storage_options.s3.allow_http, Some(true));
+        assert_eq!(settings.aws.region.as_deref(), Some("eu-central-1"));
+        assert_eq!(
+            settings.memory.memory.map(|memory| memory.bytes()),
+            Some(4 << 30)
+        );
+        assert_eq!(settings.memory.spill_dir, Some(PathBuf::from("/tmp/spill")));
+        assert_eq!(
+            settings.datafusion,
+            HashMap::from([
+                (
+                    "datafusion.execution.batch_size".to_owned(),
+                    "1024".to_owned()
+                ),
+                (
+                    "datafusion.catalog.information_schema".to_owned(),
+                    "true".to_owned()
+                ),
+            ])
+        );
+
+        assert!(matches!(
+            config.profile(Some("prod")),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let err = Config::parse("catalog-ur = \"s3://bucket\"", false).unwrap_err();
+        assert!(err.contains("`catalog-ur`"), "{err}");
+
+        let err = Config::parse("[profiles.dev]\nmemroy = \"1GiB\"", false).unwrap_err();
+        assert!(err.contains("`profiles.dev.memroy`"), "{err}");
+
+        let err = Config::parse("[s3]\nendpiont = \"http://localhost:9000\"", false).unwrap_err();
+        assert!(err.contains("endpiont"), "{err}");
+
+        let err = Config::parse("datafusion.execution.batch_size: \"1024\"", true).unwrap_err();
+        assert!(err.contains("datafusion table"), "{err}");
+
+        let config = Config::parse(
+            "datafusion:\n  datafusion.execution.batch_size: \"1024\"",
+            true,
+        )
+        .unwrap();
+        assert_eq!(config.settings.datafusion.len(), 1);
+    }
+}
@@ -1,18 +1,260 @@
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    fmt::Debug,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
-use aws_config::SdkConfig;
-use aws_credential_types::{provider::ProvideCredentials, Credentials};
+use aws_config::{sts::AssumeRoleProvider, BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
 use futures::lock::Mutex;
+use gcp_auth::{CustomServiceAccount, TokenProvider};
 use object_store::Error as ObjectStoreError;
-use object_store::{aws::AwsCredential, CredentialProvider};
+use object_store::{aws::AwsCredential, gcp::GcpCredential, CredentialProvider};
+use serde::Deserialize;
 
-/// AWS Sdk credential provider for object_store
+/// Options to load the AWS Sdk configuration
+///
+/// Values that are not set are resolved by the default provider chain of the AWS Sdk.
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "AWS")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AwsConfigOptions {
+    /// Profile of the AWS config and credentials files
+    #[clap(id = "aws-profile", long = "aws-profile")]
+    pub profile: Option<String>,
+    /// Region of the AWS services
+    #[clap(id = "aws-region", long = "aws-region")]
+    pub region: Option<String>,
+    /// ARN of an IAM role to assume
+    #[clap(id = "aws-role-arn", long = "aws-role-arn")]
+    pub role_arn: Option<String>,
+    /// External id passed when assuming the role
+    #[clap(id = "aws-external-id", long = "aws-external-id")]
+    pub external_id: Option<String>,
+    /// Session name used when assuming the role
+    #[clap(id = "aws-session-name", long = "aws-session-name")]
+    pub session_name: Option<String>,
+}
+
+impl AwsConfigOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: AwsConfigOptions) -> Self {
+        Self {
+            profile: self.profile.or(other.profile),
+            region: self.region.or(other.region),
+            role_arn: self.role_arn.or(other.role_arn),
+            external_id: self.external_id.or(other.external_id),
+            session_name: self.session_name.or(other.session_name),
+        }
+    }
+
+    /// Load the AWS Sdk configuration
+    ///
+    /// If a role is set, the credentials of the profile are used to assume it.
+    pub async fn load(&self) -> SdkConfig {
+        let mut loader = aws_config::defaults(BehaviorVersion::v2026_01_12());
+        if let Some(profile) = &self.profile {
+            loader = loader.profile_name(profile);
+        }
+        if let Some(region) = &self.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        let config = loader.load().await;
+
+        let Some(role_arn) = &self.role_arn else {
+            return config;
+        };
+
+        let mut builder = AssumeRoleProvider::builder(role_arn).configure(&config);
+        if let Some(external_id) = &self.external_id {
+            builder = builder.external_id(external_id);
+        }
+        if let Some(session_name) = &self.session_name {
+            builder = builder.session_name(session_name);
+        }
+        let provider = builder.build().await;
+
+        config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(provider))
+            .build()
+    }
+}
+
+/// Default time before the expiry of the credentials at which they are refreshed
+pub const DEFAULT_REFRESH_WINDOW: Duration = Duration::from_secs(300);
+
+/// Cache of credentials that are refreshed ahead of their expiry
+///
+/// Credentials that are about to expire are refreshed in the background while requests keep using
+/// the cached credentials. Credentials without an expiry are cached for the whole session.
 #[derive(Debug)]
-#[allow(clippy::type_complexity)]
+pub(crate) struct CredentialCache<T> {
+    refresh_window: Duration,
+    cached: Arc<RwLock<Option<CachedCredential<T>>>>,
+    refresh: Arc<Mutex<()>>,
+}
+
+impl<T> Clone for CredentialCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            refresh_window: self.refresh_window,
+            cached: self.cached.clone(),
+            refresh: self.refresh.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedCredential<T> {
+    expiry: Option<SystemTime>,
+    refresh_at: Option<SystemTime>,
+    credential: Arc<T>,
+}
+
+impl<T> Clone for CachedCredential<T> {
+    fn clone(&self) -> Self {
+        Self {
+            expiry: self.expiry,
+            refresh_at: self.refresh_at,
+            credential: self.credential.clone(),
+        }
+    }
+}
+
+/// Time at which credentials that expire at `expiry` are refreshed
+///
+/// The refresh window is capped to half of the lifetime of the credentials, so that short-lived
+/// credentials are not refreshed on every request.
+fn refresh_time(expiry: SystemTime, refresh_window: Duration) -> SystemTime {
+    let lifetime = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+    expiry - refresh_window.min(lifetime / 2)
+}
+
+impl<T> CachedCredential<T> {
+    fn new(credential: Arc<T>, expiry: Option<SystemTime>, refresh_window: Duration) -> Self {
+        let refresh_at = expiry.map(|expiry| refresh_time(expiry, refresh_window));
+        Self {
+            expiry,
+            refresh_at,
+            credential,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expiry
+            .is_some_and(|expiry| expiry <= SystemTime::now())
+    }
+
+    fn needs_refresh(&self) -> bool {
+        self.refresh_at
+            .is_some_and(|refresh_at| refresh_at <= SystemTime::now())
+    }
+}
+
+impl<T> CredentialCache<T> {
+    fn new() -> Self {
+        Self {
+            refresh_window: DEFAULT_REFRESH_WINDOW,
+            cached: Arc::new(RwLock::new(None)),
+            refresh: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Cache that starts with credentials that were obtained elsewhere
+    pub(crate) fn with_credential(credential: T, expiry: Option<SystemTime>) -> Self {
+        let cache = Self::new();
+        *cache.cached.write().unwrap_or_else(PoisonError::into_inner) = Some(
+            CachedCredential::new(Arc::new(credential), expiry, cache.refresh_window),
+        );
+        cache
+    }
+
+    fn cached(&self) -> Option<CachedCredential<T>> {
+        self.cached
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Source of the credentials of a [`CredentialCache`]
+#[async_trait]
+pub(crate) trait FetchCredential: Clone + Send + Sync + 'static {
+    type Credential: Send + Sync + 'static;
+
+    /// Name of the store in errors and logs
+    const STORE: &'static str;
+
+    fn cache(&self) -> &CredentialCache<Self::Credential>;
+
+    /// Fetch new credentials and their expiry
+    async fn fetch(&self) -> Result<(Self::Credential, Option<SystemTime>), ObjectStoreError>;
+
+    /// Cached credentials, refreshed if they are about to expire
+    async fn credential(&self) -> Result<Arc<Self::Credential>, ObjectStoreError> {
+        match self.cache().cached() {
+            Some(cached) if !cached.needs_refresh() => Ok(cached.credential),
+            Some(cached) if !cached.is_expired() => {
+                self.refresh_in_background();
+                Ok(cached.credential)
+            }
+            _ => {
+                let _guard = self.cache().refresh.lock().await;
+                self.refresh().await
+            }
+        }
+    }
+
+    fn refresh_in_background(&self) {
+        // A refresh is already in progress
+        let Some(guard) = self.cache().refresh.try_lock_owned() else {
+            return;
+        };
+        let provider = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            if let Err(err) = provider.refresh().await {
+                tracing::warn!("Failed to refresh {} credentials: {}", Self::STORE, err);
+            }
+        });
+    }
+
+    /// Fetch and cache new credentials, has to be called with the refresh lock held so that only
+    /// one refresh runs at a time
+    async fn refresh(&self) -> Result<Arc<Self::Credential>, ObjectStoreError> {
+        // The credentials might have been refreshed while waiting for the lock
+        if let Some(cached) = self.cache().cached() {
+            if !cached.needs_refresh() {
+                return Ok(cached.credential);
+            }
+        }
+
+        let (credential, expiry) = self.fetch().await?;
+        let credential = Arc::new(credential);
+        *self
+            .cache()
+            .cached
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(CachedCredential::new(
+            credential.clone(),
+            expiry,
+            self.cache().refresh_window,
+        ));
+
+        Ok(credential)
+    }
+}
+
+/// AWS Sdk credential provider for object_store
+///
+/// Credentials that are about to expire are refreshed in the background while requests keep using
+/// the cached credentials. Credentials without an expiry are cached for the whole session.
+#[derive(Debug, Clone)]
 pub struct AwsCredentialProvider {
     config: SdkConfig,
-    cache: Arc<Mutex<Option<(Option<SystemTime>, Credentials)>>>,
+    cache: CredentialCache<AwsCredential>,
 }
 
 #[async_trait]
@@ -20,41 +262,44 @@ impl CredentialProvider for AwsCredentialProvider {
     type Credential = AwsCredential;
 
     async fn get_credential(&self) -> Result<Arc<Self::Credential>, ObjectStoreError> {
-        let mut guard = self.cache.lock().await;
+        self.credential().await
+    }
+}
 
-        let is_valid = if let Some((Some(time), _)) = *guard {
-            time >= SystemTime::now()
-        } else {
-            false
-        };
+#[async_trait]
+impl FetchCredential for AwsCredentialProvider {
+    type Credential = AwsCredential;
+
+    const STORE: &'static str = "s3";
 
-        if !is_valid {
-            let provider = self
-                .config
+    fn cache(&self) -> &CredentialCache<AwsCredential> {
+        &self.cache
+    }
+
+    async fn fetch(&self) -> Result<(AwsCredential, Option<SystemTime>), ObjectStoreError> {
+        let provider =
+            self.config
                 .credentials_provider()
                 .ok_or(ObjectStoreError::NotImplemented {
                     operation: "credentials_provider".to_string(),
                     implementer: "AwsCredentialProvider".to_string(),
                 })?;
 
-            let credentials =
-                provider
-                    .provide_credentials()
-                    .await
-                    .map_err(|err| ObjectStoreError::Generic {
-                        store: "s3",
-                        source: Box::new(err),
-                    })?;
-            *guard = Some((credentials.expiry(), credentials));
-        };
-
-        let credentials = &guard.as_ref().unwrap().1;
+        let credentials =
+            provider
+                .provide_credentials()
+                .await
+                .map_err(|err| ObjectStoreError::Generic {
+                    store: Self::STORE,
+                    source: Box::new(err),
+                })?;
 
-        Ok(Arc::new(AwsCredential {
+        let credential = AwsCredential {
             key_id: credentials.access_key_id().to_string(),
             secret_key: credentials.secret_access_key().to_string(),
             token: credentials.session_token().map(ToString::to_string),
-        }))
+        };
+        Ok((credential, credentials.expiry()))
     }
 }
 
@@ -63,7 +308,208 @@ impl AwsCredentialProvider {
     pub fn new(config: &SdkConfig) -> Self {
         Self {
             config: config.clone(),
-            cache: Arc::new(Mutex::new(None)),
+            cache: CredentialCache::new(),
+        }
+    }
+
+    /// Time before the expiry of the credentials at which they are refreshed
+    pub fn with_refresh_window(mut self, refresh_window: Duration) -> Self {
+        self.cache.refresh_window = refresh_window;
+        self
+    }
+}
+
+/// Scope of the tokens used to access Google Cloud Storage
+static GCP_SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/cloud-platform"];
+
+/// Google Cloud credential provider for object_store
+///
+/// Tokens are obtained from a service account key, the application default credentials or the
+/// metadata server, which also provides the workload identity on GKE. Like the AWS credentials,
+/// tokens that are about to expire are refreshed in the background.
+#[derive(Clone)]
+pub struct GcpCredentialProvider {
+    provider: Arc<dyn TokenProvider>,
+    cache: CredentialCache<GcpCredential>,
+}
+
+impl Debug for GcpCredentialProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GcpCredentialProvider")
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for GcpCredentialProvider {
+    type Credential = GcpCredential;
+
+    async fn get_credential(&self) -> Result<Arc<Self::Credential>, ObjectStoreError> {
+        self.credential().await
+    }
+}
+
+#[async_trait]
+impl FetchCredential for GcpCredentialProvider {
+    type Credential = GcpCredential;
+
+    const STORE: &'static str = "gcs";
+
+    fn cache(&self) -> &CredentialCache<GcpCredential> {
+        &self.cache
+    }
+
+    async fn fetch(&self) -> Result<(GcpCredential, Option<SystemTime>), ObjectStoreError> {
+        let token =
+            self.provider
+                .token(&GCP_SCOPES)
+                .await
+                .map_err(|err| ObjectStoreError::Generic {
+                    store: Self::STORE,
+                    source: Box::new(err),
+                })?;
+        let credential = GcpCredential {
+            bearer: token.as_str().to_string(),
+        };
+        Ok((credential, Some(token.expires_at().into())))
+    }
+}
+
+impl GcpCredentialProvider {
+    /// Create new credential provider
+    pub fn new(provider: Arc<dyn TokenProvider>) -> Self {
+        Self {
+            provider,
+            cache: CredentialCache::new(),
+        }
+    }
+
+    /// Credential provider for a service account key file
+    pub fn from_service_account_file(path: &str) -> Result<Self, gcp_auth::Error> {
+        Ok(Self::new(Arc::new(CustomServiceAccount::from_file(path)?)))
+    }
+
+    /// Credential provider for a service account key in JSON format
+    pub fn from_service_account_key(key: &str) -> Result<Self, gcp_auth::Error> {
+        Ok(Self::new(Arc::new(CustomServiceAccount::from_json(key)?)))
+    }
+
+    /// Credential provider for the application default credentials
+    ///
+    /// Uses the service account of `GOOGLE_APPLICATION_CREDENTIALS`, the credentials of
+    /// `gcloud auth application-default login`, the metadata server or the gcloud cli, in
+    /// that order.
+    pub async fn application_default() -> Result<Self, gcp_auth::Error> {
+        Ok(Self::new(gcp_auth::provider().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    /// Credentials that count the fetches, a fetch waits until the gate is unlocked
+    #[derive(Debug, Clone)]
+    struct CountingCredential {
+        cache: CredentialCache<usize>,
+        fetches: Arc<AtomicUsize>,
+        lifetime: Option<Duration>,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl CountingCredential {
+        fn new(lifetime: Option<Duration>) -> Self {
+            Self {
+                cache: CredentialCache::new(),
+                fetches: Arc::new(AtomicUsize::new(0)),
+                lifetime,
+                gate: Arc::new(Mutex::new(())),
+            }
+        }
+
+        fn cached_value(&self) -> Option<usize> {
+            self.cache.cached().map(|cached| *cached.credential)
+        }
+    }
+
+    #[async_trait]
+    impl FetchCredential for CountingCredential {
+        type Credential = usize;
+
+        const STORE: &'static str = "test";
+
+        fn cache(&self) -> &CredentialCache<usize> {
+            &self.cache
+        }
+
+        async fn fetch(&self) -> Result<(usize, Option<SystemTime>), ObjectStoreError> {
+            let fetch = self.fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            let _gate = self.gate.lock().await;
+            Ok((
+                fetch,
+                self.lifetime.map(|lifetime| SystemTime::now() + lifetime),
+            ))
+        }
+    }
+
+    #[test]
+    fn refresh_window_is_capped_at_half_the_lifetime() {
+        let now = SystemTime::now();
+        let expiry = now + Duration::from_secs(3600);
+        assert_eq!(
+            refresh_time(expiry, DEFAULT_REFRESH_WINDOW),
+            expiry - DEFAULT_REFRESH_WINDOW
+        );
+
+        // Refreshed after about 30 seconds instead of right away
+        let expiry = now + Duration::from_secs(60);
+        let refresh_at = refresh_time(expiry, DEFAULT_REFRESH_WINDOW);
+        assert!(refresh_at >= expiry - Duration::from_secs(30));
+        assert!(refresh_at <= expiry - Duration::from_secs(29));
+
+        let expired = now - Duration::from_secs(1);
+        assert_eq!(refresh_time(expired, DEFAULT_REFRESH_WINDOW), expired);
+    }
+
+    #[tokio::test]
+    async fn credentials_without_expiry_are_cached() {
+        let provider = CountingCredential::new(None);
+        for _ in 0..3 {
+            assert_eq!(*provider.credential().await.unwrap(), 1);
+        }
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn background_refresh_does_not_block_readers() {
+        let provider = CountingCredential::new(Some(Duration::from_secs(3600)));
+        let now = SystemTime::now();
+        *provider.cache.cached.write().unwrap() = Some(CachedCredential {
+            expiry: Some(now + Duration::from_secs(60)),
+            refresh_at: Some(now - Duration::from_secs(1)),
+            credential: Arc::new(0),
+        });
+
+        let gate = provider.gate.lock().await;
+        // The cached credentials are returned without waiting for the refresh
+        let credential = provider.credential().now_or_never().unwrap().unwrap();
+        assert_eq!(*credential, 0);
+        while provider.fetches.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let credential = provider.credential().now_or_never().unwrap().unwrap();
+        assert_eq!(*credential, 0);
+
+        drop(gate);
+        while provider.cached_value() == Some(0) {
+            tokio::task::yield_now().await;
         }
+        assert_eq!(*provider.credential().await.unwrap(), 1);
+        // The refresh in progress wasn't started again by the second reader
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,9 +1,15 @@
 use async_trait::async_trait;
-use aws_config::BehaviorVersion;
 use clap::Parser;
-use credentials::AwsCredentialProvider;
+use credentials::{AwsConfigOptions, AwsCredentialProvider};
 use iceberg_rust::{error::Error, object_store::ObjectStoreBuilder};
-use std::sync::Arc;
+use memory::{MemoryOptions, ReportingMemoryPool};
+use procedures::CallProcedure;
+use registry::RestOptions;
+use script::OnError;
+use server::ServeArgs;
+use sql::rewrite_table_references;
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use storage::{register_azure_store, AzureOptions, StorageOptions, AZURE_SCHEMES};
 
 use datafusion::{
     common::tree_node::{TransformedResult, TreeNode},
@@ -11,9 +17,10 @@ use datafusion::{
     error::DataFusionError,
     execution::{
         context::{SessionContext, SessionState},
-        TaskContext,
+        runtime_env::RuntimeEnvBuilder,
+        SessionStateBuilder, TaskContext,
     },
-    logical_expr::LogicalPlan,
+    logical_expr::{DdlStatement, LogicalPlan},
 };
 use datafusion_cli::{
     cli_context::CliSessionContext,
@@ -22,31 +29,180 @@ use datafusion_cli::{
 use datafusion_iceberg::planner::iceberg_transform;
 use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, memory::InMemory, ObjectStore};
 
+pub mod catalog;
+pub mod config;
 pub mod credentials;
+pub mod memory;
+pub mod metadata_tables;
+pub mod procedures;
+pub mod registry;
+pub mod repl;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod script;
+pub mod server;
+pub mod session;
+pub mod sql;
+#[cfg(feature = "sql")]
+pub mod sql_catalog;
+pub mod storage;
+pub mod time_travel;
 
-pub static BYTES_IN_GIBIBYTE: usize = 1_074_000_000;
+pub use session::FrostbowSessionBuilder;
 
 #[derive(Debug, Parser)]
 #[clap(version, about)]
 pub struct Args {
     #[clap(short = 'u', long = "catalog-url", help = "The URL of the catalog.")]
     pub catalog_url: Option<String>,
+    #[clap(
+        long = "catalog",
+        value_name = "NAME=URL",
+        help = "Mount the catalog with the given URL under NAME. Can be repeated."
+    )]
+    pub catalogs: Vec<CatalogArg>,
     #[clap(
         short = 's',
         long,
-        help = "The storage backend to use. Can be 'aws', 'gcs'. Defaults to 'memory' if not set."
+        help = "The storage backend to use. Can be 's3', 'gcs', 'azure', 'file' or 'memory'. Determined from the location of each table if not set."
     )]
     pub storage: Option<String>,
+    #[clap(flatten)]
+    pub storage_options: StorageOptions,
+    #[clap(flatten)]
+    pub aws: AwsConfigOptions,
+    #[clap(flatten)]
+    pub rest: RestOptions,
     #[clap(short = 'c', long, help = "Execute the given command.")]
     pub command: Vec<String>,
     #[clap(short = 'f', long, help = "Execute the given files.")]
     pub file: Vec<String>,
-    #[clap(short = 'm', long, help = "Memory pool size in GB.")]
-    pub memory: Option<usize>,
+    #[clap(
+        long,
+        conflicts_with = "stop_on_error",
+        help = "Execute the remaining statements of -c and -f after a statement failed."
+    )]
+    pub continue_on_error: bool,
+    #[clap(
+        long,
+        help = "Skip the remaining statements of -c and -f after a statement failed. This is the default."
+    )]
+    pub stop_on_error: bool,
+    #[clap(flatten)]
+    pub memory: MemoryOptions,
+    #[clap(
+        long,
+        help = "Path to a TOML or YAML configuration file. Defaults to ~/.config/frostbow/config.toml."
+    )]
+    pub config: Option<String>,
+    #[clap(long, help = "Profile of the configuration file to use.")]
+    pub profile: Option<String>,
+    #[clap(skip)]
+    pub datafusion: HashMap<String, String>,
+    #[clap(subcommand)]
+    pub subcommand: Option<Command>,
+}
+
+/// Subcommands of the frostbow cli, runs the REPL if none is given
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// Serve the catalogs to remote clients
+    Serve(ServeArgs),
+}
+
+/// Catalog mounted under a name
+#[derive(Debug, Clone)]
+pub struct CatalogArg {
+    pub name: String,
+    pub url: String,
+    /// Storage backend of the catalog, uses the global storage backend if not set
+    pub storage: Option<String>,
+}
+
+impl FromStr for CatalogArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, url) = s
+            .split_once('=')
+            .ok_or(format!("Catalog {s} is not of the form NAME=URL."))?;
+        Ok(CatalogArg {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            storage: None,
+        })
+    }
+}
+
+impl Args {
+    /// Behavior of `-c` and `-f` when a statement fails
+    pub fn on_error(&self) -> OnError {
+        if self.continue_on_error {
+            OnError::Continue
+        } else {
+            OnError::Stop
+        }
+    }
 }
 
 pub struct IcebergContext(pub SessionContext);
 
+impl IcebergContext {
+    /// Plan and execute a SQL statement with the Iceberg extensions
+    pub async fn sql(&self, sql: &str) -> Result<DataFrame, DataFusionError> {
+        let plan = self.create_logical_plan(sql).await?;
+        self.execute_logical_plan(plan).await
+    }
+
+    /// Plan a SQL statement, resolving the metadata tables and `FOR ... AS OF` clauses of its
+    /// table references and the `CALL` statements of the maintenance procedures
+    pub async fn create_logical_plan(&self, sql: &str) -> Result<LogicalPlan, DataFusionError> {
+        let state = self.0.state();
+        if let Some(plan) = CallProcedure::plan(&state, sql)? {
+            return Ok(plan);
+        }
+        let sql = rewrite_table_references(sql, &state.config().options().sql_parser)?;
+        state.create_logical_plan(&sql).await
+    }
+
+    /// Session of a client of a server
+    ///
+    /// The session shares the catalogs, the settings and the memory of this session. The memory
+    /// usage of its queries is reported separately.
+    pub fn client_session(&self) -> Result<IcebergContext, DataFusionError> {
+        let state = self.0.state();
+        let memory_pool = match state.config().get_extension::<ReportingMemoryPool>() {
+            Some(memory_pool) => memory_pool.share(),
+            None => Arc::new(ReportingMemoryPool::new(
+                state.runtime_env().memory_pool.clone(),
+            )),
+        };
+        self.with_memory_pool(memory_pool)
+    }
+
+    /// Session that shares the catalogs and the settings of this session, but uses its own
+    /// memory pool and spill files
+    pub fn with_memory_pool(
+        &self,
+        memory_pool: Arc<ReportingMemoryPool>,
+    ) -> Result<IcebergContext, DataFusionError> {
+        let state = self.0.state();
+        let memory = state
+            .config()
+            .get_extension::<MemoryOptions>()
+            .unwrap_or_default();
+        let runtime_env = RuntimeEnvBuilder::from_runtime_env(state.runtime_env())
+            .with_memory_pool(memory_pool.clone())
+            .with_disk_manager_builder(memory.disk_manager_builder())
+            .build_arc()?;
+        memory_pool.set_disk_manager(runtime_env.disk_manager.clone());
+        let state = SessionStateBuilder::new_from_existing(state)
+            .with_runtime_env(runtime_env)
+            .build();
+        Ok(IcebergContext(SessionContext::new_with_state(state)))
+    }
+}
+
 #[async_trait]
 impl CliSessionContext for IcebergContext {
     fn task_ctx(&self) -> Arc<TaskContext> {
@@ -66,9 +222,11 @@ impl CliSessionContext for IcebergContext {
     }
 
     fn register_table_options_extension_from_scheme(&self, scheme: &str) {
+        let extensions = self.0.state().default_table_options().extensions.clone();
         match scheme {
-            // For Amazon S3 or Alibaba Cloud OSS
-            "s3" | "oss" | "cos" => {
+            // For Amazon S3 or Alibaba Cloud OSS, keep the AWS options of the session if they are
+            // already registered
+            "s3" | "oss" | "cos" if extensions.get::<AwsOptions>().is_none() => {
                 // Register AWS specific table options in the session context:
                 self.0
                     .register_table_options_extension(AwsOptions::default())
@@ -79,21 +237,42 @@ impl CliSessionContext for IcebergContext {
                 self.0
                     .register_table_options_extension(GcpOptions::default())
             }
+            // For Azure Blob Storage and ADLS Gen2, keep the Azure options of the session if
+            // they are already registered
+            scheme
+                if AZURE_SCHEMES.contains(&scheme)
+                    && extensions.get::<AzureOptions>().is_none() =>
+            {
+                self.0
+                    .register_table_options_extension(AzureOptions::default())
+            }
             // For unsupported schemes, do nothing:
             _ => {}
         }
     }
 
     async fn execute_logical_plan(&self, plan: LogicalPlan) -> Result<DataFrame, DataFusionError> {
+        if let LogicalPlan::Extension(extension) = &plan {
+            if let Some(call) = extension.node.as_any().downcast_ref::<CallProcedure>() {
+                return call.execute(&self.0).await;
+            }
+        }
+        if let LogicalPlan::Ddl(DdlStatement::CreateExternalTable(cmd)) = &plan {
+            register_azure_store(&self.0, &cmd.location, &cmd.options)?;
+        }
         let plan = plan.transform(iceberg_transform).data()?;
         self.0.execute_logical_plan(plan).await
     }
 }
 
-pub async fn get_storage(storage: Option<&str>) -> Result<ObjectStoreBuilder, Error> {
+pub async fn get_storage(
+    storage: Option<&str>,
+    options: &StorageOptions,
+    aws: &AwsConfigOptions,
+) -> Result<ObjectStoreBuilder, Error> {
     match storage {
         Some("s3") => {
-            let config = aws_config::load_defaults(BehaviorVersion::v2026_01_12()).await;
+            let config = aws.load().await;
 
             let mut builder = AmazonS3Builder::from_env();
             if let Some(region) = config.region() {
@@ -102,18 +281,41 @@ pub async fn get_storage(storage: Option<&str>) -> Result<ObjectStoreBuilder, Er
             if let Some(endpoint) = config.endpoint_url() {
                 builder = builder.with_endpoint(endpoint)
             }
+            let builder = options.s3.apply(builder)?;
 
-            Ok(ObjectStoreBuilder::S3(Box::new(builder.with_credentials(
-                Arc::new(AwsCredentialProvider::new(&config)),
-            ))))
+            let mut credentials = AwsCredentialProvider::new(&config);
+            if let Some(refresh_window) = options.s3.credentials_refresh_window {
+                credentials = credentials.with_refresh_window(Duration::from_secs(refresh_window));
+            }
+
+            Ok(ObjectStoreBuilder::S3(Box::new(
+                builder.with_credentials(Arc::new(credentials)),
+            )))
         }
-        Some("gcs") => Ok(ObjectStoreBuilder::gcs()),
+        Some("gcs") => Ok(ObjectStoreBuilder::GCS(Box::new(
+            options.gcs.builder().await?,
+        ))),
+        Some("azure") => Ok(ObjectStoreBuilder::Azure(Box::new(options.azure.builder()))),
         Some("file") => Ok(ObjectStoreBuilder::Filesystem(Arc::new(
             LocalFileSystem::new(),
         ))),
-        None => Ok(ObjectStoreBuilder::Memory(Arc::new(InMemory::new()))),
+        Some("memory") => Ok(ObjectStoreBuilder::Memory(Arc::new(InMemory::new()))),
+        None => Err(Error::NotFound("Storage backend".to_owned())),
         Some(x) => Err(Error::InvalidFormat(format!(
             "Storage {x} is not supported."
         ))),
     }
 }
+
+/// Get the storage backend from the scheme of a table or warehouse location
+pub fn storage_from_location(location: &str) -> Option<&'static str> {
+    let scheme = location.split_once("://").map(|(scheme, _)| scheme);
+    match scheme {
+        Some("s3" | "s3a") => Some("s3"),
+        Some("gs" | "gcs") => Some("gcs"),
+        Some(scheme) if AZURE_SCHEMES.contains(&scheme) => Some("azure"),
+        Some("file") => Some("file"),
+        None if location.starts_with('/') => Some("file"),
+        _ => None,
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use datafusion::{
+    error::DataFusionError,
+    execution::{
+        disk_manager::{DiskManager, DiskManagerBuilder, DiskManagerMode},
+        memory_pool::{
+            human_readable_size, FairSpillPool, GreedyMemoryPool, MemoryConsumer, MemoryLimit,
+            MemoryPool, MemoryReservation, UnboundedMemoryPool,
+        },
+    },
+};
+use serde::{de, Deserialize, Deserializer};
+
+/// Size in bytes, parsed from values like `512MiB` or `4GB`
+///
+/// Decimal units (`KB`, `MB`, `GB`, `TB`) are powers of 1000, binary units (`KiB`, `MiB`,
+/// `GiB`, `TiB`) powers of 1024. A number without unit is a size in GiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemorySize(pub u64);
+
+impl MemorySize {
+    /// Size in bytes
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for MemorySize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" => 1 << 30,
+            "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            _ => return Err(format!("Size {s} has an unknown unit.")),
+        };
+        let size = if let Ok(number) = number.parse::<u64>() {
+            number.checked_mul(multiplier)
+        } else {
+            number
+                .parse::<f64>()
+                .ok()
+                .map(|number| number * multiplier as f64)
+                .filter(|size| size.is_finite() && *size < u64::MAX as f64)
+                .map(|size| size.round() as u64)
+        };
+        size.map(MemorySize)
+            .ok_or(format!("Size {s} is not a valid size."))
+    }
+}
+
+impl fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", human_readable_size(self.0 as usize))
+    }
+}
+
+impl<'de> Deserialize<'de> for MemorySize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Number(u64),
+            String(String),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Number(number) => number
+                .checked_mul(1 << 30)
+                .map(MemorySize)
+                .ok_or_else(|| de::Error::custom(format!("Size {number} is too large."))),
+            Value::String(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Memory pool of the session
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MemoryPoolKind {
+    /// Grant memory to the first operators that request it
+    #[default]
+    Greedy,
+    /// Share the memory fairly between the operators that can spill to disk
+    Fair,
+}
+
+/// Options of the memory pool and the spill files
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "Memory")]
+#[serde(rename_all = "kebab-case")]
+pub struct MemoryOptions {
+    /// Memory pool size, for example 512MiB or 4GB. A number without unit is a size in GiB.
+    #[clap(short = 'm', long = "memory", value_name = "SIZE")]
+    pub memory: Option<MemorySize>,
+    /// Memory pool used if the memory is limited
+    #[clap(long = "memory-pool", value_enum)]
+    pub memory_pool: Option<MemoryPoolKind>,
+    /// Directory for the files of operators that spill to disk. Defaults to the temporary directory of the OS.
+    #[clap(long = "spill-dir", value_name = "PATH")]
+    pub spill_dir: Option<PathBuf>,
+    /// Maximum disk space of the spill files, for example 100GiB
+    #[clap(long = "spill-quota", value_name = "SIZE")]
+    pub spill_quota: Option<MemorySize>,
+}
+
+impl MemoryOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: MemoryOptions) -> Self {
+        Self {
+            memory: self.memory.or(other.memory),
+            memory_pool: self.memory_pool.or(other.memory_pool),
+            spill_dir: self.spill_dir.or(other.spill_dir),
+            spill_quota: self.spill_quota.or(other.spill_quota),
+        }
+    }
+
+    /// Create a new memory pool of the configured kind and size
+    pub fn memory_pool(&self) -> Arc<ReportingMemoryPool> {
+        let inner: Arc<dyn MemoryPool> = match (self.memory, self.memory_pool.unwrap_or_default()) {
+            (None, _) => Arc::new(UnboundedMemoryPool::default()),
+            (Some(size), MemoryPoolKind::Fair) => Arc::new(FairSpillPool::new(size.0 as usize)),
+            (Some(size), MemoryPoolKind::Greedy) => {
+                Arc::new(GreedyMemoryPool::new(size.0 as usize))
+            }
+        };
+        Arc::new(ReportingMemoryPool::new(inner))
+    }
+
+    /// Builder of the disk manager for the spill files
+    pub fn disk_manager_builder(&self) -> DiskManagerBuilder {
+        let builder = DiskManager::builder();
+        let builder = match &self.spill_dir {
+            Some(dir) => builder.with_mode(DiskManagerMode::Directories(vec![dir.clone()])),
+            None => builder,
+        };
+        match self.spill_quota {
+            Some(quota) => builder.with_max_temp_directory_size(quota.0),
+            None => builder,
+        }
+    }
+}
+
+/// Memory pool that reports the peak memory and spill usage of every query
+///
+/// A query ends when the last memory consumer of the pool is unregistered. The sessions of a
+/// server get pools of their own with [`ReportingMemoryPool::share`], so that their queries are
+/// reported separately.
+#[derive(Debug)]
+pub struct ReportingMemoryPool {
+    inner: Arc<dyn MemoryPool>,
+    disk_manager: OnceLock<Arc<DiskManager>>,
+    consumers: AtomicUsize,
+    reserved: AtomicUsize,
+    peak_reserved: AtomicUsize,
+    peak_spilled: AtomicU64,
+    peak_spill_files: AtomicUsize,
+}
+
+impl ReportingMemoryPool {
+    pub fn new(inner: Arc<dyn MemoryPool>) -> Self {
+        Self {
+            inner,
+            disk_manager: OnceLock::new(),
+            consumers: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+            peak_reserved: AtomicUsize::new(0),
+            peak_spilled: AtomicU64::new(0),
+            peak_spill_files: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pool that shares the memory of this pool, but reports the queries that use it separately
+    pub fn share(&self) -> Arc<ReportingMemoryPool> {
+        Arc::new(Self::new(self.inner.clone()))
+    }
+
+    /// Disk manager of the runtime, used to report the size of the spill files
+    pub fn set_disk_manager(&self, disk_manager: Arc<DiskManager>) {
+        let _ = self.disk_manager.set(disk_manager);
+    }
+
+    /// Record the current memory and spill usage
+    fn sample(&self) {
+        self.peak_reserved
+            .fetch_max(self.reserved.load(Ordering::Relaxed), Ordering::Relaxed);
+        if let Some(disk_manager) = self.disk_manager.get() {
+            let progress = disk_manager.spilling_progress();
+            self.peak_spilled
+                .fetch_max(progress.current_bytes, Ordering::Relaxed);
+            self.peak_spill_files
+                .fetch_max(progress.active_files_count, Ordering::Relaxed);
+        }
+    }
+
+    /// Log the peak usage of the finished query and reset it
+    fn report(&self) {
+        let reserved = self.peak_reserved.swap(0, Ordering::Relaxed);
+        let spilled = self.peak_spilled.swap(0, Ordering::Relaxed);
+        let spill_files = self.peak_spill_files.swap(0, Ordering::Relaxed);
+        if reserved == 0 && spilled == 0 {
+            return;
+        }
+        let limit = match self.inner.memory_limit() {
+            MemoryLimit::Finite(limit) => human_readable_size(limit),
+            _ => "unlimited".to_owned(),
+        };
+        tracing::info!(
+            "Query memory: peak {} of {limit}, spilled {} in {spill_files} files",
+            human_readable_size(reserved),
+            human_readable_size(spilled as usize),
+        );
+    }
+}
+
+impl MemoryPool for ReportingMemoryPool {
+    fn register(&self, consumer: &MemoryConsumer) {
+        self.consumers.fetch_add(1, Ordering::Relaxed);
+        self.inner.register(consumer)
+    }
+
+    fn unregister(&self, consumer: &MemoryConsumer) {
+        self.inner.unregister(consumer);
+        self.sample();
+        if self.consumers.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.report();
+        }
+    }
+
+    fn grow(&self, reservation: &MemoryReservation, additional: usize) {
+        self.inner.grow(reservation, additional);
+        self.reserved.fetch_add(additional, Ordering::Relaxed);
+        self.sample();
+    }
+
+    fn shrink(&self, reservation: &MemoryReservation, shrink: usize) {
+        self.sample();
+        self.reserved.fetch_sub(shrink, Ordering::Relaxed);
+        self.inner.shrink(reservation, shrink)
+    }
+
+    fn try_grow(
+        &self,
+        reservation: &MemoryReservation,
+        additional: usize,
+    ) -> Result<(), DataFusionError> {
+        self.inner.try_grow(reservation, additional)?;
+        self.reserved.fetch_add(additional, Ordering::Relaxed);
+        self.sample();
+        Ok(())
+    }
+
+    fn reserved(&self) -> usize {
+        self.inner.reserved()
+    }
+
+    fn memory_limit(&self) -> MemoryLimit {
+        self.inner.memory_limit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_size_units() {
+        let size = |s: &str| s.parse::<MemorySize>().map(|size| size.bytes());
+        assert_eq!(size("4"), Ok(4 << 30));
+        assert_eq!(size("1.5"), Ok(3 << 29));
+        assert_eq!(size("512B"), Ok(512));
+        assert_eq!(size("2KB"), Ok(2_000));
+        assert_eq!(size("2KiB"), Ok(2_048));
+        assert_eq!(size("512MB"), Ok(512_000_000));
+        assert_eq!(size("512MiB"), Ok(512 << 20));
+        assert_eq!(size("4 gb"), Ok(4_000_000_000));
+        assert_eq!(size("4GiB"), Ok(4 << 30));
+        assert_eq!(size("1TiB"), Ok(1 << 40));
+        assert_eq!(size("0.5GB"), Ok(500_000_000));
+        assert!(size("4XB").is_err());
+        assert!(size("GB").is_err());
+        assert!(size("99999999999TiB").is_err());
+    }
+
+    #[test]
+    fn shared_pools_report_separately() {
+        let pool = ReportingMemoryPool::new(Arc::new(GreedyMemoryPool::new(100)));
+        let first = pool.share();
+        let second = pool.share();
+        let first_reservation = MemoryConsumer::new("first").register(&(first.clone() as _));
+        let second_reservation = MemoryConsumer::new("second").register(&(second.clone() as _));
+
+        first_reservation.try_grow(60).unwrap();
+        // The memory limit is shared
+        assert!(second_reservation.try_grow(50).is_err());
+        second_reservation.try_grow(30).unwrap();
+
+        assert_eq!(first.peak_reserved.load(Ordering::Relaxed), 60);
+        assert_eq!(second.peak_reserved.load(Ordering::Relaxed), 30);
+
+        drop(first_reservation);
+        assert_eq!(first.peak_reserved.load(Ordering::Relaxed), 0);
+        assert_eq!(second.peak_reserved.load(Ordering::Relaxed), 30);
+        assert_eq!(second.reserved.load(Ordering::Relaxed), 30);
+    }
+}
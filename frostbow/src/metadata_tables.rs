@@ -0,0 +1,859 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::{
+        array::{
+            ArrayRef, BooleanArray, Int32Array, Int64Array, MapBuilder, StringArray, StringBuilder,
+            TimestampMillisecondArray,
+        },
+        datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    catalog::{Session, TableProvider},
+    datasource::{MemTable, TableType},
+    error::DataFusionError,
+    logical_expr::Expr,
+    physical_plan::ExecutionPlan,
+};
+use iceberg_rust::{
+    error::Error,
+    spec::{
+        manifest::{Content, FileFormat, Status},
+        manifest_list::Content as ManifestContent,
+        snapshot::{Operation, SnapshotRetention},
+        values::Struct,
+    },
+    table::Table,
+};
+
+/// Metadata table of an Iceberg table, addressed as `table$snapshots`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataTable {
+    Snapshots,
+    History,
+    Files,
+    DataFiles,
+    DeleteFiles,
+    Manifests,
+    Partitions,
+    Refs,
+    MetadataLogEntries,
+}
+
+impl MetadataTable {
+    pub const ALL: [MetadataTable; 9] = [
+        MetadataTable::Snapshots,
+        MetadataTable::History,
+        MetadataTable::Files,
+        MetadataTable::DataFiles,
+        MetadataTable::DeleteFiles,
+        MetadataTable::Manifests,
+        MetadataTable::Partitions,
+        MetadataTable::Refs,
+        MetadataTable::MetadataLogEntries,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataTable::Snapshots => "snapshots",
+            MetadataTable::History => "history",
+            MetadataTable::Files => "files",
+            MetadataTable::DataFiles => "data_files",
+            MetadataTable::DeleteFiles => "delete_files",
+            MetadataTable::Manifests => "manifests",
+            MetadataTable::Partitions => "partitions",
+            MetadataTable::Refs => "refs",
+            MetadataTable::MetadataLogEntries => "metadata_log_entries",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|table| table.name() == name)
+    }
+
+    /// Name of the metadata table of `table`
+    pub fn table_name(&self, table: &str) -> String {
+        format!("{table}${}", self.name())
+    }
+
+    /// Split a name like `orders$snapshots` into the table and the metadata table
+    pub fn from_table_name(name: &str) -> Option<(&str, Self)> {
+        let (table, metadata_table) = name.rsplit_once('$')?;
+        Some((table, Self::from_name(metadata_table)?))
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        let timestamp = || DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+        let fields = match self {
+            MetadataTable::Snapshots => vec![
+                Field::new("committed_at", timestamp(), false),
+                Field::new("snapshot_id", DataType::Int64, false),
+                Field::new("parent_id", DataType::Int64, true),
+                Field::new("sequence_number", DataType::Int64, false),
+                Field::new("operation", DataType::Utf8, false),
+                Field::new("manifest_list", DataType::Utf8, false),
+                Field::new("summary", summary_type(), false),
+            ],
+            MetadataTable::History => vec![
+                Field::new("made_current_at", timestamp(), false),
+                Field::new("snapshot_id", DataType::Int64, false),
+                Field::new("parent_id", DataType::Int64, true),
+                Field::new("is_current_ancestor", DataType::Boolean, false),
+            ],
+            MetadataTable::Files | MetadataTable::DataFiles | MetadataTable::DeleteFiles => vec![
+                Field::new("content", DataType::Int32, false),
+                Field::new("file_path", DataType::Utf8, false),
+                Field::new("file_format", DataType::Utf8, false),
+                Field::new("spec_id", DataType::Int32, false),
+                Field::new("partition", DataType::Utf8, false),
+                Field::new("record_count", DataType::Int64, false),
+                Field::new("file_size_in_bytes", DataType::Int64, false),
+                Field::new("sort_order_id", DataType::Int32, true),
+            ],
+            MetadataTable::Manifests => vec![
+                Field::new("content", DataType::Int32, false),
+                Field::new("path", DataType::Utf8, false),
+                Field::new("length", DataType::Int64, false),
+                Field::new("partition_spec_id", DataType::Int32, false),
+                Field::new("sequence_number", DataType::Int64, false),
+                Field::new("added_snapshot_id", DataType::Int64, false),
+                Field::new("added_files_count", DataType::Int32, true),
+                Field::new("existing_files_count", DataType::Int32, true),
+                Field::new("deleted_files_count", DataType::Int32, true),
+                Field::new("added_rows_count", DataType::Int64, true),
+                Field::new("existing_rows_count", DataType::Int64, true),
+                Field::new("deleted_rows_count", DataType::Int64, true),
+            ],
+            MetadataTable::Partitions => vec![
+                Field::new("partition", DataType::Utf8, false),
+                Field::new("spec_id", DataType::Int32, false),
+                Field::new("record_count", DataType::Int64, false),
+                Field::new("file_count", DataType::Int64, false),
+                Field::new("total_data_file_size_in_bytes", DataType::Int64, false),
+                Field::new("position_delete_record_count", DataType::Int64, false),
+                Field::new("position_delete_file_count", DataType::Int64, false),
+                Field::new("equality_delete_record_count", DataType::Int64, false),
+                Field::new("equality_delete_file_count", DataType::Int64, false),
+            ],
+            MetadataTable::Refs => vec![
+                Field::new("name", DataType::Utf8, false),
+                Field::new("type", DataType::Utf8, false),
+                Field::new("snapshot_id", DataType::Int64, false),
+                Field::new("max_reference_age_in_ms", DataType::Int64, true),
+                Field::new("min_snapshots_to_keep", DataType::Int32, true),
+                Field::new("max_snapshot_age_in_ms", DataType::Int64, true),
+            ],
+            MetadataTable::MetadataLogEntries => vec![
+                Field::new("timestamp", timestamp(), false),
+                Field::new("file", DataType::Utf8, false),
+                Field::new("latest_snapshot_id", DataType::Int64, true),
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Rows of the metadata table, the files, manifests and partitions are read from the
+    /// snapshot if it is set, otherwise from the current snapshot
+    pub async fn batch(
+        &self,
+        table: &Table,
+        snapshot_id: Option<i64>,
+    ) -> Result<RecordBatch, DataFusionError> {
+        let columns = match self {
+            MetadataTable::Snapshots => snapshots(table)?,
+            MetadataTable::History => history(table),
+            MetadataTable::Files => files(table, snapshot_id, |_| true).await?,
+            MetadataTable::DataFiles => {
+                files(table, snapshot_id, |content| *content == Content::Data).await?
+            }
+            MetadataTable::DeleteFiles => {
+                files(table, snapshot_id, |content| *content != Content::Data).await?
+            }
+            MetadataTable::Manifests => manifests(table, snapshot_id).await?,
+            MetadataTable::Partitions => partitions(table, snapshot_id).await?,
+            MetadataTable::Refs => refs(table),
+            MetadataTable::MetadataLogEntries => metadata_log_entries(table),
+        };
+        Ok(RecordBatch::try_new(self.schema(), columns)?)
+    }
+}
+
+fn summary_type() -> DataType {
+    DataType::Map(
+        Arc::new(Field::new(
+            "entries",
+            DataType::Struct(Fields::from(vec![
+                Field::new("keys", DataType::Utf8, false),
+                Field::new("values", DataType::Utf8, true),
+            ])),
+            false,
+        )),
+        false,
+    )
+}
+
+fn timestamps(values: Vec<i64>) -> ArrayRef {
+    Arc::new(TimestampMillisecondArray::from(values).with_timezone("UTC"))
+}
+
+fn snapshots(table: &Table) -> Result<Vec<ArrayRef>, DataFusionError> {
+    let mut snapshots: Vec<_> = table.metadata().snapshots.values().collect();
+    snapshots.sort_by_key(|snapshot| (*snapshot.timestamp_ms(), *snapshot.sequence_number()));
+    let mut summary = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    for snapshot in &snapshots {
+        let other: BTreeMap<_, _> = snapshot.summary().other.iter().collect();
+        for (key, value) in other {
+            summary.keys().append_value(key);
+            summary.values().append_value(value);
+        }
+        summary.append(true)?;
+    }
+    Ok(vec![
+        timestamps(snapshots.iter().map(|x| *x.timestamp_ms()).collect()),
+        Arc::new(Int64Array::from_iter_values(
+            snapshots.iter().map(|x| *x.snapshot_id()),
+        )),
+        Arc::new(Int64Array::from_iter(
+            snapshots.iter().map(|x| *x.parent_snapshot_id()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            snapshots.iter().map(|x| *x.sequence_number()),
+        )),
+        Arc::new(StringArray::from_iter_values(snapshots.iter().map(
+            |x| match x.summary().operation {
+                Operation::Append => "append",
+                Operation::Replace => "replace",
+                Operation::Overwrite => "overwrite",
+                Operation::Delete => "delete",
+            },
+        ))),
+        Arc::new(StringArray::from_iter_values(
+            snapshots.iter().map(|x| x.manifest_list()),
+        )),
+        Arc::new(summary.finish()),
+    ])
+}
+
+fn history(table: &Table) -> Vec<ArrayRef> {
+    let metadata = table.metadata();
+    let mut ancestors = HashSet::new();
+    let mut current = metadata.current_snapshot_id;
+    while let Some(id) = current.filter(|id| ancestors.insert(*id)) {
+        current = metadata
+            .snapshots
+            .get(&id)
+            .and_then(|snapshot| *snapshot.parent_snapshot_id());
+    }
+    let log = &metadata.snapshot_log;
+    vec![
+        timestamps(log.iter().map(|x| x.timestamp_ms).collect()),
+        Arc::new(Int64Array::from_iter_values(
+            log.iter().map(|x| x.snapshot_id),
+        )),
+        Arc::new(Int64Array::from_iter(log.iter().map(|x| {
+            metadata
+                .snapshots
+                .get(&x.snapshot_id)
+                .and_then(|snapshot| *snapshot.parent_snapshot_id())
+        }))),
+        Arc::new(BooleanArray::from_iter(
+            log.iter().map(|x| Some(ancestors.contains(&x.snapshot_id))),
+        )),
+    ]
+}
+
+/// Live files of the snapshot with their partition spec
+async fn live_files(
+    table: &Table,
+    snapshot_id: Option<i64>,
+) -> Result<Vec<(i32, iceberg_rust::spec::manifest::DataFile)>, Error> {
+    let manifests = table.manifests(None, snapshot_id).await?;
+    let spec_ids: HashMap<_, _> = manifests
+        .iter()
+        .map(|manifest| (manifest.manifest_path.clone(), manifest.partition_spec_id))
+        .collect();
+    let mut files = Vec::new();
+    for entry in table.datafiles(&manifests, None, (None, None)).await? {
+        let (path, entry) = entry?;
+        if *entry.status() != Status::Deleted {
+            let spec_id = spec_ids.get(&path).copied().unwrap_or_default();
+            files.push((spec_id, entry.data_file().clone()));
+        }
+    }
+    Ok(files)
+}
+
+fn partition_string(partition: &Struct) -> String {
+    let mut fields: Vec<_> = partition.lookup.iter().collect();
+    fields.sort_by_key(|(_, index)| **index);
+    let fields: Vec<_> = fields
+        .into_iter()
+        .map(|(name, index)| match partition.fields.get(*index) {
+            Some(Some(value)) => format!("{name}={value}"),
+            _ => format!("{name}=null"),
+        })
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+async fn files(
+    table: &Table,
+    snapshot_id: Option<i64>,
+    content: impl Fn(&Content) -> bool,
+) -> Result<Vec<ArrayRef>, DataFusionError> {
+    let files: Vec<_> = live_files(table, snapshot_id)
+        .await
+        .map_err(|err| DataFusionError::External(Box::new(err)))?
+        .into_iter()
+        .filter(|(_, file)| content(file.content()))
+        .collect();
+    Ok(vec![
+        Arc::new(Int32Array::from_iter_values(
+            files.iter().map(|(_, x)| x.content().clone() as i32),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            files.iter().map(|(_, x)| x.file_path()),
+        )),
+        Arc::new(StringArray::from_iter_values(files.iter().map(
+            |(_, x)| match x.file_format() {
+                FileFormat::Avro => "AVRO",
+                FileFormat::Orc => "ORC",
+                FileFormat::Parquet => "PARQUET",
+            },
+        ))),
+        Arc::new(Int32Array::from_iter_values(
+            files.iter().map(|(spec_id, _)| *spec_id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            files.iter().map(|(_, x)| partition_string(x.partition())),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            files.iter().map(|(_, x)| *x.record_count()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            files.iter().map(|(_, x)| *x.file_size_in_bytes()),
+        )),
+        Arc::new(Int32Array::from_iter(
+            files.iter().map(|(_, x)| *x.sort_order_id()),
+        )),
+    ])
+}
+
+async fn manifests(
+    table: &Table,
+    snapshot_id: Option<i64>,
+) -> Result<Vec<ArrayRef>, DataFusionError> {
+    let manifests = table
+        .manifests(None, snapshot_id)
+        .await
+        .map_err(|err| DataFusionError::External(Box::new(err)))?;
+    Ok(vec![
+        Arc::new(Int32Array::from_iter_values(manifests.iter().map(
+            |x| match x.content {
+                ManifestContent::Data => 0,
+                ManifestContent::Deletes => 1,
+            },
+        ))),
+        Arc::new(StringArray::from_iter_values(
+            manifests.iter().map(|x| &x.manifest_path),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            manifests.iter().map(|x| x.manifest_length),
+        )),
+        Arc::new(Int32Array::from_iter_values(
+            manifests.iter().map(|x| x.partition_spec_id),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            manifests.iter().map(|x| x.sequence_number),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            manifests.iter().map(|x| x.added_snapshot_id),
+        )),
+        Arc::new(Int32Array::from_iter(
+            manifests.iter().map(|x| x.added_files_count),
+        )),
+        Arc::new(Int32Array::from_iter(
+            manifests.iter().map(|x| x.existing_files_count),
+        )),
+        Arc::new(Int32Array::from_iter(
+            manifests.iter().map(|x| x.deleted_files_count),
+        )),
+        Arc::new(Int64Array::from_iter(
+            manifests.iter().map(|x| x.added_rows_count),
+        )),
+        Arc::new(Int64Array::from_iter(
+            manifests.iter().map(|x| x.existing_rows_count),
+        )),
+        Arc::new(Int64Array::from_iter(
+            manifests.iter().map(|x| x.deleted_rows_count),
+        )),
+    ])
+}
+
+/// Statistics of the files of a partition
+#[derive(Default)]
+struct PartitionStats {
+    record_count: i64,
+    file_count: i64,
+    total_data_file_size_in_bytes: i64,
+    position_delete_record_count: i64,
+    position_delete_file_count: i64,
+    equality_delete_record_count: i64,
+    equality_delete_file_count: i64,
+}
+
+async fn partitions(
+    table: &Table,
+    snapshot_id: Option<i64>,
+) -> Result<Vec<ArrayRef>, DataFusionError> {
+    let files = live_files(table, snapshot_id)
+        .await
+        .map_err(|err| DataFusionError::External(Box::new(err)))?;
+    let mut partitions: BTreeMap<(String, i32), PartitionStats> = BTreeMap::new();
+    for (spec_id, file) in files {
+        let stats = partitions
+            .entry((partition_string(file.partition()), spec_id))
+            .or_default();
+        match file.content() {
+            Content::Data => {
+                stats.record_count += file.record_count();
+                stats.file_count += 1;
+                stats.total_data_file_size_in_bytes += file.file_size_in_bytes();
+            }
+            Content::PositionDeletes => {
+                stats.position_delete_record_count += file.record_count();
+                stats.position_delete_file_count += 1;
+            }
+            Content::EqualityDeletes => {
+                stats.equality_delete_record_count += file.record_count();
+                stats.equality_delete_file_count += 1;
+            }
+        }
+    }
+    let column = |f: fn(&PartitionStats) -> i64| -> ArrayRef {
+        Arc::new(Int64Array::from_iter_values(partitions.values().map(f)))
+    };
+    Ok(vec![
+        Arc::new(StringArray::from_iter_values(
+            partitions.keys().map(|(partition, _)| partition),
+        )),
+        Arc::new(Int32Array::from_iter_values(
+            partitions.keys().map(|(_, spec_id)| *spec_id),
+        )),
+        column(|x| x.record_count),
+        column(|x| x.file_count),
+        column(|x| x.total_data_file_size_in_bytes),
+        column(|x| x.position_delete_record_count),
+        column(|x| x.position_delete_file_count),
+        column(|x| x.equality_delete_record_count),
+        column(|x| x.equality_delete_file_count),
+    ])
+}
+
+fn refs(table: &Table) -> Vec<ArrayRef> {
+    let refs: BTreeMap<_, _> = table.metadata().refs.iter().collect();
+    let retention = |f: fn(&SnapshotRetention) -> Option<i64>| -> ArrayRef {
+        Arc::new(Int64Array::from_iter(
+            refs.values().map(|reference| f(&reference.retention)),
+        ))
+    };
+    vec![
+        Arc::new(StringArray::from_iter_values(refs.keys())),
+        Arc::new(StringArray::from_iter_values(refs.values().map(
+            |reference| match reference.retention {
+                SnapshotRetention::Branch { .. } => "BRANCH",
+                SnapshotRetention::Tag { .. } => "TAG",
+            },
+        ))),
+        Arc::new(Int64Array::from_iter_values(
+            refs.values().map(|reference| reference.snapshot_id),
+        )),
+        retention(|retention| match retention {
+            SnapshotRetention::Branch { max_ref_age_ms, .. } => *max_ref_age_ms,
+            SnapshotRetention::Tag { max_ref_age_ms } => Some(*max_ref_age_ms),
+        }),
+        Arc::new(Int32Array::from_iter(refs.values().map(
+            |reference| match reference.retention {
+                SnapshotRetention::Branch {
+                    min_snapshots_to_keep,
+                    ..
+                } => min_snapshots_to_keep,
+                SnapshotRetention::Tag { .. } => None,
+            },
+        ))),
+        retention(|retention| match retention {
+            SnapshotRetention::Branch {
+                max_snapshot_age_ms,
+                ..
+            } => *max_snapshot_age_ms,
+            SnapshotRetention::Tag { .. } => None,
+        }),
+    ]
+}
+
+fn metadata_log_entries(table: &Table) -> Vec<ArrayRef> {
+    let metadata = table.metadata();
+    let log = &metadata.metadata_log;
+    vec![
+        timestamps(log.iter().map(|x| x.timestamp_ms).collect()),
+        Arc::new(StringArray::from_iter_values(
+            log.iter().map(|x| &x.metadata_file),
+        )),
+        Arc::new(Int64Array::from_iter(log.iter().map(|x| {
+            metadata
+                .snapshot_log
+                .iter()
+                .filter(|entry| entry.timestamp_ms <= x.timestamp_ms)
+                .max_by_key(|entry| entry.timestamp_ms)
+                .map(|entry| entry.snapshot_id)
+        }))),
+    ]
+}
+
+/// Table provider of a metadata table, the rows are read when the table is scanned
+#[derive(Debug)]
+pub struct MetadataTableProvider {
+    metadata_table: MetadataTable,
+    table: Table,
+    snapshot_id: Option<i64>,
+    schema: SchemaRef,
+}
+
+impl MetadataTableProvider {
+    pub fn new(metadata_table: MetadataTable, table: Table, snapshot_id: Option<i64>) -> Self {
+        Self {
+            metadata_table,
+            table,
+            snapshot_id,
+            schema: metadata_table.schema(),
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for MetadataTableProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let batch = self
+            .metadata_table
+            .batch(&self.table, self.snapshot_id)
+            .await?;
+        MemTable::try_new(self.schema(), vec![vec![batch]])?
+            .scan(state, projection, filters, limit)
+            .await
+    }
+}
+
+#[cfg(all(test, feature = "sql"))]
+mod tests {
+    use std::sync::Arc;
+
+    use datafusion::arrow::{
+        array::{AsArray, RecordBatch},
+        datatypes::{Int32Type, Int64Type},
+    };
+    use iceberg_rust::{
+        catalog::{identifier::Identifier, namespace::Namespace, tabular::Tabular, CatalogList},
+        object_store::ObjectStoreBuilder,
+        spec::{
+            partition::{PartitionField, PartitionSpec, Transform},
+            schema::Schema,
+            table_metadata::MetadataLog,
+            types::{PrimitiveType, StructField, Type},
+        },
+        table::Table,
+    };
+    use iceberg_sql_catalog::SqlCatalogList;
+    use object_store::memory::InMemory;
+
+    use super::MetadataTable;
+    use crate::{FrostbowSessionBuilder, IcebergContext};
+
+    /// Session with the table `iceberg.db.t` partitioned by `v`, with the rows (1, 10) and
+    /// (2, 10) of the first snapshot and the row (3, 20) of the second
+    async fn session() -> (IcebergContext, Table) {
+        let catalog_list = Arc::new(
+            SqlCatalogList::new(
+                "sqlite://",
+                ObjectStoreBuilder::Memory(Arc::new(InMemory::new())),
+            )
+            .await
+            .unwrap(),
+        );
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        catalog
+            .create_namespace(&Namespace::try_new(&["db".to_owned()]).unwrap(), None)
+            .await
+            .unwrap();
+        let schema = Schema::builder()
+            .with_struct_field(StructField::new(
+                1,
+                "id",
+                true,
+                Type::Primitive(PrimitiveType::Long),
+                None,
+            ))
+            .with_struct_field(StructField::new(
+                2,
+                "v",
+                true,
+                Type::Primitive(PrimitiveType::Int),
+                None,
+            ))
+            .build()
+            .unwrap();
+        let partition_spec = PartitionSpec::builder()
+            .with_partition_field(PartitionField::new(2, 1000, "v", Transform::Identity))
+            .build()
+            .unwrap();
+        Table::builder()
+            .with_name("t")
+            .with_location("/warehouse/db/t")
+            .with_schema(schema)
+            .with_partition_spec(partition_spec)
+            .build(&["db".to_owned()], catalog.clone())
+            .await
+            .unwrap();
+
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        for values in ["(1, 10), (2, 10)", "(3, 20)"] {
+            collect(&ctx, &format!("INSERT INTO iceberg.db.t VALUES {values}")).await;
+        }
+        let identifier = Identifier::new(&["db".to_owned()], "t");
+        let Tabular::Table(table) = catalog.load_tabular(&identifier).await.unwrap() else {
+            panic!("t is a table")
+        };
+        (ctx, table)
+    }
+
+    async fn collect(ctx: &IcebergContext, sql: &str) -> Vec<RecordBatch> {
+        ctx.sql(sql).await.unwrap().collect().await.unwrap()
+    }
+
+    fn int64_values(batches: &[RecordBatch], column: usize) -> Vec<Option<i64>> {
+        batches
+            .iter()
+            .flat_map(|batch| batch.column(column).as_primitive::<Int64Type>().iter())
+            .collect()
+    }
+
+    fn int32_values(batches: &[RecordBatch], column: usize) -> Vec<Option<i32>> {
+        batches
+            .iter()
+            .flat_map(|batch| batch.column(column).as_primitive::<Int32Type>().iter())
+            .collect()
+    }
+
+    fn string_values(batches: &[RecordBatch], column: usize) -> Vec<String> {
+        batches
+            .iter()
+            .flat_map(|batch| batch.column(column).as_string::<i32>().iter())
+            .map(|value| value.unwrap_or_default().to_owned())
+            .collect()
+    }
+
+    /// Ids of the snapshots of the table in the order of their commits
+    fn snapshot_ids(table: &Table) -> Vec<Option<i64>> {
+        let mut snapshots: Vec<_> = table.metadata().snapshots.values().collect();
+        snapshots.sort_by_key(|snapshot| *snapshot.sequence_number());
+        snapshots
+            .into_iter()
+            .map(|snapshot| Some(*snapshot.snapshot_id()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn snapshots_lists_the_commits() {
+        let (ctx, table) = session().await;
+        let batches = collect(
+            &ctx,
+            r#"SELECT snapshot_id, parent_id, operation, summary['added-records']
+            FROM iceberg.db."t$snapshots""#,
+        )
+        .await;
+
+        let ids = snapshot_ids(&table);
+        assert_eq!(int64_values(&batches, 0), ids);
+        assert_eq!(int64_values(&batches, 1), vec![None, ids[0]]);
+        assert_eq!(string_values(&batches, 2), vec!["append", "append"]);
+        assert_eq!(string_values(&batches, 3), vec!["2", "1"]);
+    }
+
+    #[tokio::test]
+    async fn history_marks_the_ancestors_of_the_current_snapshot() {
+        let (ctx, table) = session().await;
+        let batches = collect(
+            &ctx,
+            r#"SELECT snapshot_id, parent_id, is_current_ancestor FROM iceberg.db."t$history""#,
+        )
+        .await;
+
+        let ids = snapshot_ids(&table);
+        assert_eq!(int64_values(&batches, 0), ids);
+        assert_eq!(int64_values(&batches, 1), vec![None, ids[0]]);
+        let ancestors: Vec<_> = batches
+            .iter()
+            .flat_map(|batch| batch.column(2).as_boolean().iter())
+            .collect();
+        assert_eq!(ancestors, vec![Some(true), Some(true)]);
+    }
+
+    #[tokio::test]
+    async fn files_lists_the_live_data_files() {
+        let (ctx, _) = session().await;
+        for name in ["files", "data_files"] {
+            let batches = collect(
+                &ctx,
+                &format!(
+                    r#"SELECT content, file_format, partition, record_count, file_path
+                    FROM iceberg.db."t${name}" ORDER BY partition"#
+                ),
+            )
+            .await;
+
+            assert_eq!(int32_values(&batches, 0), vec![Some(0), Some(0)]);
+            assert_eq!(string_values(&batches, 1), vec!["PARQUET", "PARQUET"]);
+            assert_eq!(string_values(&batches, 2), vec!["{v=10}", "{v=20}"]);
+            assert_eq!(int64_values(&batches, 3), vec![Some(2), Some(1)]);
+            assert!(string_values(&batches, 4)
+                .iter()
+                .all(|path| path.starts_with("/warehouse/db/t/data/")));
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_files_excludes_data_files() {
+        let (ctx, _) = session().await;
+        let batches = collect(&ctx, r#"SELECT count(*) FROM iceberg.db."t$delete_files""#).await;
+        assert_eq!(int64_values(&batches, 0), vec![Some(0)]);
+    }
+
+    #[tokio::test]
+    async fn manifests_lists_the_manifests_of_the_current_snapshot() {
+        let (ctx, table) = session().await;
+        let batches = collect(
+            &ctx,
+            r#"SELECT content, path, added_snapshot_id, added_files_count, existing_rows_count
+            FROM iceberg.db."t$manifests""#,
+        )
+        .await;
+
+        let manifests = table.manifests(None, None).await.unwrap();
+        assert!(!manifests.is_empty());
+        assert!(int32_values(&batches, 0).iter().all(|x| *x == Some(0)));
+        let paths: Vec<_> = manifests.iter().map(|x| x.manifest_path.clone()).collect();
+        assert_eq!(string_values(&batches, 1), paths);
+        let added_snapshot_ids: Vec<_> = manifests
+            .iter()
+            .map(|x| Some(x.added_snapshot_id))
+            .collect();
+        assert_eq!(int64_values(&batches, 2), added_snapshot_ids);
+        let added_files: Vec<_> = manifests.iter().map(|x| x.added_files_count).collect();
+        assert_eq!(int32_values(&batches, 3), added_files);
+        let existing_rows: Vec<_> = manifests.iter().map(|x| x.existing_rows_count).collect();
+        assert_eq!(int64_values(&batches, 4), existing_rows);
+    }
+
+    #[tokio::test]
+    async fn partitions_aggregates_the_files() {
+        let (ctx, _) = session().await;
+        let batches = collect(
+            &ctx,
+            r#"SELECT partition, spec_id, record_count, file_count, position_delete_file_count
+            FROM iceberg.db."t$partitions""#,
+        )
+        .await;
+
+        assert_eq!(string_values(&batches, 0), vec!["{v=10}", "{v=20}"]);
+        assert_eq!(int32_values(&batches, 1), vec![Some(0), Some(0)]);
+        assert_eq!(int64_values(&batches, 2), vec![Some(2), Some(1)]);
+        assert_eq!(int64_values(&batches, 3), vec![Some(1), Some(1)]);
+        assert_eq!(int64_values(&batches, 4), vec![Some(0), Some(0)]);
+    }
+
+    #[tokio::test]
+    async fn refs_lists_the_main_branch() {
+        let (ctx, table) = session().await;
+        let batches = collect(
+            &ctx,
+            r#"SELECT name, type, snapshot_id FROM iceberg.db."t$refs""#,
+        )
+        .await;
+
+        assert_eq!(string_values(&batches, 0), vec!["main"]);
+        assert_eq!(string_values(&batches, 1), vec!["BRANCH"]);
+        assert_eq!(
+            int64_values(&batches, 2),
+            vec![table.metadata().current_snapshot_id]
+        );
+    }
+
+    #[tokio::test]
+    async fn metadata_log_entries_lists_the_previous_metadata_files() {
+        let (_, table) = session().await;
+        // The SQL catalog doesn't keep a metadata log, so the entries are added to the metadata
+        let mut metadata = table.metadata().clone();
+        let mut log = metadata.snapshot_log.clone();
+        log.sort_by_key(|entry| entry.timestamp_ms);
+        metadata.metadata_log = vec![
+            MetadataLog {
+                metadata_file: "/warehouse/db/t/metadata/00000.metadata.json".to_owned(),
+                timestamp_ms: log[0].timestamp_ms - 1,
+            },
+            MetadataLog {
+                metadata_file: "/warehouse/db/t/metadata/00001.metadata.json".to_owned(),
+                timestamp_ms: log[1].timestamp_ms,
+            },
+        ];
+        let table = Table::new(
+            table.identifier().clone(),
+            table.catalog(),
+            table.object_store(),
+            metadata,
+        )
+        .await
+        .unwrap();
+
+        let batch = MetadataTable::MetadataLogEntries
+            .batch(&table, None)
+            .await
+            .unwrap();
+        let batches = [batch];
+        assert_eq!(
+            string_values(&batches, 1),
+            vec![
+                "/warehouse/db/t/metadata/00000.metadata.json",
+                "/warehouse/db/t/metadata/00001.metadata.json"
+            ]
+        );
+        assert_eq!(
+            int64_values(&batches, 2),
+            vec![None, Some(log[1].snapshot_id)]
+        );
+    }
+}
@@ -0,0 +1,1899 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use datafusion::{
+    arrow::{
+        array::{ArrayRef, AsArray, Int64Array, StringArray, TimestampMillisecondArray},
+        compute::cast,
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    common::{DFSchema, DFSchemaRef, ScalarValue, TableReference},
+    dataframe::DataFrame,
+    datasource::file_format::options::ParquetReadOptions,
+    error::DataFusionError,
+    execution::{
+        context::SessionContext, object_store::ObjectStoreUrl, runtime_env::RuntimeEnv,
+        SessionState, TaskContext,
+    },
+    logical_expr::{ident, lit, Cast, Expr, Extension, LogicalPlan, UserDefinedLogicalNodeCore},
+    parquet::arrow::PARQUET_FIELD_ID_META_KEY,
+    physical_plan::stream::RecordBatchStreamAdapter,
+    sql::{
+        parser::{DFParser, Statement as DFStatement},
+        sqlparser::{
+            ast::{
+                Expr as SqlExpr, FunctionArg, FunctionArgExpr, FunctionArguments, Ident, Statement,
+            },
+            dialect::dialect_from_str,
+            keywords::Keyword,
+            parser::Parser,
+        },
+    },
+};
+use datafusion_iceberg::{
+    table::{write_parquet_data_files, DataFusionTableConfigBuilder},
+    DataFusionTable,
+};
+use futures::{channel::mpsc, SinkExt, TryStreamExt};
+use iceberg_rust::{
+    catalog::tabular::Tabular,
+    error::Error,
+    spec::{
+        error::Error as SpecError,
+        manifest::{Content, DataFile, Status},
+        snapshot::{Operation, SnapshotReference},
+        util::strip_prefix,
+        values::Struct,
+    },
+    table::Table,
+};
+use object_store::{path::Path, ObjectStore, ObjectStoreExt};
+
+use crate::sql::starts_with_keyword;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Table maintenance procedure that is run with `CALL system.<procedure>(...)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
+pub enum Procedure {
+    /// Remove the snapshots that are older than a timestamp from the table metadata
+    ExpireSnapshots,
+    /// Delete the files in the table location that are not referenced by any snapshot
+    RemoveOrphanFiles,
+    /// Compact or sort the data files of each partition into files of the target size
+    RewriteDataFiles,
+    /// Rewrite the manifests of the current snapshot, clustered by partition
+    RewriteManifests,
+    /// Make an ancestor of the current snapshot the current snapshot
+    RollbackToSnapshot,
+}
+
+impl Procedure {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Procedure::ExpireSnapshots => "expire_snapshots",
+            Procedure::RemoveOrphanFiles => "remove_orphan_files",
+            Procedure::RewriteDataFiles => "rewrite_data_files",
+            Procedure::RewriteManifests => "rewrite_manifests",
+            Procedure::RollbackToSnapshot => "rollback_to_snapshot",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Procedure::ExpireSnapshots,
+            Procedure::RemoveOrphanFiles,
+            Procedure::RewriteDataFiles,
+            Procedure::RewriteManifests,
+            Procedure::RollbackToSnapshot,
+        ]
+        .into_iter()
+        .find(|procedure| procedure.name() == name)
+    }
+
+    /// Names of the arguments in the order of the positional arguments
+    fn parameters(&self) -> &'static [&'static str] {
+        match self {
+            Procedure::ExpireSnapshots => &["table", "older_than", "retain_last"],
+            Procedure::RemoveOrphanFiles => &["table", "older_than", "location", "dry_run"],
+            Procedure::RewriteDataFiles => &[
+                "table",
+                "strategy",
+                "sort_order",
+                "where",
+                "target_file_size",
+            ],
+            Procedure::RewriteManifests => &["table"],
+            Procedure::RollbackToSnapshot => &["table", "snapshot_id"],
+        }
+    }
+
+    /// Schema of the result of the procedure
+    pub fn schema(&self) -> SchemaRef {
+        let fields = match self {
+            Procedure::ExpireSnapshots => vec![
+                Field::new("expired_snapshot_id", DataType::Int64, false),
+                Field::new(
+                    "committed_at",
+                    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                    false,
+                ),
+            ],
+            Procedure::RemoveOrphanFiles => {
+                vec![Field::new("orphan_file_location", DataType::Utf8, false)]
+            }
+            Procedure::RewriteDataFiles => vec![
+                Field::new("rewritten_data_files_count", DataType::Int64, false),
+                Field::new("added_data_files_count", DataType::Int64, false),
+                Field::new("rewritten_bytes_count", DataType::Int64, false),
+            ],
+            Procedure::RewriteManifests => vec![
+                Field::new("rewritten_manifests_count", DataType::Int64, false),
+                Field::new("added_manifests_count", DataType::Int64, false),
+            ],
+            Procedure::RollbackToSnapshot => vec![
+                Field::new("previous_snapshot_id", DataType::Int64, true),
+                Field::new("current_snapshot_id", DataType::Int64, false),
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+}
+
+impl fmt::Display for Procedure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "system.{}", self.name())
+    }
+}
+
+/// Logical plan node of a `CALL` statement
+///
+/// The node can't be executed by DataFusion, it is intercepted by
+/// [`IcebergContext`](crate::IcebergContext) which runs the procedure and returns what it
+/// changed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallProcedure {
+    procedure: Procedure,
+    /// Catalog of the procedure name, used for table names without catalog
+    catalog: Option<String>,
+    /// Names of the arguments, in the same order as the expressions
+    parameters: Vec<&'static str>,
+    args: Vec<Expr>,
+    schema: DFSchemaRef,
+}
+
+impl PartialOrd for CallProcedure {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (&self.procedure, &self.catalog, &self.parameters, &self.args).partial_cmp(&(
+            &other.procedure,
+            &other.catalog,
+            &other.parameters,
+            &other.args,
+        ))
+    }
+}
+
+impl UserDefinedLogicalNodeCore for CallProcedure {
+    fn name(&self) -> &str {
+        "CallProcedure"
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        Vec::new()
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        self.args.clone()
+    }
+
+    fn fmt_for_explain(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args: Vec<_> = self
+            .parameters
+            .iter()
+            .zip(&self.args)
+            .map(|(name, arg)| format!("{name} => {arg}"))
+            .collect();
+        write!(f, "CallProcedure: {}({})", self.procedure, args.join(", "))
+    }
+
+    fn with_exprs_and_inputs(
+        &self,
+        exprs: Vec<Expr>,
+        _inputs: Vec<LogicalPlan>,
+    ) -> Result<Self, DataFusionError> {
+        Ok(Self {
+            args: exprs,
+            ..self.clone()
+        })
+    }
+}
+
+impl CallProcedure {
+    /// Plan a `CALL` statement, returns `None` for all other statements
+    pub fn plan(state: &SessionState, sql: &str) -> Result<Option<LogicalPlan>, DataFusionError> {
+        let options = &state.config().options().sql_parser;
+        if !starts_with_keyword(sql, options, Keyword::CALL) {
+            return Ok(None);
+        }
+        let Some(dialect) = dialect_from_str(options.dialect) else {
+            return Ok(None);
+        };
+        let statements = DFParser::parse_sql_with_dialect(sql, dialect.as_ref())?;
+        let (Some(DFStatement::Statement(statement)), 1) = (statements.front(), statements.len())
+        else {
+            return Ok(None);
+        };
+        let Statement::Call(function) = statement.as_ref() else {
+            return Ok(None);
+        };
+
+        let normalize = |ident: &Ident| {
+            if ident.quote_style.is_none() && options.enable_ident_normalization {
+                ident.value.to_lowercase()
+            } else {
+                ident.value.clone()
+            }
+        };
+        let name = function
+            .name
+            .0
+            .iter()
+            .map(|part| part.as_ident().map(normalize))
+            .collect::<Option<Vec<_>>>();
+        let (catalog, procedure) = match name.as_deref() {
+            Some([system, procedure]) if system == "system" => (None, procedure),
+            Some([catalog, system, procedure]) if system == "system" => {
+                (Some(catalog.clone()), procedure)
+            }
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "Procedure {} doesn't exist, procedures are called as system.<procedure>.",
+                    function.name
+                )))
+            }
+        };
+        let procedure = Procedure::from_name(procedure).ok_or_else(|| {
+            DataFusionError::Plan(format!("Procedure {} doesn't exist.", function.name))
+        })?;
+
+        let args = match &function.args {
+            FunctionArguments::List(list) => list.args.as_slice(),
+            _ => &[],
+        };
+        let mut bound: Vec<(&'static str, Expr)> = Vec::new();
+        for (position, arg) in args.iter().enumerate() {
+            let (name, arg) = match arg {
+                FunctionArg::Named { name, arg, .. }
+                | FunctionArg::ExprNamed {
+                    name: SqlExpr::Identifier(name),
+                    arg,
+                    ..
+                } => (Some(normalize(name)), arg),
+                FunctionArg::Unnamed(arg) if bound.len() == position => (None, arg),
+                FunctionArg::Unnamed(_) => {
+                    return Err(DataFusionError::Plan(format!(
+                        "Positional arguments of {procedure} have to come before named arguments."
+                    )))
+                }
+                _ => {
+                    return Err(DataFusionError::Plan(format!(
+                        "Argument {arg} of {procedure} is not supported."
+                    )))
+                }
+            };
+            let parameter = match name {
+                Some(name) => procedure
+                    .parameters()
+                    .iter()
+                    .find(|parameter| **parameter == name)
+                    .ok_or_else(|| {
+                        DataFusionError::Plan(format!(
+                            "Procedure {procedure} has no argument {name}."
+                        ))
+                    })?,
+                None => procedure.parameters().get(position).ok_or_else(|| {
+                    DataFusionError::Plan(format!(
+                        "Procedure {procedure} takes at most {} arguments.",
+                        procedure.parameters().len()
+                    ))
+                })?,
+            };
+            if bound.iter().any(|(name, _)| name == parameter) {
+                return Err(DataFusionError::Plan(format!(
+                    "Argument {parameter} of {procedure} is given more than once."
+                )));
+            }
+            let FunctionArgExpr::Expr(arg) = arg else {
+                return Err(DataFusionError::Plan(format!(
+                    "Argument {parameter} of {procedure} is not an expression."
+                )));
+            };
+            let arg = state.create_logical_expr(&arg.to_string(), &DFSchema::empty())?;
+            bound.push((parameter, arg));
+        }
+        if !bound.iter().any(|(name, _)| *name == "table") {
+            return Err(DataFusionError::Plan(format!(
+                "Procedure {procedure} requires the argument table."
+            )));
+        }
+
+        let (parameters, args) = bound.into_iter().unzip();
+        Ok(Some(LogicalPlan::Extension(Extension {
+            node: Arc::new(CallProcedure {
+                procedure,
+                catalog,
+                parameters,
+                args,
+                schema: Arc::new(DFSchema::try_from(procedure.schema())?),
+            }),
+        })))
+    }
+
+    /// Run the procedure, returns what it changed
+    pub async fn execute(&self, ctx: &SessionContext) -> Result<DataFrame, DataFusionError> {
+        let args = self.evaluate(ctx).await?;
+        let mut table = self.table(ctx, &args).await?;
+        let columns = match self.procedure {
+            Procedure::ExpireSnapshots => {
+                let retain_last = args
+                    .integer("retain_last")?
+                    .map(|retain_last| {
+                        usize::try_from(retain_last).map_err(|_| {
+                            DataFusionError::Plan(format!(
+                                "Argument retain_last of {} is negative.",
+                                self.procedure
+                            ))
+                        })
+                    })
+                    .transpose()?;
+                expire_snapshots(&mut table, args.timestamp_ms("older_than")?, retain_last).await
+            }
+            Procedure::RemoveOrphanFiles => {
+                let location = args.string("location")?;
+                if let Some(location) = &location {
+                    if !is_within(location, &table.metadata().location) {
+                        return Err(DataFusionError::Plan(format!(
+                            "Argument location of {} has to be the location of table {} or below it, got {location}.",
+                            self.procedure,
+                            table.identifier()
+                        )));
+                    }
+                }
+                remove_orphan_files(
+                    &table,
+                    args.timestamp_ms("older_than")?,
+                    location,
+                    args.boolean("dry_run")?.unwrap_or_default(),
+                )
+                .await
+            }
+            Procedure::RewriteDataFiles => {
+                let columns = rewrite_data_files(ctx, &mut table, &args).await?;
+                return ctx.read_batch(RecordBatch::try_new(self.procedure.schema(), columns)?);
+            }
+            Procedure::RewriteManifests => rewrite_manifests(&mut table).await,
+            Procedure::RollbackToSnapshot => {
+                let snapshot_id = args.integer("snapshot_id")?.ok_or_else(|| {
+                    DataFusionError::Plan(format!(
+                        "Procedure {} requires the argument snapshot_id.",
+                        self.procedure
+                    ))
+                })?;
+                if !is_ancestor(&table, snapshot_id) {
+                    return Err(DataFusionError::Plan(format!(
+                        "Snapshot {snapshot_id} is not an ancestor of the current snapshot of {}.",
+                        table.identifier()
+                    )));
+                }
+                rollback_to_snapshot(&mut table, snapshot_id).await
+            }
+        }
+        .map_err(|err| DataFusionError::External(Box::new(err)))?;
+        ctx.read_batch(RecordBatch::try_new(self.procedure.schema(), columns)?)
+    }
+
+    /// Evaluate the arguments of the procedure
+    async fn evaluate(&self, ctx: &SessionContext) -> Result<Arguments, DataFusionError> {
+        let exprs = self
+            .parameters
+            .iter()
+            .zip(&self.args)
+            .map(|(name, arg)| arg.clone().alias(*name));
+        let batches = ctx.read_empty()?.select(exprs)?.collect().await?;
+        let mut values = HashMap::new();
+        if let Some(batch) = batches.first() {
+            for (name, column) in self.parameters.iter().zip(batch.columns()) {
+                values.insert(*name, ScalarValue::try_from_array(column, 0)?);
+            }
+        }
+        Ok(Arguments {
+            procedure: self.procedure,
+            values,
+        })
+    }
+
+    /// Iceberg table of the `table` argument
+    async fn table(
+        &self,
+        ctx: &SessionContext,
+        args: &Arguments,
+    ) -> Result<Table, DataFusionError> {
+        let name = args.string("table")?.ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "Procedure {} requires the argument table.",
+                self.procedure
+            ))
+        })?;
+        let reference = match (&self.catalog, TableReference::parse_str(&name)) {
+            (Some(catalog), TableReference::Partial { schema, table }) => {
+                TableReference::full(catalog.as_str(), schema, table)
+            }
+            (Some(catalog), TableReference::Bare { table }) => TableReference::full(
+                catalog.as_str(),
+                ctx.state()
+                    .config()
+                    .options()
+                    .catalog
+                    .default_schema
+                    .as_str(),
+                table,
+            ),
+            (_, reference) => reference,
+        };
+        let provider = ctx.table_provider(reference).await?;
+        let Some(provider) = provider.as_any().downcast_ref::<DataFusionTable>() else {
+            return Err(DataFusionError::Plan(format!(
+                "Procedure {} is only supported for Iceberg tables, {name} is not one.",
+                self.procedure
+            )));
+        };
+        let tabular = provider
+            .tabular
+            .read()
+            .map_err(|_| {
+                DataFusionError::Execution(format!(
+                    "Table {name} can't be read, a writer of its metadata panicked."
+                ))
+            })?
+            .clone();
+        match tabular {
+            Tabular::Table(table) => Ok(table),
+            tabular => Err(DataFusionError::Plan(format!(
+                "Procedure {} is only supported for tables, {} is a view.",
+                self.procedure,
+                tabular.identifier()
+            ))),
+        }
+    }
+}
+
+/// Evaluated arguments of a procedure
+struct Arguments {
+    procedure: Procedure,
+    values: HashMap<&'static str, ScalarValue>,
+}
+
+impl Arguments {
+    fn value(
+        &self,
+        name: &str,
+        data_type: &DataType,
+    ) -> Result<Option<ScalarValue>, DataFusionError> {
+        match self.values.get(name) {
+            Some(value) if !value.is_null() => value.cast_to(data_type).map(Some).map_err(|_| {
+                DataFusionError::Plan(format!(
+                    "Argument {name} of {} has to be of type {data_type}, got {value}.",
+                    self.procedure
+                ))
+            }),
+            _ => Ok(None),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, DataFusionError> {
+        match self.value(name, &DataType::Utf8)? {
+            Some(ScalarValue::Utf8(value)) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn integer(&self, name: &str) -> Result<Option<i64>, DataFusionError> {
+        match self.value(name, &DataType::Int64)? {
+            Some(ScalarValue::Int64(value)) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Result<Option<bool>, DataFusionError> {
+        match self.value(name, &DataType::Boolean)? {
+            Some(ScalarValue::Boolean(value)) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn timestamp_ms(&self, name: &str) -> Result<Option<i64>, DataFusionError> {
+        match self.value(name, &DataType::Timestamp(TimeUnit::Millisecond, None))? {
+            Some(ScalarValue::TimestampMillisecond(value, _)) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Expire the snapshots older than `older_than`, 5 days ago by default, keeping at least the
+/// last `retain_last` snapshots and all snapshots referenced by a branch or tag
+async fn expire_snapshots(
+    table: &mut Table,
+    older_than: Option<i64>,
+    retain_last: Option<usize>,
+) -> Result<Vec<ArrayRef>, Error> {
+    let before: Vec<_> = table
+        .metadata()
+        .snapshots
+        .values()
+        .map(|snapshot| (*snapshot.snapshot_id(), *snapshot.timestamp_ms()))
+        .collect();
+    table
+        .new_transaction(None)
+        .expire_snapshots(
+            Some(older_than.unwrap_or_else(|| now_ms() - 5 * DAY_MS)),
+            retain_last,
+            false,
+            true,
+            false,
+        )
+        .commit()
+        .await?;
+    let mut expired: Vec<_> = before
+        .into_iter()
+        .filter(|(id, _)| !table.metadata().snapshots.contains_key(id))
+        .collect();
+    expired.sort_by_key(|(_, timestamp_ms)| *timestamp_ms);
+    Ok(vec![
+        Arc::new(Int64Array::from_iter_values(
+            expired.iter().map(|(id, _)| *id),
+        )),
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                expired.iter().map(|(_, timestamp_ms)| *timestamp_ms),
+            )
+            .with_timezone("UTC"),
+        ),
+    ])
+}
+
+/// Whether `location` is the table location or a path below it
+///
+/// Only the files of the table are known to be referenced, the files of other tables in a parent
+/// location would be deleted as orphans.
+fn is_within(location: &str, table_location: &str) -> bool {
+    let location = location.trim_end_matches('/');
+    let table_location = table_location.trim_end_matches('/');
+    location
+        .strip_prefix(table_location)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        && !location.split('/').any(|segment| segment == "..")
+}
+
+/// Delete the files in the location of the table that were last modified before
+/// `older_than`, 3 days ago by default, and are not referenced by any snapshot
+///
+/// Metadata files are never deleted, a missing metadata file would break the table. The
+/// statistics files that a metadata file references are kept as well.
+async fn remove_orphan_files(
+    table: &Table,
+    older_than: Option<i64>,
+    location: Option<String>,
+    dry_run: bool,
+) -> Result<Vec<ArrayRef>, Error> {
+    let older_than = older_than.unwrap_or_else(|| now_ms() - 3 * DAY_MS);
+    let metadata = table.metadata();
+    let location = location.unwrap_or_else(|| metadata.location.clone());
+    // `s3://bucket/db/table` has the prefix `s3://bucket` and the path `db/table`
+    let path = strip_prefix(&location);
+    let prefix = location
+        .strip_suffix(path.as_str())
+        .unwrap_or_default()
+        .to_owned();
+
+    let mut referenced = referenced_files(table).await?;
+    referenced.extend(statistics_files(table).await?);
+
+    let object_store = table.object_store();
+    let files: Vec<_> = object_store
+        .list(Some(&Path::from(path)))
+        .try_collect()
+        .await?;
+    let mut orphans = Vec::new();
+    for file in files {
+        let name = file.location.filename().unwrap_or_default();
+        if referenced.contains(file.location.as_ref())
+            || name.ends_with(".metadata.json")
+            || name == "version-hint.text"
+            || file.last_modified.timestamp_millis() >= older_than
+        {
+            continue;
+        }
+        if !dry_run {
+            object_store.delete(&file.location).await?;
+        }
+        orphans.push(format!("{prefix}/{}", file.location));
+    }
+    orphans.sort();
+    Ok(vec![Arc::new(StringArray::from(orphans))])
+}
+
+/// Paths of the metadata, manifest and data files that are referenced by a snapshot of the
+/// table, relative to its object store
+async fn referenced_files(table: &Table) -> Result<HashSet<String>, Error> {
+    let metadata = table.metadata();
+    let mut referenced: HashSet<String> = metadata
+        .metadata_log
+        .iter()
+        .map(|entry| Path::from(strip_prefix(&entry.metadata_file)).to_string())
+        .collect();
+    let mut manifests = HashMap::new();
+    for snapshot in metadata.snapshots.values() {
+        referenced.insert(Path::from(strip_prefix(snapshot.manifest_list())).to_string());
+        for manifest in table.manifests(None, Some(*snapshot.snapshot_id())).await? {
+            manifests.insert(manifest.manifest_path.clone(), manifest);
+        }
+    }
+    let manifests: Vec<_> = manifests.into_values().collect();
+    referenced.extend(
+        manifests
+            .iter()
+            .map(|manifest| Path::from(strip_prefix(&manifest.manifest_path)).to_string()),
+    );
+    for entry in table.datafiles(&manifests, None, (None, None)).await? {
+        let (_, entry) = entry?;
+        referenced.insert(Path::from(strip_prefix(entry.data_file().file_path())).to_string());
+    }
+    Ok(referenced)
+}
+
+/// Paths of the statistics files, like the puffin files of other engines, that the metadata
+/// files of the table reference
+///
+/// The parsed table metadata drops the statistics files, so they are read from every metadata
+/// file in the metadata directory of the table.
+async fn statistics_files(table: &Table) -> Result<HashSet<String>, Error> {
+    let object_store = table.object_store();
+    let directory = format!("{}/metadata", strip_prefix(&table.metadata().location));
+    let files: Vec<_> = object_store
+        .list(Some(&Path::from(directory)))
+        .try_collect()
+        .await?;
+    let mut referenced = HashSet::new();
+    for file in files {
+        if !file
+            .location
+            .filename()
+            .is_some_and(|name| name.ends_with(".metadata.json"))
+        {
+            continue;
+        }
+        let bytes = object_store.get(&file.location).await?.bytes().await?;
+        let metadata: serde_json::Value = serde_json::from_slice(&bytes)?;
+        for key in ["statistics", "partition-statistics"] {
+            let paths = metadata
+                .get(key)
+                .and_then(|files| files.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|file| file.get("statistics-path")?.as_str());
+            referenced.extend(paths.map(|path| Path::from(strip_prefix(path)).to_string()));
+        }
+    }
+    Ok(referenced)
+}
+
+/// Rewrite the live data files of the current snapshot into new manifests
+async fn rewrite_manifests(table: &mut Table) -> Result<Vec<ArrayRef>, Error> {
+    let manifests = table.manifests(None, None).await?;
+    let mut files = Vec::new();
+    for entry in table.datafiles(&manifests, None, (None, None)).await? {
+        let (_, entry) = entry?;
+        if *entry.status() == Status::Deleted {
+            continue;
+        }
+        if *entry.data_file().content() != Content::Data {
+            return Err(Error::NotSupported(
+                "rewriting the manifests of tables with delete files".to_owned(),
+            ));
+        }
+        files.push(entry.data_file().clone());
+    }
+    if files.is_empty() {
+        return Ok(vec![
+            Arc::new(Int64Array::from(vec![0])),
+            Arc::new(Int64Array::from(vec![0])),
+        ]);
+    }
+    table.new_transaction(None).replace(files).commit().await?;
+    let added = table.manifests(None, None).await?;
+    Ok(vec![
+        Arc::new(Int64Array::from(vec![manifests.len() as i64])),
+        Arc::new(Int64Array::from(vec![added.len() as i64])),
+    ])
+}
+
+/// Default of the `write.target-file-size-bytes` table property
+const DEFAULT_TARGET_FILE_SIZE: i64 = 512 * 1024 * 1024;
+
+/// Number of times the commit of a rewrite is retried after concurrent appends
+const COMMIT_ATTEMPTS: usize = 4;
+
+const DATA_FILE_PATH_COLUMN: &str = "__data_file_path";
+
+/// How `rewrite_data_files` selects and orders the data files
+enum RewriteStrategy {
+    /// Combine the files smaller than 75% of the target file size
+    BinPack,
+    /// Rewrite all files of a partition, ordered by the `ORDER BY` expressions
+    Sort(String),
+}
+
+/// Rewrite the data files of the current snapshot into files of the target size
+///
+/// The files of each partition are read and sorted under the memory pool of the session. The
+/// rewrite is committed on top of the snapshot that was read, if other appends were committed in
+/// the meantime the commit is retried on top of them.
+async fn rewrite_data_files(
+    ctx: &SessionContext,
+    table: &mut Table,
+    args: &Arguments,
+) -> Result<Vec<ArrayRef>, DataFusionError> {
+    let procedure = args.procedure;
+    let sort_order = args.string("sort_order")?;
+    let strategy = args
+        .string("strategy")?
+        .map(|strategy| strategy.to_lowercase());
+    let strategy = match (strategy.as_deref(), sort_order) {
+        (None | Some("sort"), Some(sort_order)) => RewriteStrategy::Sort(sort_order),
+        (None | Some("binpack"), None) => RewriteStrategy::BinPack,
+        (Some("binpack"), Some(_)) => {
+            return Err(DataFusionError::Plan(format!(
+                "Argument sort_order of {procedure} requires the strategy sort."
+            )))
+        }
+        (Some("sort"), None) => {
+            return Err(DataFusionError::Plan(format!(
+                "Strategy sort of {procedure} requires the argument sort_order."
+            )))
+        }
+        (Some(strategy), _) => {
+            return Err(DataFusionError::Plan(format!(
+                "Strategy {strategy} of {procedure} is not supported, use binpack or sort."
+            )))
+        }
+    };
+    let target_file_size = match args.integer("target_file_size")? {
+        Some(size) => size,
+        None => table
+            .metadata()
+            .properties
+            .get("write.target-file-size-bytes")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_TARGET_FILE_SIZE),
+    };
+    if target_file_size <= 0 {
+        return Err(DataFusionError::Plan(format!(
+            "Argument target_file_size of {procedure} has to be positive."
+        )));
+    }
+    let selected = match args.string("where")? {
+        Some(predicate) => Some(files_matching(ctx, table, &predicate).await?),
+        None => None,
+    };
+
+    let mut partitions: HashMap<Struct, Vec<DataFile>> = HashMap::new();
+    for file in live_data_files(table).await? {
+        if selected
+            .as_ref()
+            .is_none_or(|selected| selected.contains(file.file_path()))
+        {
+            partitions
+                .entry(file.partition().clone())
+                .or_default()
+                .push(file);
+        }
+    }
+
+    let groups: Vec<_> = match strategy {
+        RewriteStrategy::BinPack => partitions
+            .into_values()
+            .flat_map(|files| bin_pack(files, target_file_size))
+            .collect(),
+        RewriteStrategy::Sort(_) => partitions.into_values().collect(),
+    };
+    let rewritten: Vec<_> = groups.iter().flatten().cloned().collect();
+    if rewritten.is_empty() {
+        return Ok(vec![
+            Arc::new(Int64Array::from(vec![0])),
+            Arc::new(Int64Array::from(vec![0])),
+            Arc::new(Int64Array::from(vec![0])),
+        ]);
+    }
+
+    let sort_order = match &strategy {
+        RewriteStrategy::Sort(sort_order) => Some(sort_order.as_str()),
+        RewriteStrategy::BinPack => None,
+    };
+    let mut added = Vec::new();
+    let result = async {
+        let store = RegisteredObjectStore::new(ctx, table.object_store())?;
+        for files in &groups {
+            write_data_files(
+                ctx,
+                table,
+                &store.url,
+                files,
+                sort_order,
+                target_file_size,
+                &mut added,
+            )
+            .await?;
+        }
+        commit_rewrite(table, &rewritten, added.clone()).await
+    }
+    .await;
+    if let Err(err) = result {
+        // A failed commit might have been applied by the catalog, only the new files that no
+        // snapshot references are deleted. They are all kept if the table can't be loaded.
+        let referenced = match load_table(table).await {
+            Ok(current) => referenced_files(&current).await.ok(),
+            Err(_) => None,
+        };
+        if let Some(referenced) = referenced {
+            for file in &added {
+                let path = Path::from(strip_prefix(file.file_path()));
+                if !referenced.contains(path.as_ref()) {
+                    table.object_store().delete(&path).await.ok();
+                }
+            }
+        }
+        return Err(err);
+    }
+
+    Ok(vec![
+        Arc::new(Int64Array::from(vec![rewritten.len() as i64])),
+        Arc::new(Int64Array::from(vec![added.len() as i64])),
+        Arc::new(Int64Array::from(vec![rewritten
+            .iter()
+            .map(|file| *file.file_size_in_bytes())
+            .sum::<i64>()])),
+    ])
+}
+
+/// Read data files with the current schema of the table
+///
+/// The columns of the files are resolved by their field id like Iceberg requires, so renamed
+/// columns keep their values and a column that was dropped and added again under the same name
+/// is read as NULL. Files without field ids are resolved by name. Files are read together with
+/// the other files of the same file schema.
+async fn read_data_files(
+    ctx: &SessionContext,
+    table: &Table,
+    object_store_url: &ObjectStoreUrl,
+    files: &[DataFile],
+) -> Result<DataFrame, DataFusionError> {
+    let schema = table
+        .current_schema(None)
+        .map_err(|err| DataFusionError::External(Box::new(err)))?;
+    let arrow_schema: Schema = schema
+        .fields()
+        .try_into()
+        .map_err(|err: SpecError| DataFusionError::External(Box::new(err)))?;
+
+    // The field ids are part of the field metadata
+    let options = ParquetReadOptions {
+        file_extension: "",
+        ..Default::default()
+    }
+    .skip_metadata(false);
+    let mut groups: Vec<(Schema, Vec<String>)> = Vec::new();
+    for file in files {
+        let path = format!(
+            "{object_store_url}{}",
+            Path::from(strip_prefix(file.file_path()))
+        );
+        let file_schema = ctx
+            .read_parquet(path.as_str(), options.clone())
+            .await?
+            .schema()
+            .as_arrow()
+            .clone();
+        match groups.iter_mut().find(|(schema, _)| *schema == file_schema) {
+            Some((_, paths)) => paths.push(path),
+            None => groups.push((file_schema, vec![path])),
+        }
+    }
+
+    let mut result: Option<DataFrame> = None;
+    for (file_schema, paths) in groups {
+        let df = ctx
+            .read_parquet(paths, options.clone().schema(&file_schema))
+            .await?;
+        let columns = arrow_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let column = match field_id(field) {
+                    Some(id) if file_schema.fields().iter().any(|x| field_id(x).is_some()) => {
+                        file_schema
+                            .fields()
+                            .iter()
+                            .find(|file_field| field_id(file_field) == Some(id))
+                    }
+                    _ => file_schema
+                        .fields()
+                        .iter()
+                        .find(|file_field| file_field.name() == field.name()),
+                };
+                let expr = match column {
+                    Some(column) => ident(column.name()),
+                    None => lit(ScalarValue::Null),
+                };
+                Expr::Cast(Cast::new(Box::new(expr), field.data_type().clone())).alias(field.name())
+            })
+            .collect::<Vec<_>>();
+        let df = df.select(columns)?;
+        result = Some(match result {
+            Some(result) => result.union(df)?,
+            None => df,
+        });
+    }
+    result.ok_or_else(|| DataFusionError::Internal("No data files to read.".to_owned()))
+}
+
+/// Iceberg field id of a column of a parquet file
+fn field_id(field: &Field) -> Option<&str> {
+    field
+        .metadata()
+        .get(PARQUET_FIELD_ID_META_KEY)
+        .map(String::as_str)
+}
+
+/// Paths of the data files with rows that match the predicate
+async fn files_matching(
+    ctx: &SessionContext,
+    table: &Table,
+    predicate: &str,
+) -> Result<HashSet<String>, DataFusionError> {
+    let config = DataFusionTableConfigBuilder::default()
+        .enable_data_file_path_column(true)
+        .enable_manifest_file_path_column(false)
+        .build()
+        .map_err(|err| DataFusionError::External(Box::new(err)))?;
+    let provider = DataFusionTable::new_with_config(
+        Tabular::Table(table.clone()),
+        None,
+        None,
+        None,
+        Some(config),
+    );
+    let df = ctx.read_table(Arc::new(provider))?;
+    let predicate = df.parse_sql_expr(predicate)?;
+    let batches = df
+        .filter(predicate)?
+        .select_columns(&[DATA_FILE_PATH_COLUMN])?
+        .distinct()?
+        .collect()
+        .await?;
+    let mut paths = HashSet::new();
+    for batch in batches {
+        let column = cast(batch.column(0), &DataType::Utf8)?;
+        let column = column.as_string::<i32>();
+        paths.extend(column.iter().flatten().map(ToOwned::to_owned));
+    }
+    Ok(paths)
+}
+
+/// Group the files smaller than 75% of the target size into bins of at most the target size,
+/// only bins with more than one file are worth rewriting
+fn bin_pack(mut files: Vec<DataFile>, target_file_size: i64) -> Vec<Vec<DataFile>> {
+    files.retain(|file| *file.file_size_in_bytes() < target_file_size / 4 * 3);
+    files.sort_by_key(|file| Reverse(*file.file_size_in_bytes()));
+    let mut bins: Vec<(i64, Vec<DataFile>)> = Vec::new();
+    for file in files {
+        let size = *file.file_size_in_bytes();
+        match bins
+            .iter_mut()
+            .find(|(total, _)| total + size <= target_file_size)
+        {
+            Some((total, bin)) => {
+                *total += size;
+                bin.push(file);
+            }
+            None => bins.push((size, vec![file])),
+        }
+    }
+    bins.into_iter()
+        .map(|(_, bin)| bin)
+        .filter(|bin| bin.len() > 1)
+        .collect()
+}
+
+/// Read the files, sorted by `sort_order`, and write them into new data files of about the
+/// target size
+async fn write_data_files(
+    ctx: &SessionContext,
+    table: &Table,
+    object_store_url: &ObjectStoreUrl,
+    files: &[DataFile],
+    sort_order: Option<&str>,
+    target_file_size: i64,
+    added: &mut Vec<DataFile>,
+) -> Result<(), DataFusionError> {
+    let mut df = read_data_files(ctx, table, object_store_url, files).await?;
+    if let Some(sort_order) = sort_order {
+        let dialect = ctx.state().config().options().sql_parser.dialect;
+        let dialect = dialect_from_str(dialect)
+            .ok_or_else(|| DataFusionError::Plan(format!("Dialect {dialect} is not supported.")))?;
+        let order_by = Parser::new(dialect.as_ref())
+            .try_with_sql(sort_order)?
+            .parse_comma_separated(Parser::parse_order_by_expr)?;
+        let exprs = order_by
+            .iter()
+            .map(|order_by| {
+                let asc = order_by.options.asc.unwrap_or(true);
+                let nulls_first = order_by.options.nulls_first.unwrap_or(!asc);
+                Ok(df
+                    .parse_sql_expr(&order_by.expr.to_string())?
+                    .sort(asc, nulls_first))
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+        df = df.sort(exprs)?;
+    }
+    let mut stream = df.execute_stream().await?;
+
+    // Every call of the writer produces one file per partition, the rows are split into calls
+    // of about the target size
+    let records: i64 = files.iter().map(|file| *file.record_count()).sum();
+    let bytes: i64 = files.iter().map(|file| *file.file_size_in_bytes()).sum();
+    let rows_per_file =
+        ((target_file_size as f64 * records as f64 / bytes.max(1) as f64).ceil() as usize).max(1);
+    let mut config = ctx.copied_config();
+    config.options_mut().execution.minimum_parallel_output_files = 1;
+    config.options_mut().execution.soft_max_rows_per_output_file = usize::MAX;
+    let task_ctx = Arc::new(TaskContext::from(&ctx.state()).with_session_config(config));
+
+    let mut remainder: Option<RecordBatch> = None;
+    loop {
+        let (mut sender, receiver) = mpsc::channel(1);
+        let input = Box::pin(RecordBatchStreamAdapter::new(stream.schema(), receiver));
+        let feed = async {
+            let mut rows = 0;
+            while rows < rows_per_file {
+                let batch = match remainder.take() {
+                    Some(batch) => batch,
+                    None => match stream.try_next().await? {
+                        Some(batch) => batch,
+                        None => break,
+                    },
+                };
+                let batch = if rows + batch.num_rows() > rows_per_file {
+                    let length = rows_per_file - rows;
+                    remainder = Some(batch.slice(length, batch.num_rows() - length));
+                    batch.slice(0, length)
+                } else {
+                    batch
+                };
+                rows += batch.num_rows();
+                if sender.send(Ok(batch)).await.is_err() {
+                    // The writer failed and returns the error
+                    break;
+                }
+            }
+            sender.close_channel();
+            Ok::<_, DataFusionError>(rows)
+        };
+        let (files, rows) = futures::try_join!(
+            write_parquet_data_files(table, input, &task_ctx, None),
+            feed
+        )?;
+        added.extend(files);
+        if rows < rows_per_file {
+            return Ok(());
+        }
+    }
+}
+
+/// Object store of a table that is registered in the runtime of the session while a rewrite
+/// reads its files
+///
+/// The runtime is shared by all sessions of a server, so every rewrite registers the store under
+/// a url of its own and deregisters it when it is done.
+struct RegisteredObjectStore {
+    runtime_env: Arc<RuntimeEnv>,
+    url: ObjectStoreUrl,
+}
+
+impl RegisteredObjectStore {
+    fn new(
+        ctx: &SessionContext,
+        object_store: Arc<dyn ObjectStore>,
+    ) -> Result<Self, DataFusionError> {
+        let url =
+            ObjectStoreUrl::parse(format!("frostbow://rewrite-{:016x}", rand::random::<u64>()))?;
+        let runtime_env = ctx.runtime_env();
+        runtime_env.register_object_store(url.as_ref(), object_store);
+        Ok(Self { runtime_env, url })
+    }
+}
+
+impl Drop for RegisteredObjectStore {
+    fn drop(&mut self) {
+        self.runtime_env
+            .deregister_object_store(self.url.as_ref())
+            .ok();
+    }
+}
+
+/// Replace the rewritten files with the added files
+///
+/// The commit requires the current snapshot to be unchanged. If only appends were committed
+/// since the files were read, the rewritten files are still live and the commit is retried on
+/// top of the appends.
+async fn commit_rewrite(
+    table: &mut Table,
+    rewritten: &[DataFile],
+    added: Vec<DataFile>,
+) -> Result<(), DataFusionError> {
+    let base = table.metadata().current_snapshot_id;
+    let paths: HashSet<&str> = rewritten
+        .iter()
+        .map(|file| file.file_path().as_str())
+        .collect();
+    for _ in 0..COMMIT_ATTEMPTS {
+        // The catalog validates the commit against the metadata it loaded last
+        *table = load_table(table).await?;
+        if !only_appends_since(table, base) {
+            return Err(DataFusionError::Execution(format!(
+                "Table {} was changed concurrently by an operation other than append.",
+                table.identifier()
+            )));
+        }
+        let (removed, mut files): (Vec<_>, Vec<_>) = live_data_files(table)
+            .await?
+            .into_iter()
+            .partition(|file| paths.contains(file.file_path().as_str()));
+        if removed.len() != paths.len() {
+            return Err(DataFusionError::Execution(format!(
+                "Data files of table {} were removed concurrently.",
+                table.identifier()
+            )));
+        }
+        files.extend(added.iter().cloned());
+
+        let previous = table.metadata().current_snapshot_id;
+        let result = table.new_transaction(None).replace(files).commit().await;
+        let snapshot_id = table.metadata().current_snapshot_id;
+        let current = load_table(table).await?;
+        match result {
+            // A catalog can lose a commit that races with another commit without an error
+            Ok(())
+                if snapshot_id.is_some_and(|id| current.metadata().snapshots.contains_key(&id)) =>
+            {
+                *table = current;
+                return Ok(());
+            }
+            Ok(()) => (),
+            Err(err) if current.metadata().current_snapshot_id == previous => {
+                return Err(DataFusionError::External(Box::new(err)))
+            }
+            // The outcome is unknown, for example after a timeout, the catalog might have applied
+            // the commit
+            Err(_) => {
+                let referenced = referenced_files(&current)
+                    .await
+                    .map_err(|err| DataFusionError::External(Box::new(err)))?;
+                if added.iter().any(|file| {
+                    referenced.contains(Path::from(strip_prefix(file.file_path())).as_ref())
+                }) {
+                    *table = current;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Err(DataFusionError::Execution(format!(
+        "Rewrite of table {} failed after {COMMIT_ATTEMPTS} attempts to commit, other commits were concurrent.",
+        table.identifier()
+    )))
+}
+
+/// Data files of the current snapshot
+///
+/// Tables with delete files are not supported, the rewritten rows would get a newer sequence
+/// number than the delete files that apply to them.
+async fn live_data_files(table: &Table) -> Result<Vec<DataFile>, DataFusionError> {
+    let external = |err: Error| DataFusionError::External(Box::new(err));
+    let manifests = table.manifests(None, None).await.map_err(external)?;
+    let mut files = Vec::new();
+    for entry in table
+        .datafiles(&manifests, None, (None, None))
+        .await
+        .map_err(external)?
+    {
+        let (_, entry) = entry.map_err(external)?;
+        if *entry.status() == Status::Deleted {
+            continue;
+        }
+        if *entry.data_file().content() != Content::Data {
+            return Err(external(Error::NotSupported(
+                "rewriting the data files of tables with delete files".to_owned(),
+            )));
+        }
+        files.push(entry.data_file().clone());
+    }
+    Ok(files)
+}
+
+/// Load the latest metadata of the table from its catalog
+async fn load_table(table: &Table) -> Result<Table, DataFusionError> {
+    match table
+        .catalog()
+        .load_tabular(table.identifier())
+        .await
+        .map_err(|err| DataFusionError::External(Box::new(err)))?
+    {
+        Tabular::Table(table) => Ok(table),
+        tabular => Err(DataFusionError::Plan(format!(
+            "{} is not a table anymore.",
+            tabular.identifier()
+        ))),
+    }
+}
+
+/// Whether all snapshots committed after `base` are appends
+fn only_appends_since(table: &Table, base: Option<i64>) -> bool {
+    let metadata = table.metadata();
+    let mut snapshot_id = metadata.current_snapshot_id;
+    while snapshot_id != base {
+        let Some(snapshot) = snapshot_id.and_then(|id| metadata.snapshots.get(&id)) else {
+            return false;
+        };
+        if snapshot.summary().operation != Operation::Append {
+            return false;
+        }
+        snapshot_id = *snapshot.parent_snapshot_id();
+    }
+    true
+}
+
+/// Whether the snapshot is the current snapshot of the table or one of its ancestors
+fn is_ancestor(table: &Table, snapshot_id: i64) -> bool {
+    let metadata = table.metadata();
+    let mut ancestor = metadata.current_snapshot_id;
+    while let Some(id) = ancestor.filter(|id| *id != snapshot_id) {
+        ancestor = metadata
+            .snapshots
+            .get(&id)
+            .and_then(|snapshot| *snapshot.parent_snapshot_id());
+    }
+    ancestor.is_some()
+}
+
+/// Set the main branch to a snapshot that is an ancestor of the current snapshot
+async fn rollback_to_snapshot(table: &mut Table, snapshot_id: i64) -> Result<Vec<ArrayRef>, Error> {
+    let metadata = table.metadata();
+    let previous = metadata.current_snapshot_id;
+    let retention = metadata
+        .refs
+        .get("main")
+        .map(|reference| reference.retention.clone())
+        .unwrap_or_default();
+    table
+        .new_transaction(None)
+        .set_snapshot_ref((
+            "main".to_owned(),
+            SnapshotReference {
+                snapshot_id,
+                retention,
+            },
+        ))
+        .commit()
+        .await?;
+    Ok(vec![
+        Arc::new(Int64Array::from(vec![previous])),
+        Arc::new(Int64Array::from(vec![snapshot_id])),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use iceberg_rust::spec::manifest::FileFormat;
+
+    use super::*;
+
+    #[cfg(feature = "sql")]
+    use {
+        crate::{FrostbowSessionBuilder, IcebergContext},
+        async_trait::async_trait,
+        datafusion::arrow::datatypes::{Int32Type, Int64Type},
+        iceberg_rust::{
+            catalog::{
+                commit::{CommitTable, CommitView, TableUpdate},
+                create::{CreateMaterializedView, CreateTable, CreateView},
+                identifier::Identifier,
+                namespace::Namespace,
+                Catalog, CatalogList,
+            },
+            materialized_view::MaterializedView,
+            object_store::ObjectStoreBuilder,
+            spec::{
+                schema::Schema as IcebergSchema,
+                snapshot::{SnapshotBuilder, SnapshotRetention},
+                types::{PrimitiveType, StructField, Type},
+            },
+            view::View,
+        },
+        iceberg_sql_catalog::SqlCatalogList,
+        object_store::memory::InMemory,
+    };
+
+    #[cfg(feature = "sql")]
+    fn schema(schema_id: i32, value: &str) -> IcebergSchema {
+        IcebergSchema::builder()
+            .with_schema_id(schema_id)
+            .with_struct_field(StructField::new(
+                1,
+                "id",
+                true,
+                Type::Primitive(PrimitiveType::Long),
+                None,
+            ))
+            .with_struct_field(StructField::new(
+                2,
+                value,
+                false,
+                Type::Primitive(PrimitiveType::Int),
+                None,
+            ))
+            .build()
+            .unwrap()
+    }
+
+    /// Catalog list with the table `iceberg.db.t` in memory
+    #[cfg(feature = "sql")]
+    async fn create_table() -> (Arc<SqlCatalogList>, Arc<dyn Catalog>) {
+        let catalog_list = Arc::new(
+            SqlCatalogList::new(
+                "sqlite://",
+                ObjectStoreBuilder::Memory(Arc::new(InMemory::new())),
+            )
+            .await
+            .unwrap(),
+        );
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        catalog
+            .create_namespace(&Namespace::try_new(&["db".to_owned()]).unwrap(), None)
+            .await
+            .unwrap();
+        Table::builder()
+            .with_name("t")
+            .with_location("/warehouse/db/t")
+            .with_schema(schema(0, "v"))
+            .build(&["db".to_owned()], catalog.clone())
+            .await
+            .unwrap();
+        (catalog_list, catalog)
+    }
+
+    #[cfg(feature = "sql")]
+    async fn load(catalog: &Arc<dyn Catalog>) -> Table {
+        let identifier = Identifier::new(&["db".to_owned()], "t");
+        let Tabular::Table(table) = catalog.clone().load_tabular(&identifier).await.unwrap() else {
+            panic!("t is a table")
+        };
+        table
+    }
+
+    #[cfg(feature = "sql")]
+    async fn collect(ctx: &IcebergContext, sql: &str) -> Vec<RecordBatch> {
+        ctx.sql(sql).await.unwrap().collect().await.unwrap()
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn rewrite_data_files_resolves_renamed_columns() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list.clone())
+            .build()
+            .await
+            .unwrap();
+        for values in ["(1, 10)", "(2, 20)"] {
+            collect(&ctx, &format!("INSERT INTO iceberg.db.t VALUES {values}")).await;
+        }
+
+        // Rename v to w and commit a snapshot with the new schema, like other engines do
+        let identifier = Identifier::new(&["db".to_owned()], "t");
+        let table = load(&catalog).await;
+        let current = table.metadata().current_snapshot(None).unwrap().unwrap();
+        let snapshot = SnapshotBuilder::default()
+            .with_parent_snapshot_id(*current.snapshot_id())
+            .with_sequence_number(*current.sequence_number() + 1)
+            .with_manifest_list(current.manifest_list().clone())
+            .with_summary(current.summary().clone())
+            .with_schema_id(1)
+            .build()
+            .unwrap();
+        catalog
+            .update_table(CommitTable {
+                identifier,
+                requirements: Vec::new(),
+                updates: vec![
+                    TableUpdate::AddSchema {
+                        schema: schema(1, "w"),
+                        last_column_id: Some(2),
+                    },
+                    TableUpdate::SetCurrentSchema { schema_id: 1 },
+                    TableUpdate::SetSnapshotRef {
+                        ref_name: "main".to_owned(),
+                        snapshot_reference: SnapshotReference {
+                            snapshot_id: *snapshot.snapshot_id(),
+                            retention: SnapshotRetention::default(),
+                        },
+                    },
+                    TableUpdate::AddSnapshot { snapshot },
+                ],
+            })
+            .await
+            .unwrap();
+
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        let batches = collect(
+            &ctx,
+            "CALL system.rewrite_data_files(table => 'iceberg.db.t')",
+        )
+        .await;
+        let rewritten = batches[0].column(0).as_primitive::<Int64Type>().value(0);
+        assert_eq!(rewritten, 2);
+
+        let batches = collect(&ctx, "SELECT w FROM iceberg.db.t ORDER BY id").await;
+        let values: Vec<_> = batches
+            .iter()
+            .flat_map(|batch| batch.column(0).as_primitive::<Int32Type>().iter())
+            .collect();
+        assert_eq!(values, vec![Some(10), Some(20)]);
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn rewrite_is_committed_on_top_of_concurrent_appends() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        for values in ["(1, 10)", "(2, 20)"] {
+            collect(&ctx, &format!("INSERT INTO iceberg.db.t VALUES {values}")).await;
+        }
+        let mut table = load(&catalog).await;
+        let rewritten = live_data_files(&table).await.unwrap();
+
+        collect(&ctx, "INSERT INTO iceberg.db.t VALUES (3, 30)").await;
+        // The files are replaced by themselves, the appended file has to stay live
+        commit_rewrite(&mut table, &rewritten, rewritten.clone())
+            .await
+            .unwrap();
+        assert_eq!(live_data_files(&table).await.unwrap().len(), 3);
+        let batches = collect(&ctx, "SELECT count(*) FROM iceberg.db.t").await;
+        assert_eq!(batches[0].column(0).as_primitive::<Int64Type>().value(0), 3);
+
+        let mut table = load(&catalog).await;
+        let rewritten = live_data_files(&table).await.unwrap();
+        collect(
+            &ctx,
+            "CALL system.rewrite_data_files(table => 'iceberg.db.t')",
+        )
+        .await;
+        let err = commit_rewrite(&mut table, &rewritten, rewritten.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("changed concurrently"), "{err}");
+    }
+
+    /// Catalog that applies the commits of tables but fails like on a timeout afterwards
+    #[cfg(feature = "sql")]
+    #[derive(Debug)]
+    struct LostResponseCatalog(Arc<dyn Catalog>);
+
+    #[cfg(feature = "sql")]
+    #[async_trait]
+    impl Catalog for LostResponseCatalog {
+        fn name(&self) -> &str {
+            self.0.name()
+        }
+        async fn create_namespace(
+            &self,
+            namespace: &Namespace,
+            properties: Option<HashMap<String, String>>,
+        ) -> Result<HashMap<String, String>, Error> {
+            self.0.create_namespace(namespace, properties).await
+        }
+        async fn drop_namespace(&self, namespace: &Namespace) -> Result<(), Error> {
+            self.0.drop_namespace(namespace).await
+        }
+        async fn load_namespace(
+            &self,
+            namespace: &Namespace,
+        ) -> Result<HashMap<String, String>, Error> {
+            self.0.load_namespace(namespace).await
+        }
+        async fn update_namespace(
+            &self,
+            namespace: &Namespace,
+            updates: Option<HashMap<String, String>>,
+            removals: Option<Vec<String>>,
+        ) -> Result<(), Error> {
+            self.0.update_namespace(namespace, updates, removals).await
+        }
+        async fn namespace_exists(&self, namespace: &Namespace) -> Result<bool, Error> {
+            self.0.namespace_exists(namespace).await
+        }
+        async fn list_tabulars(&self, namespace: &Namespace) -> Result<Vec<Identifier>, Error> {
+            self.0.list_tabulars(namespace).await
+        }
+        async fn list_namespaces(&self, parent: Option<&str>) -> Result<Vec<Namespace>, Error> {
+            self.0.list_namespaces(parent).await
+        }
+        async fn tabular_exists(&self, identifier: &Identifier) -> Result<bool, Error> {
+            self.0.tabular_exists(identifier).await
+        }
+        async fn drop_table(&self, identifier: &Identifier) -> Result<(), Error> {
+            self.0.drop_table(identifier).await
+        }
+        async fn drop_view(&self, identifier: &Identifier) -> Result<(), Error> {
+            self.0.drop_view(identifier).await
+        }
+        async fn drop_materialized_view(&self, identifier: &Identifier) -> Result<(), Error> {
+            self.0.drop_materialized_view(identifier).await
+        }
+        async fn load_tabular(self: Arc<Self>, identifier: &Identifier) -> Result<Tabular, Error> {
+            // The tables commit through this catalog
+            match self.0.clone().load_tabular(identifier).await? {
+                Tabular::Table(table) => Ok(Tabular::Table(
+                    Table::new(
+                        identifier.clone(),
+                        self.clone(),
+                        table.object_store(),
+                        table.metadata().clone(),
+                    )
+                    .await?,
+                )),
+                tabular => Ok(tabular),
+            }
+        }
+        async fn create_table(
+            self: Arc<Self>,
+            identifier: Identifier,
+            create_table: CreateTable,
+        ) -> Result<Table, Error> {
+            self.0.clone().create_table(identifier, create_table).await
+        }
+        async fn create_view(
+            self: Arc<Self>,
+            identifier: Identifier,
+            create_view: CreateView<Option<()>>,
+        ) -> Result<View, Error> {
+            self.0.clone().create_view(identifier, create_view).await
+        }
+        async fn create_materialized_view(
+            self: Arc<Self>,
+            identifier: Identifier,
+            create_view: CreateMaterializedView,
+        ) -> Result<MaterializedView, Error> {
+            self.0
+                .clone()
+                .create_materialized_view(identifier, create_view)
+                .await
+        }
+        async fn update_table(self: Arc<Self>, commit: CommitTable) -> Result<Table, Error> {
+            self.0.clone().update_table(commit).await?;
+            Err(Error::External(Box::new(std::io::Error::from(
+                std::io::ErrorKind::TimedOut,
+            ))))
+        }
+        async fn update_view(
+            self: Arc<Self>,
+            commit: CommitView<Option<()>>,
+        ) -> Result<View, Error> {
+            self.0.clone().update_view(commit).await
+        }
+        async fn update_materialized_view(
+            self: Arc<Self>,
+            commit: CommitView<Identifier>,
+        ) -> Result<MaterializedView, Error> {
+            self.0.clone().update_materialized_view(commit).await
+        }
+        async fn register_table(
+            self: Arc<Self>,
+            identifier: Identifier,
+            metadata_location: &str,
+        ) -> Result<Table, Error> {
+            self.0
+                .clone()
+                .register_table(identifier, metadata_location)
+                .await
+        }
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn rewrite_keeps_files_of_applied_commit_that_failed() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        for values in ["(1, 10)", "(2, 20)"] {
+            collect(&ctx, &format!("INSERT INTO iceberg.db.t VALUES {values}")).await;
+        }
+
+        let catalog: Arc<dyn Catalog> = Arc::new(LostResponseCatalog(catalog));
+        let mut table = load(&catalog).await;
+        let args = Arguments {
+            procedure: Procedure::RewriteDataFiles,
+            values: HashMap::new(),
+        };
+        let result = rewrite_data_files(&ctx.0, &mut table, &args).await.unwrap();
+        assert_eq!(result[0].as_primitive::<Int64Type>().value(0), 2);
+        assert_eq!(result[1].as_primitive::<Int64Type>().value(0), 1);
+
+        // The added file is live and was not deleted
+        assert_eq!(live_data_files(&table).await.unwrap().len(), 1);
+        let batches = collect(&ctx, "SELECT sum(v) FROM iceberg.db.t").await;
+        assert_eq!(
+            batches[0].column(0).as_primitive::<Int64Type>().value(0),
+            30
+        );
+    }
+
+    #[cfg(feature = "sql")]
+    fn int64_values(batches: &[RecordBatch], column: usize) -> Vec<i64> {
+        batches
+            .iter()
+            .flat_map(|batch| batch.column(column).as_primitive::<Int64Type>().values())
+            .copied()
+            .collect()
+    }
+
+    #[cfg(feature = "sql")]
+    async fn insert_rows(ctx: &IcebergContext, rows: i64) {
+        for id in 1..=rows {
+            collect(
+                ctx,
+                &format!("INSERT INTO iceberg.db.t VALUES ({id}, {id})"),
+            )
+            .await;
+        }
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn remove_orphan_files_keeps_referenced_files() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        insert_rows(&ctx, 2).await;
+
+        let object_store = load(&catalog).await.object_store();
+        let orphan = Path::from("warehouse/db/t/data/orphan.parquet");
+        let statistics = Path::from("warehouse/db/t/metadata/statistics.puffin");
+        object_store.put(&orphan, "orphan".into()).await.unwrap();
+        object_store
+            .put(&statistics, "puffin".into())
+            .await
+            .unwrap();
+        // Statistics files are only referenced by the metadata files
+        object_store
+            .put(
+                &Path::from("warehouse/db/t/metadata/00099-statistics.metadata.json"),
+                r#"{"statistics": [{"statistics-path": "/warehouse/db/t/metadata/statistics.puffin"}]}"#.into(),
+            )
+            .await
+            .unwrap();
+
+        let call = |dry_run: bool| {
+            format!(
+                "CALL system.remove_orphan_files(table => 'iceberg.db.t', older_than => TIMESTAMP '2200-01-01 00:00:00', dry_run => {dry_run})"
+            )
+        };
+        let orphans = |batches: Vec<RecordBatch>| -> Vec<String> {
+            batches
+                .iter()
+                .flat_map(|batch| batch.column(0).as_string::<i32>().iter())
+                .flatten()
+                .map(ToOwned::to_owned)
+                .collect()
+        };
+        let expected = vec!["/warehouse/db/t/data/orphan.parquet".to_owned()];
+
+        assert_eq!(orphans(collect(&ctx, &call(true)).await), expected);
+        object_store.head(&orphan).await.unwrap();
+
+        assert_eq!(orphans(collect(&ctx, &call(false)).await), expected);
+        assert!(object_store.head(&orphan).await.is_err());
+        object_store.head(&statistics).await.unwrap();
+        let batches = collect(&ctx, "SELECT count(*) FROM iceberg.db.t").await;
+        assert_eq!(int64_values(&batches, 0), vec![2]);
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn expire_snapshots_retains_last_snapshots() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        insert_rows(&ctx, 3).await;
+        let table = load(&catalog).await;
+        let current = table.metadata().current_snapshot_id.unwrap();
+
+        let batches = collect(
+            &ctx,
+            "CALL system.expire_snapshots(table => 'iceberg.db.t', older_than => TIMESTAMP '2200-01-01 00:00:00', retain_last => 1)",
+        )
+        .await;
+        let mut expired = int64_values(&batches, 0);
+        expired.sort();
+        let mut ancestors: Vec<_> = table
+            .metadata()
+            .snapshots
+            .keys()
+            .copied()
+            .filter(|id| *id != current)
+            .collect();
+        ancestors.sort();
+        assert_eq!(expired, ancestors);
+
+        let table = load(&catalog).await;
+        assert_eq!(
+            table.metadata().snapshots.keys().collect::<Vec<_>>(),
+            vec![&current]
+        );
+        let batches = collect(&ctx, "SELECT count(*) FROM iceberg.db.t").await;
+        assert_eq!(int64_values(&batches, 0), vec![3]);
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn rollback_to_snapshot_rejects_non_ancestors() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        insert_rows(&ctx, 2).await;
+        let table = load(&catalog).await;
+        let second = table.metadata().current_snapshot_id.unwrap();
+        let first = table.metadata().snapshots[&second]
+            .parent_snapshot_id()
+            .unwrap();
+
+        let call = |snapshot_id: i64| {
+            format!(
+                "CALL system.rollback_to_snapshot(table => 'iceberg.db.t', snapshot_id => {snapshot_id})"
+            )
+        };
+        let batches = collect(&ctx, &call(first)).await;
+        assert_eq!(int64_values(&batches, 0), vec![second]);
+        assert_eq!(int64_values(&batches, 1), vec![first]);
+        let batches = collect(&ctx, "SELECT count(*) FROM iceberg.db.t").await;
+        assert_eq!(int64_values(&batches, 0), vec![1]);
+
+        // The second snapshot is a descendant of the current snapshot now
+        let err = match ctx.sql(&call(second)).await {
+            Ok(df) => df.collect().await.unwrap_err(),
+            Err(err) => err,
+        };
+        assert!(err.to_string().contains("is not an ancestor"), "{err}");
+        assert_eq!(
+            load(&catalog).await.metadata().current_snapshot_id,
+            Some(first)
+        );
+    }
+
+    #[cfg(feature = "sql")]
+    #[tokio::test]
+    async fn rewrite_manifests_counts_manifests() {
+        let (catalog_list, catalog) = create_table().await;
+        let ctx = FrostbowSessionBuilder::new(catalog_list)
+            .build()
+            .await
+            .unwrap();
+        insert_rows(&ctx, 3).await;
+        let manifests = load(&catalog).await.manifests(None, None).await.unwrap();
+
+        let batches = collect(
+            &ctx,
+            "CALL system.rewrite_manifests(table => 'iceberg.db.t')",
+        )
+        .await;
+        assert_eq!(int64_values(&batches, 0), vec![manifests.len() as i64]);
+        assert_eq!(int64_values(&batches, 1), vec![1]);
+        let table = load(&catalog).await;
+        assert_eq!(table.manifests(None, None).await.unwrap().len(), 1);
+        assert_eq!(live_data_files(&table).await.unwrap().len(), 3);
+        let batches = collect(&ctx, "SELECT count(*) FROM iceberg.db.t").await;
+        assert_eq!(int64_values(&batches, 0), vec![3]);
+    }
+
+    fn data_file(path: &str, size: i64) -> DataFile {
+        DataFile::builder()
+            .with_content(Content::Data)
+            .with_file_path(path.to_owned())
+            .with_file_format(FileFormat::Parquet)
+            .with_partition(Struct::from_iter([]))
+            .with_record_count(1)
+            .with_file_size_in_bytes(size)
+            .with_column_sizes(None)
+            .with_value_counts(None)
+            .with_null_value_counts(None)
+            .with_nan_value_counts(None)
+            .with_lower_bounds(None)
+            .with_upper_bounds(None)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn bin_pack_small_files() {
+        let files = [80, 10, 50, 30, 40, 20]
+            .map(|size| data_file(&format!("{size}.parquet"), size))
+            .to_vec();
+        let bins: Vec<Vec<_>> = bin_pack(files, 100)
+            .iter()
+            .map(|bin| bin.iter().map(|file| *file.file_size_in_bytes()).collect())
+            .collect();
+        // Files of at least 75% of the target size are not rewritten
+        assert_eq!(bins, vec![vec![50, 40, 10], vec![30, 20]]);
+
+        // A single small file is not worth rewriting
+        assert!(bin_pack(
+            vec![data_file("a.parquet", 10), data_file("b.parquet", 95)],
+            100
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn orphan_file_location_has_to_be_within_the_table() {
+        let table = "s3://bucket/warehouse/db/orders";
+        assert!(is_within("s3://bucket/warehouse/db/orders", table));
+        assert!(is_within("s3://bucket/warehouse/db/orders/", table));
+        assert!(is_within("s3://bucket/warehouse/db/orders/data", table));
+        assert!(!is_within("s3://bucket/warehouse", table));
+        assert!(!is_within("s3://bucket/warehouse/db/orders_archive", table));
+        assert!(!is_within(
+            "s3://bucket/warehouse/db/orders/../customers",
+            table
+        ));
+        assert!(!is_within("s3://other/warehouse/db/orders", table));
+    }
+}
@@ -0,0 +1,804 @@
+use std::{collections::BTreeMap, fmt::Debug, fs, sync::Arc};
+
+use async_trait::async_trait;
+use iceberg_file_catalog::FileCatalogList;
+use iceberg_rust::{catalog::CatalogList, error::Error};
+use iceberg_s3tables_catalog::S3TablesCatalogList;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    catalog::{CatalogOperation, MultiCatalogList, SupportedCatalogList},
+    credentials::AwsConfigOptions,
+    storage::{StorageRouter, AZURE_SCHEMES},
+    Args,
+};
+
+/// Creates the catalog list for a catalog URL
+///
+/// The object stores of the tables are built by the storage router from the table locations.
+#[async_trait]
+pub trait CatalogFactory: Send + Sync + Debug {
+    async fn create(
+        &self,
+        url: &str,
+        storage: Arc<StorageRouter>,
+    ) -> Result<Arc<dyn CatalogList>, Error>;
+
+    /// Operations that the catalogs of the factory don't implement
+    fn unsupported(&self) -> &'static [CatalogOperation] {
+        &[]
+    }
+}
+
+/// Namespace operations that the SQL and Glue catalogs don't implement
+#[cfg(any(feature = "sql", feature = "glue"))]
+static UNSUPPORTED_NAMESPACE_OPERATIONS: [CatalogOperation; 4] = [
+    CatalogOperation::DropNamespace,
+    CatalogOperation::LoadNamespace,
+    CatalogOperation::UpdateNamespace,
+    CatalogOperation::NamespaceExists,
+];
+
+/// Registry of catalog factories keyed by URL prefix
+///
+/// A catalog URL is resolved by the factory with the longest matching prefix.
+#[derive(Debug)]
+pub struct CatalogRegistry {
+    factories: Vec<(String, Arc<dyn CatalogFactory>)>,
+}
+
+impl CatalogRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self {
+            factories: Vec::new(),
+        }
+    }
+
+    /// Register a factory for all catalog URLs starting with the prefix
+    pub fn register(&mut self, prefix: &str, factory: Arc<dyn CatalogFactory>) -> &mut Self {
+        self.factories.retain(|(x, _)| x != prefix);
+        self.factories.push((prefix.to_owned(), factory));
+        self
+    }
+
+    /// Get the factory for a catalog URL
+    pub fn factory(&self, url: &str) -> Option<&Arc<dyn CatalogFactory>> {
+        self.factories
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, factory)| factory)
+    }
+
+    /// Create the catalog list for a catalog URL
+    ///
+    /// The operations that the catalogs don't implement fail with [`Error::NotSupported`].
+    pub async fn create(
+        &self,
+        url: &str,
+        storage: Arc<StorageRouter>,
+    ) -> Result<Arc<dyn CatalogList>, Error> {
+        let factory = self
+            .factory(url)
+            .ok_or(Error::NotSupported(format!("Catalog URL {url}")))?;
+        let catalog_list = factory.create(url, storage).await?;
+        match factory.unsupported() {
+            [] => Ok(catalog_list),
+            unsupported => Ok(Arc::new(SupportedCatalogList::new(
+                catalog_list,
+                unsupported,
+            ))),
+        }
+    }
+
+    /// Create the catalog list for the catalogs passed as command line arguments
+    ///
+    /// Catalogs without a storage backend of their own use the given storage router.
+    pub async fn catalog_list(
+        &self,
+        args: &Args,
+        storage: Arc<StorageRouter>,
+    ) -> Result<Arc<dyn CatalogList>, Error> {
+        if args.catalog_url.is_none() && args.catalogs.is_empty() {
+            return Err(Error::NotFound("ICEBERG_CATALOG_URL".to_string()));
+        }
+
+        let default_catalog_list = match &args.catalog_url {
+            Some(catalog_url) => Some(self.create(catalog_url, storage.clone()).await?),
+            None => None,
+        };
+
+        let mut catalog_list = MultiCatalogList::new(default_catalog_list);
+
+        for catalog in &args.catalogs {
+            let storage = match &catalog.storage {
+                Some(x) => Arc::new(StorageRouter::new(
+                    Some(x),
+                    &args.storage_options,
+                    &args.aws,
+                )),
+                None => storage.clone(),
+            };
+            tracing::info!("Mounting catalog {}", catalog.name);
+            catalog_list
+                .mount(&catalog.name, self.create(&catalog.url, storage).await?)
+                .await?;
+        }
+
+        Ok(Arc::new(catalog_list))
+    }
+}
+
+impl CatalogRegistry {
+    /// Registry with the catalogs that are bundled with frostbow
+    ///
+    /// The AWS catalogs load their Sdk configuration with the given options, generic REST catalogs
+    /// are authenticated with the REST options.
+    pub fn bundled(aws: &AwsConfigOptions, rest: &RestOptions) -> Self {
+        let mut registry = Self::new();
+        for scheme in ["s3", "s3a", "gs", "gcs", "file"]
+            .into_iter()
+            .chain(AZURE_SCHEMES)
+        {
+            registry.register(&format!("{scheme}://"), Arc::new(FileCatalogFactory));
+        }
+        registry.register("arn:", Arc::new(S3TablesCatalogFactory::new(aws)));
+        #[cfg(feature = "sql")]
+        for prefix in ["sqlite:", "postgres:", "postgresql:", "mysql:", "mariadb:"] {
+            registry.register(prefix, Arc::new(sql::SqlCatalogFactory));
+        }
+        #[cfg(feature = "glue")]
+        registry.register("glue://", Arc::new(glue::GlueCatalogFactory::new(aws)));
+        #[cfg(feature = "rest")]
+        registry
+            .register("http://", Arc::new(rest::RestCatalogFactory::new(rest)))
+            .register("https://", Arc::new(rest::RestCatalogFactory::new(rest)))
+            .register(
+                "https://glue",
+                Arc::new(rest::GlueRestCatalogFactory::new(aws)),
+            )
+            .register(
+                "https://s3tables",
+                Arc::new(rest::S3TablesRestCatalogFactory::new(aws)),
+            );
+        registry
+    }
+}
+
+impl Default for CatalogRegistry {
+    /// Registry with the bundled catalogs and the default AWS and REST configuration
+    fn default() -> Self {
+        Self::bundled(&AwsConfigOptions::default(), &RestOptions::default())
+    }
+}
+
+/// Filesystem catalog for warehouse URLs like `s3://`, `gs://`, `abfss://` or `file://`
+///
+/// The storage backend is selected by the scheme of the warehouse.
+#[derive(Debug)]
+pub struct FileCatalogFactory;
+
+#[async_trait]
+impl CatalogFactory for FileCatalogFactory {
+    async fn create(
+        &self,
+        url: &str,
+        storage: Arc<StorageRouter>,
+    ) -> Result<Arc<dyn CatalogList>, Error> {
+        tracing::info!("Using file catalog with URL: {}", url);
+        Ok(Arc::new(
+            FileCatalogList::new(url, storage.location_builder(url).await?)
+                .await
+                .map_err(Error::from)?,
+        ))
+    }
+
+    fn unsupported(&self) -> &'static [CatalogOperation] {
+        &[
+            CatalogOperation::DropNamespace,
+            CatalogOperation::LoadNamespace,
+            CatalogOperation::UpdateNamespace,
+            CatalogOperation::NamespaceExists,
+            CatalogOperation::DropTable,
+            CatalogOperation::DropView,
+            CatalogOperation::DropMaterializedView,
+            CatalogOperation::RegisterTable,
+        ]
+    }
+}
+
+/// S3Tables catalog for table bucket ARNs
+#[derive(Debug)]
+pub struct S3TablesCatalogFactory {
+    aws: AwsConfigOptions,
+}
+
+impl S3TablesCatalogFactory {
+    pub fn new(aws: &AwsConfigOptions) -> Self {
+        Self { aws: aws.clone() }
+    }
+}
+
+#[async_trait]
+impl CatalogFactory for S3TablesCatalogFactory {
+    async fn create(
+        &self,
+        url: &str,
+        storage: Arc<StorageRouter>,
+    ) -> Result<Arc<dyn CatalogList>, Error> {
+        tracing::info!("Using S3 tables catalog with ARN: {}", url);
+        let config = self.aws.load().await;
+
+        Ok(Arc::new(S3TablesCatalogList::new(
+            &config,
+            url,
+            storage.default_builder("s3").await?,
+        )))
+    }
+
+    fn unsupported(&self) -> &'static [CatalogOperation] {
+        &[
+            CatalogOperation::LoadNamespace,
+            CatalogOperation::UpdateNamespace,
+        ]
+    }
+}
+
+#[cfg(feature = "sql")]
+pub mod sql {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use iceberg_rust::{catalog::CatalogList, error::Error};
+    use iceberg_sql_catalog::SqlCatalogList;
+
+    use super::{CatalogFactory, UNSUPPORTED_NAMESPACE_OPERATIONS};
+    use crate::{
+        catalog::CatalogOperation, sql_catalog::SqlStorageCatalogList, storage::StorageRouter,
+    };
+
+    /// SQL catalog for sqlite, postgres and mysql URLs
+    ///
+    /// Without an explicit storage backend the tables are routed to the storage backend of their
+    /// location, in-memory databases are rejected then.
+    #[derive(Debug)]
+    pub struct SqlCatalogFactory;
+
+    #[async_trait]
+    impl CatalogFactory for SqlCatalogFactory {
+        async fn create(
+            &self,
+            url: &str,
+            storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            tracing::info!("Using SQL catalog with URL: {}", url);
+            match storage.explicit() {
+                Some(explicit) => Ok(Arc::new(
+                    SqlCatalogList::new(url, storage.builder(explicit).await?)
+                        .await
+                        .map_err(Error::from)?,
+                )),
+                None => Ok(Arc::new(SqlStorageCatalogList::new(url, storage).await?)),
+            }
+        }
+
+        fn unsupported(&self) -> &'static [CatalogOperation] {
+            &UNSUPPORTED_NAMESPACE_OPERATIONS
+        }
+    }
+}
+
+#[cfg(feature = "glue")]
+pub mod glue {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use iceberg_glue_catalog::GlueCatalogList;
+    use iceberg_rust::{catalog::CatalogList, error::Error};
+
+    use super::{CatalogFactory, UNSUPPORTED_NAMESPACE_OPERATIONS};
+    use crate::{catalog::CatalogOperation, credentials::AwsConfigOptions, storage::StorageRouter};
+
+    /// Native Glue catalog for `glue://<name>` URLs
+    #[derive(Debug)]
+    pub struct GlueCatalogFactory {
+        aws: AwsConfigOptions,
+    }
+
+    impl GlueCatalogFactory {
+        pub fn new(aws: &AwsConfigOptions) -> Self {
+            Self { aws: aws.clone() }
+        }
+    }
+
+    #[async_trait]
+    impl CatalogFactory for GlueCatalogFactory {
+        async fn create(
+            &self,
+            url: &str,
+            storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            let name = url.trim_start_matches("glue://").trim_end_matches('/');
+            let name = if name.is_empty() { "glue" } else { name };
+
+            tracing::info!("Using Glue catalog {}", name);
+            let config = self.aws.load().await;
+
+            Ok(Arc::new(GlueCatalogList::new(
+                name,
+                &config,
+                storage.default_builder("s3").await?,
+            )))
+        }
+
+        fn unsupported(&self) -> &'static [CatalogOperation] {
+            &UNSUPPORTED_NAMESPACE_OPERATIONS
+        }
+    }
+}
+
+/// Options of the Iceberg REST catalogs of [`rest::RestCatalogFactory`]
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "REST catalog")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RestOptions {
+    /// Warehouse requested from the config endpoint of the catalog
+    #[clap(id = "warehouse", long = "warehouse")]
+    pub warehouse: Option<String>,
+    /// Client id for the OAuth2 client credentials flow
+    #[clap(id = "rest-client-id", long = "rest-client-id")]
+    pub client_id: Option<String>,
+    /// Client secret for the OAuth2 client credentials flow
+    #[clap(id = "rest-client-secret", long = "rest-client-secret")]
+    pub client_secret: Option<String>,
+    /// Token endpoint of the OAuth2 server, defaults to the `/v1/oauth/tokens` endpoint of the catalog
+    #[clap(id = "rest-oauth2-server-uri", long = "rest-oauth2-server-uri")]
+    pub oauth2_server_uri: Option<String>,
+    /// Scope requested for the OAuth2 token
+    #[clap(id = "rest-scope", long = "rest-scope")]
+    pub scope: Option<String>,
+    /// Audience requested for the OAuth2 token
+    #[clap(id = "rest-audience", long = "rest-audience")]
+    pub audience: Option<String>,
+    /// Static bearer token
+    #[clap(
+        id = "rest-token",
+        long = "rest-token",
+        env = "ICEBERG_REST_TOKEN",
+        hide_env_values = true
+    )]
+    pub token: Option<String>,
+    /// File containing a static bearer token
+    #[clap(id = "rest-token-file", long = "rest-token-file")]
+    pub token_file: Option<String>,
+    /// Additional HTTP header sent with every request. Can be repeated.
+    #[clap(id = "rest-header", long = "rest-header", value_name = "NAME=VALUE", value_parser = parse_header)]
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub headers: Vec<(String, String)>,
+}
+
+impl RestOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: RestOptions) -> Self {
+        Self {
+            warehouse: self.warehouse.or(other.warehouse),
+            client_id: self.client_id.or(other.client_id),
+            client_secret: self.client_secret.or(other.client_secret),
+            oauth2_server_uri: self.oauth2_server_uri.or(other.oauth2_server_uri),
+            scope: self.scope.or(other.scope),
+            audience: self.audience.or(other.audience),
+            token: self.token.or(other.token),
+            token_file: self.token_file.or(other.token_file),
+            headers: if self.headers.is_empty() {
+                other.headers
+            } else {
+                self.headers
+            },
+        }
+    }
+
+    /// Static bearer token, read from the token file if it is not set directly
+    pub fn bearer_token(&self) -> Result<Option<String>, Error> {
+        match (&self.token, &self.token_file) {
+            (Some(token), _) => Ok(Some(token.clone())),
+            (None, Some(path)) => Ok(Some(fs::read_to_string(path)?.trim().to_owned())),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or(format!("Header {s} is not of the form NAME=VALUE."))?;
+    Ok((name.trim().to_owned(), value.trim().to_owned()))
+}
+
+fn deserialize_headers<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .collect())
+}
+
+#[cfg(feature = "rest")]
+pub mod rest {
+    use std::{collections::HashMap, sync::Arc};
+
+    use async_trait::async_trait;
+    use aws_config::SdkConfig;
+    use iceberg_rest_catalog::{
+        apis::configuration::{AWSv4Key, Configuration, ConfigurationBuilder},
+        catalog::{RestCatalogList, RestNoPrefixCatalogList},
+        configuration_rewriter::ConfigurationRewriter,
+        oauth2_client_credentials::OAuth2ClientCredentials,
+    };
+    use iceberg_rust::{catalog::CatalogList, error::Error};
+    use object_store::CredentialProvider;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use secrecy::SecretString;
+
+    use super::{CatalogFactory, RestOptions};
+    use crate::{
+        credentials::{AwsConfigOptions, AwsCredentialProvider},
+        rest::{catalog_properties, RestConfigCatalogList},
+        storage::StorageRouter,
+    };
+
+    /// Iceberg REST catalog
+    ///
+    /// Requests are authenticated with the OAuth2 client credentials flow if a client id and
+    /// secret are given, with a static bearer token otherwise.
+    #[derive(Debug)]
+    pub struct RestCatalogFactory {
+        options: RestOptions,
+    }
+
+    impl RestCatalogFactory {
+        pub fn new(options: &RestOptions) -> Self {
+            Self {
+                options: options.clone(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CatalogFactory for RestCatalogFactory {
+        async fn create(
+            &self,
+            url: &str,
+            storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            tracing::info!("Using REST catalog with URL: {}", url);
+            let url = url.trim_end_matches('/');
+
+            let mut headers = HeaderMap::new();
+            for (name, value) in &self.options.headers {
+                headers.insert(
+                    HeaderName::try_from(name.as_str())
+                        .map_err(|err| Error::InvalidFormat(err.to_string()))?,
+                    HeaderValue::try_from(value.as_str())
+                        .map_err(|err| Error::InvalidFormat(err.to_string()))?,
+                );
+            }
+            let client = reqwest::Client::builder()
+                .default_headers(headers)
+                .build()
+                .map_err(|err| Error::External(Box::new(err)))?;
+
+            let mut builder = ConfigurationBuilder::default();
+            builder.base_path(url.to_owned()).client(client);
+
+            let rewriter: Option<Arc<dyn ConfigurationRewriter>> =
+                match (&self.options.client_id, &self.options.client_secret) {
+                    (Some(client_id), Some(client_secret)) => {
+                        let server_uri = self
+                            .options
+                            .oauth2_server_uri
+                            .clone()
+                            .unwrap_or_else(|| format!("{url}/v1/oauth/tokens"));
+                        tracing::info!("Using OAuth2 token endpoint {}", server_uri);
+                        Some(Arc::new(OAuth2ClientCredentials::new(
+                            server_uri,
+                            client_id.clone(),
+                            client_secret.clone(),
+                            self.options.audience.clone(),
+                            self.options.scope.clone(),
+                        )))
+                    }
+                    (None, None) => {
+                        if let Some(token) = self.options.bearer_token()? {
+                            builder.bearer_access_token(token);
+                        }
+                        None
+                    }
+                    (Some(_), None) => {
+                        return Err(Error::NotFound("REST client secret".to_owned()))
+                    }
+                    (None, Some(_)) => return Err(Error::NotFound("REST client id".to_owned())),
+                };
+
+            let configuration = builder
+                .build()
+                .map_err(|err| Error::InvalidFormat(err.to_string()))?;
+
+            let warehouse = self.options.warehouse.as_deref();
+            let properties = match catalog_properties(&configuration, &rewriter, warehouse).await {
+                Ok(properties) => properties,
+                // Catalogs without a config endpoint are still usable without a warehouse
+                Err(err) if warehouse.is_none() => {
+                    tracing::warn!(
+                        "Failed to get the configuration of the REST catalog: {}",
+                        err
+                    );
+                    HashMap::new()
+                }
+                Err(err) => return Err(err),
+            };
+
+            let name = warehouse
+                .map(ToOwned::to_owned)
+                .or_else(|| properties.get("prefix").cloned());
+            if let Some(name) = &name {
+                tracing::info!("Using REST catalog {}", name);
+            }
+            Ok(Arc::new(RestConfigCatalogList::new(
+                name.as_deref(),
+                properties,
+                configuration,
+                rewriter,
+                storage,
+            )))
+        }
+    }
+
+    /// Iceberg REST endpoint of the Glue catalog
+    #[derive(Debug)]
+    pub struct GlueRestCatalogFactory {
+        aws: AwsConfigOptions,
+    }
+
+    impl GlueRestCatalogFactory {
+        pub fn new(aws: &AwsConfigOptions) -> Self {
+            Self { aws: aws.clone() }
+        }
+    }
+
+    #[async_trait]
+    impl CatalogFactory for GlueRestCatalogFactory {
+        async fn create(
+            &self,
+            url: &str,
+            storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            tracing::info!("Using Glue catalog with URL: {}", url);
+            let config = self.aws.load().await;
+
+            let (configuration, rewriter) = aws_configuration(url, "https://glue", &config).await?;
+
+            Ok(Arc::new(RestNoPrefixCatalogList::new(
+                "iceberg",
+                configuration,
+                Some(rewriter),
+                Some(storage.default_builder("s3").await?),
+                false,
+            )))
+        }
+    }
+
+    /// Iceberg REST endpoint of S3Tables
+    #[derive(Debug)]
+    pub struct S3TablesRestCatalogFactory {
+        aws: AwsConfigOptions,
+    }
+
+    impl S3TablesRestCatalogFactory {
+        pub fn new(aws: &AwsConfigOptions) -> Self {
+            Self { aws: aws.clone() }
+        }
+    }
+
+    #[async_trait]
+    impl CatalogFactory for S3TablesRestCatalogFactory {
+        async fn create(
+            &self,
+            url: &str,
+            storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            tracing::info!("Using S3 tables REST catalog with URL: {}", url);
+            let config = self.aws.load().await;
+
+            let (configuration, rewriter) =
+                aws_configuration(url, "https://s3tables", &config).await?;
+
+            Ok(Arc::new(RestCatalogList::new(
+                configuration,
+                Some(rewriter),
+                Some(storage.default_builder("s3").await?),
+                false,
+            )))
+        }
+    }
+
+    /// REST configuration signed with AWS SigV4
+    ///
+    /// A bare `https://glue` or `https://s3tables` URL is completed with the regional endpoint.
+    async fn aws_configuration(
+        url: &str,
+        service_url: &str,
+        config: &SdkConfig,
+    ) -> Result<(Configuration, Arc<dyn ConfigurationRewriter>), Error> {
+        let region = config
+            .region()
+            .ok_or(Error::InvalidFormat("Region missing.".to_owned()))?
+            .to_string();
+
+        let mut url = url.to_owned();
+        if url == service_url {
+            url.push_str(&format!(".{}.amazonaws.com/iceberg", &region));
+        }
+
+        config
+            .credentials_provider()
+            .ok_or(Error::NotFound("Credentials provider".to_owned()))?;
+
+        let configuration = ConfigurationBuilder::default()
+            .base_path(url)
+            .build()
+            .map_err(|err| Error::InvalidFormat(err.to_string()))?;
+
+        let rewriter = SigV4Rewriter {
+            credentials: AwsCredentialProvider::new(config),
+            region,
+            service: service_url.trim_start_matches("https://").to_owned(),
+        };
+
+        Ok((configuration, Arc::new(rewriter)))
+    }
+
+    /// Signs the requests of the REST catalog with the current AWS credentials
+    #[derive(Debug)]
+    struct SigV4Rewriter {
+        credentials: AwsCredentialProvider,
+        region: String,
+        service: String,
+    }
+
+    #[async_trait]
+    impl ConfigurationRewriter for SigV4Rewriter {
+        async fn rewrite_configuration(
+            &self,
+            mut configuration: Configuration,
+        ) -> Result<Configuration, Error> {
+            let credentials = self.credentials.get_credential().await?;
+
+            configuration.aws_v4_key = Some(AWSv4Key {
+                access_key: credentials.key_id.clone(),
+                secret_key: SecretString::from(credentials.secret_key.as_str()),
+                session_token: credentials.token.as_deref().map(SecretString::from),
+                region: self.region.clone(),
+                service: self.service.clone(),
+            });
+
+            Ok(configuration)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "rest")]
+    use {
+        axum::{
+            extract::State,
+            http::{header, HeaderMap},
+            routing::{get, post},
+            Form, Json, Router,
+        },
+        serde_json::{json, Value},
+        std::{collections::HashMap, sync::Mutex},
+        tokio::net::TcpListener,
+    };
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct NamedFactory(&'static str);
+
+    #[async_trait]
+    impl CatalogFactory for NamedFactory {
+        async fn create(
+            &self,
+            _url: &str,
+            _storage: Arc<StorageRouter>,
+        ) -> Result<Arc<dyn CatalogList>, Error> {
+            Err(Error::NotSupported(self.0.to_owned()))
+        }
+    }
+
+    #[test]
+    fn factory_of_longest_prefix() {
+        let mut registry = CatalogRegistry::new();
+        registry
+            .register("https://", Arc::new(NamedFactory("rest")))
+            .register("https://glue", Arc::new(NamedFactory("glue")))
+            .register("https://glue.eu", Arc::new(NamedFactory("glue-eu")))
+            .register("sqlite:", Arc::new(NamedFactory("sql")))
+            .register("sqlite:", Arc::new(NamedFactory("sqlite")));
+        let factory = |url: &str| registry.factory(url).map(|factory| format!("{factory:?}"));
+        let named = |name: &'static str| Some(format!("{:?}", NamedFactory(name)));
+
+        assert_eq!(factory("https://catalog.example.com"), named("rest"));
+        assert_eq!(
+            factory("https://glue.us-east-1.amazonaws.com/iceberg"),
+            named("glue")
+        );
+        assert_eq!(
+            factory("https://glue.eu-west-1.amazonaws.com/iceberg"),
+            named("glue-eu")
+        );
+        assert_eq!(factory("sqlite://catalog.db"), named("sqlite"));
+        assert_eq!(factory("http://catalog.example.com"), None);
+    }
+
+    #[cfg(feature = "rest")]
+    #[tokio::test]
+    async fn rest_catalog_authenticates_with_client_credentials() {
+        type Requests = Arc<Mutex<Vec<String>>>;
+        async fn token(
+            State(requests): State<Requests>,
+            Form(form): Form<HashMap<String, String>>,
+        ) -> Json<Value> {
+            requests.lock().unwrap().push(format!(
+                "token {} {} {} {}",
+                form["grant_type"], form["client_id"], form["client_secret"], form["scope"]
+            ));
+            Json(json!({"access_token": "t0ken", "token_type": "bearer", "expires_in": 3600}))
+        }
+        async fn config(State(requests): State<Requests>, headers: HeaderMap) -> Json<Value> {
+            let authorization = headers[header::AUTHORIZATION].to_str().unwrap();
+            requests
+                .lock()
+                .unwrap()
+                .push(format!("config {authorization}"));
+            Json(json!({"defaults": {}, "overrides": {"prefix": "lake"}}))
+        }
+
+        let requests = Requests::default();
+        let app = Router::new()
+            .route("/v1/oauth/tokens", post(token))
+            .route("/v1/config", get(config))
+            .with_state(requests.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let options = RestOptions {
+            client_id: Some("frostbow".to_owned()),
+            client_secret: Some("s3cret".to_owned()),
+            scope: Some("catalog".to_owned()),
+            ..Default::default()
+        };
+        let storage = Arc::new(StorageRouter::new(
+            None,
+            &Default::default(),
+            &Default::default(),
+        ));
+        let catalog_list = rest::RestCatalogFactory::new(&options)
+            .create(&url, storage)
+            .await
+            .unwrap();
+
+        assert_eq!(catalog_list.list_catalogs().await, vec!["lake".to_owned()]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "token client_credentials frostbow s3cret catalog".to_owned(),
+                "config Bearer t0ken".to_owned()
+            ]
+        );
+    }
+}
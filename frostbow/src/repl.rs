@@ -0,0 +1,235 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use datafusion::{
+    config::SqlParserOptions,
+    sql::{parser::DFParser, sqlparser::dialect::dialect_from_str},
+};
+use datafusion_cli::{
+    cli_context::CliSessionContext,
+    command::{Command, OutputFormat},
+    helper::CliHelper,
+    print_options::PrintOptions,
+};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::{CmdKind, Highlighter},
+    hint::Hinter,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Editor, Helper,
+};
+use tokio::signal;
+
+use crate::{
+    script::exec_sql,
+    sql::{rewrite_table_references, split_statements},
+};
+
+/// Run the interactive shell
+///
+/// Works like the REPL of `datafusion-cli`, but validates and executes the statements with the
+/// metadata tables, `FOR ... AS OF` clauses and `CALL` statements of frostbow. The REPL of
+/// `datafusion-cli` parses the input itself and has no hook for them, so changes to its
+/// `exec_from_repl` have to be followed here.
+pub async fn exec_from_repl(
+    ctx: &dyn CliSessionContext,
+    print_options: &mut PrintOptions,
+) -> rustyline::Result<()> {
+    let mut rl = Editor::new()?;
+    rl.set_helper(Some(FrostbowHelper::new(
+        sql_parser_options(ctx),
+        print_options.color,
+    )));
+    let history = history_file();
+    if let Some(history) = &history {
+        rl.load_history(history).ok();
+    }
+
+    loop {
+        match rl.readline("> ") {
+            Ok(line) if line.starts_with('\\') => {
+                rl.add_history_entry(line.trim_end())?;
+                let command = line.split_whitespace().collect::<Vec<_>>().join(" ");
+                match command[1..].parse::<Command>() {
+                    Ok(Command::Quit) => break,
+                    Ok(Command::OutputFormat(Some(subcommand))) => {
+                        match subcommand.parse::<OutputFormat>() {
+                            Ok(command) => {
+                                if let Err(e) = command.execute(print_options).await {
+                                    eprintln!("{e}")
+                                }
+                            }
+                            Err(_) => eprintln!(
+                                "'\\{}' is not a valid command, you can use '\\?' to see all commands",
+                                &line[1..]
+                            ),
+                        }
+                    }
+                    Ok(Command::OutputFormat(None)) => {
+                        println!("Output format is {:?}.", print_options.format)
+                    }
+                    Ok(cmd) => {
+                        if let Err(e) = cmd.execute(ctx, print_options).await {
+                            eprintln!("{e}")
+                        }
+                    }
+                    Err(_) => eprintln!(
+                        "'\\{}' is not a valid command, you can use '\\?' to see all commands",
+                        &line[1..]
+                    ),
+                }
+            }
+            Ok(line) => {
+                let options = sql_parser_options(ctx);
+                for sql in split_statements(&line, &options) {
+                    rl.add_history_entry(&sql)?;
+                    let options = sql_parser_options(ctx);
+                    tokio::select! {
+                        res = exec_sql(ctx, &sql, &options, print_options) => {
+                            if let Err(err) = res {
+                                eprintln!("{err}")
+                            }
+                        },
+                        _ = signal::ctrl_c() => {
+                            // The remaining statements of the line are abandoned
+                            println!("^C");
+                            break
+                        },
+                    }
+                    // The dialect might have changed
+                    rl.helper_mut()
+                        .unwrap()
+                        .set_options(sql_parser_options(ctx));
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                println!("^C");
+                rl.helper().unwrap().inner.reset_hint();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                println!("\\q");
+                break;
+            }
+            Err(err) => {
+                eprintln!("Unknown error happened {err:?}");
+                break;
+            }
+        }
+    }
+
+    match history {
+        Some(history) => {
+            if let Some(dir) = history.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            rl.save_history(&history)
+        }
+        None => Ok(()),
+    }
+}
+
+/// History of the shell in the data directory of the user, like `~/.local/share/frostbow/history`
+fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("frostbow").join("history"))
+}
+
+fn sql_parser_options(ctx: &dyn CliSessionContext) -> SqlParserOptions {
+    ctx.task_ctx().session_config().options().sql_parser.clone()
+}
+
+/// Editor helper of `datafusion-cli` that accepts the table references of frostbow
+struct FrostbowHelper {
+    inner: CliHelper,
+    options: SqlParserOptions,
+}
+
+impl FrostbowHelper {
+    fn new(options: SqlParserOptions, color: bool) -> Self {
+        Self {
+            inner: CliHelper::new(&options.dialect, color),
+            options,
+        }
+    }
+
+    fn set_options(&mut self, options: SqlParserOptions) {
+        self.inner.set_dialect(&options.dialect);
+        self.options = options;
+    }
+
+    fn validate_input(&self, input: &str) -> ValidationResult {
+        let Some(sql) = input.strip_suffix(';') else {
+            return if input.starts_with('\\') {
+                ValidationResult::Valid(None)
+            } else {
+                ValidationResult::Incomplete
+            };
+        };
+        let Some(dialect) = dialect_from_str(self.options.dialect) else {
+            return ValidationResult::Invalid(Some(format!(
+                "  🤔 Invalid dialect: {}",
+                self.options.dialect
+            )));
+        };
+        for sql in split_statements(sql, &self.options) {
+            let statements = rewrite_table_references(&sql, &self.options)
+                .and_then(|sql| DFParser::parse_sql_with_dialect(&sql, dialect.as_ref()));
+            match statements {
+                Ok(statements) if statements.is_empty() => {
+                    return ValidationResult::Invalid(Some(
+                        "  🤔 You entered an empty statement".to_owned(),
+                    ))
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    return ValidationResult::Invalid(Some(format!(
+                        "  🤔 Invalid statement: {}",
+                        err.strip_backtrace()
+                    )))
+                }
+            }
+        }
+        ValidationResult::Valid(None)
+    }
+}
+
+impl Highlighter for FrostbowHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.inner.highlight(line, pos)
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize, kind: CmdKind) -> bool {
+        self.inner.highlight_char(line, pos, kind)
+    }
+}
+
+impl Hinter for FrostbowHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.inner.hint(line, pos, ctx)
+    }
+}
+
+impl Completer for FrostbowHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> Result<(usize, Vec<Pair>), ReadlineError> {
+        self.inner.complete(line, pos, ctx)
+    }
+}
+
+impl Validator for FrostbowHelper {
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        let result = self.validate_input(ctx.input().trim_end());
+        self.inner.reset_hint();
+        Ok(result)
+    }
+}
+
+impl Helper for FrostbowHelper {}
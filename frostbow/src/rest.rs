@@ -0,0 +1,784 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use iceberg_rest_catalog::{
+    apis::{self, catalog_api_api, configuration::Configuration, configuration_api_api},
+    catalog::RestCatalog,
+    configuration_rewriter::ConfigurationRewriter,
+    models::{self, LoadTableResult},
+};
+use iceberg_rust::{
+    catalog::{
+        commit::{CommitTable, CommitView},
+        create::{CreateMaterializedView, CreateTable, CreateView},
+        identifier::Identifier,
+        namespace::Namespace,
+        tabular::Tabular,
+        Catalog, CatalogList,
+    },
+    error::Error,
+    materialized_view::MaterializedView,
+    object_store::parse::object_store_from_config,
+    table::Table,
+    view::View,
+};
+use object_store::{
+    aws::{AmazonS3Builder, AwsCredential},
+    CredentialProvider, Error as ObjectStoreError, ObjectStore,
+};
+use url::Url;
+
+use crate::{
+    credentials::{CredentialCache, FetchCredential},
+    storage::StorageRouter,
+};
+
+/// Value of the `X-Iceberg-Access-Delegation` header to request vended credentials
+static ACCESS_DELEGATION: &str = "vended-credentials";
+
+/// Object stores of the loaded tables of a catalog
+type ObjectStoreCache = Arc<RwLock<HashMap<Identifier, Arc<dyn ObjectStore>>>>;
+
+/// Keys of the table config that contain storage credentials
+static CREDENTIAL_KEYS: [&str; 4] = [
+    "s3.access-key-id",
+    "gcs.oauth2.token",
+    "gcs.credentials-json",
+    "azure.access-key",
+];
+
+/// Catalog properties from the config endpoint of the catalog
+///
+/// The defaults of the server are applied first, then the warehouse requested by the client and
+/// finally the overrides of the server.
+pub async fn catalog_properties(
+    configuration: &Configuration,
+    rewriter: &Option<Arc<dyn ConfigurationRewriter>>,
+    warehouse: Option<&str>,
+) -> Result<HashMap<String, String>, Error> {
+    let configuration = match rewriter {
+        Some(rewriter) => {
+            rewriter
+                .rewrite_configuration(configuration.clone())
+                .await?
+        }
+        None => configuration.clone(),
+    };
+    let config = configuration_api_api::get_config(&configuration, warehouse).await?;
+
+    let mut properties = config.defaults;
+    if let Some(warehouse) = warehouse {
+        properties.insert("warehouse".to_owned(), warehouse.to_owned());
+    }
+    properties.extend(config.overrides);
+    Ok(properties)
+}
+
+/// REST catalogs that use the prefix and properties negotiated with the config endpoint
+///
+/// If the catalog list has a name, it provides a single catalog with the negotiated prefix.
+/// Otherwise the name of each catalog is used as its prefix. The object stores of the loaded
+/// tables are shared by all instances of a catalog.
+#[derive(Debug)]
+pub struct RestConfigCatalogList {
+    name: Option<String>,
+    prefix: Option<String>,
+    properties: Arc<HashMap<String, String>>,
+    configuration: Configuration,
+    rewriter: Option<Arc<dyn ConfigurationRewriter>>,
+    storage: Arc<StorageRouter>,
+    caches: RwLock<HashMap<String, ObjectStoreCache>>,
+}
+
+impl RestConfigCatalogList {
+    pub fn new(
+        name: Option<&str>,
+        properties: HashMap<String, String>,
+        configuration: Configuration,
+        rewriter: Option<Arc<dyn ConfigurationRewriter>>,
+        storage: Arc<StorageRouter>,
+    ) -> Self {
+        Self {
+            name: name.map(ToOwned::to_owned),
+            prefix: properties.get("prefix").cloned(),
+            properties: Arc::new(properties),
+            configuration,
+            rewriter,
+            storage,
+            caches: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl CatalogList for RestConfigCatalogList {
+    fn catalog(&self, name: &str) -> Option<Arc<dyn Catalog>> {
+        let prefix = match &self.name {
+            Some(x) if x == name => self.prefix.clone(),
+            Some(_) => return None,
+            None => Some(name.to_owned()),
+        };
+        let client = Arc::new(RestClient {
+            prefix,
+            configuration: self.configuration.clone(),
+            rewriter: self.rewriter.clone(),
+        });
+        let cache = self
+            .caches
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(name.to_owned())
+            .or_default()
+            .clone();
+        Some(Arc::new(RestConfigCatalog {
+            name: name.to_owned(),
+            properties: self.properties.clone(),
+            storage: self.storage.clone(),
+            cache,
+            inner: Arc::new(RestCatalog::new(
+                client.prefix.as_deref(),
+                self.configuration.clone(),
+                self.rewriter.clone(),
+                None,
+                false,
+            )),
+            client,
+        }))
+    }
+
+    async fn list_catalogs(&self) -> Vec<String> {
+        self.name.iter().cloned().collect()
+    }
+}
+
+/// Connection to the REST catalog
+#[derive(Debug)]
+struct RestClient {
+    prefix: Option<String>,
+    configuration: Configuration,
+    rewriter: Option<Arc<dyn ConfigurationRewriter>>,
+}
+
+impl RestClient {
+    async fn configuration(&self) -> Result<Configuration, Error> {
+        match &self.rewriter {
+            Some(rewriter) => {
+                rewriter
+                    .rewrite_configuration(self.configuration.clone())
+                    .await
+            }
+            None => Ok(self.configuration.clone()),
+        }
+    }
+
+    async fn load_table(
+        &self,
+        identifier: &Identifier,
+    ) -> Result<LoadTableResult, apis::Error<catalog_api_api::LoadTableError>> {
+        let configuration = self
+            .configuration()
+            .await
+            .map_err(|err| apis::Error::Io(std::io::Error::other(err)))?;
+        catalog_api_api::load_table(
+            &configuration,
+            self.prefix.as_deref(),
+            &identifier.namespace().to_string(),
+            identifier.name(),
+            Some(ACCESS_DELEGATION),
+            None,
+        )
+        .await
+    }
+}
+
+/// REST catalog with vended storage credentials
+///
+/// Tables are loaded with the storage credentials vended by the catalog, S3 credentials are
+/// reloaded from the catalog before they expire, GCS and Azure credentials are not. Without vended
+/// credentials the storage backend is selected by the scheme of the table location. The
+/// `table-default.*` and `table-override.*` catalog properties are applied to the properties of new
+/// tables. Namespaces and views are handled by the inner catalog.
+#[derive(Debug)]
+struct RestConfigCatalog {
+    name: String,
+    client: Arc<RestClient>,
+    properties: Arc<HashMap<String, String>>,
+    storage: Arc<StorageRouter>,
+    cache: ObjectStoreCache,
+    inner: Arc<RestCatalog>,
+}
+
+impl RestConfigCatalog {
+    fn table_properties<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (String, String)> + 'a {
+        self.properties.iter().filter_map(move |(key, value)| {
+            key.strip_prefix(prefix)
+                .map(|key| (key.to_owned(), value.clone()))
+        })
+    }
+
+    /// Object store of a table, built from the vended credentials if there are any
+    async fn get_object_store(
+        &self,
+        identifier: &Identifier,
+        response: &LoadTableResult,
+    ) -> Result<Arc<dyn ObjectStore>, Error> {
+        let location = &response.metadata.location;
+        let config = storage_config(response);
+
+        if config.contains_key("s3.access-key-id") {
+            let credentials =
+                VendedCredentialProvider::new(self.client.clone(), identifier.clone(), &config)?;
+            let mut builder = AmazonS3Builder::new()
+                .with_url(location)
+                .with_credentials(Arc::new(credentials));
+            for (key, value) in &config {
+                builder = match key.as_str() {
+                    "client.region" | "s3.region" => builder.with_region(value),
+                    "s3.endpoint" => builder
+                        .with_endpoint(value)
+                        .with_allow_http(value.starts_with("http://")),
+                    "s3.path-style-access" => {
+                        builder.with_virtual_hosted_style_request(value != "true")
+                    }
+                    _ => builder,
+                };
+            }
+            Ok(Arc::new(builder.build()?))
+        } else if CREDENTIAL_KEYS.iter().any(|key| config.contains_key(*key)) {
+            // Only vended S3 credentials are reloaded. GCS and Azure credentials are used as
+            // vended, the table has to be loaded again once they expired.
+            object_store_from_config(Url::parse(location)?, config)
+        } else {
+            self.storage.object_store(location).await
+        }
+    }
+
+    async fn table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        response: LoadTableResult,
+    ) -> Result<Table, Error> {
+        let object_store = self.get_object_store(&identifier, &response).await?;
+
+        self.cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(identifier.clone(), object_store.clone());
+
+        Table::new(identifier, self, object_store, response.metadata).await
+    }
+}
+
+#[async_trait]
+impl Catalog for RestConfigCatalog {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn create_namespace(
+        &self,
+        namespace: &Namespace,
+        properties: Option<HashMap<String, String>>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.inner.create_namespace(namespace, properties).await
+    }
+    async fn drop_namespace(&self, namespace: &Namespace) -> Result<(), Error> {
+        self.inner.drop_namespace(namespace).await
+    }
+    async fn load_namespace(
+        &self,
+        namespace: &Namespace,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.inner.load_namespace(namespace).await
+    }
+    async fn update_namespace(
+        &self,
+        namespace: &Namespace,
+        updates: Option<HashMap<String, String>>,
+        removals: Option<Vec<String>>,
+    ) -> Result<(), Error> {
+        self.inner
+            .update_namespace(namespace, updates, removals)
+            .await
+    }
+    async fn namespace_exists(&self, namespace: &Namespace) -> Result<bool, Error> {
+        self.inner.namespace_exists(namespace).await
+    }
+    async fn list_tabulars(&self, namespace: &Namespace) -> Result<Vec<Identifier>, Error> {
+        self.inner.list_tabulars(namespace).await
+    }
+    async fn list_namespaces(&self, parent: Option<&str>) -> Result<Vec<Namespace>, Error> {
+        self.inner.list_namespaces(parent).await
+    }
+    async fn tabular_exists(&self, identifier: &Identifier) -> Result<bool, Error> {
+        self.inner.tabular_exists(identifier).await
+    }
+    async fn drop_table(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(identifier);
+        self.inner.drop_table(identifier).await
+    }
+    async fn drop_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.inner.drop_view(identifier).await
+    }
+    async fn drop_materialized_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.inner.drop_materialized_view(identifier).await
+    }
+    async fn load_tabular(self: Arc<Self>, identifier: &Identifier) -> Result<Tabular, Error> {
+        match self.client.load_table(identifier).await {
+            Ok(response) => Ok(Tabular::Table(
+                self.table(identifier.clone(), response).await?,
+            )),
+            // Views are loaded by the inner catalog
+            Err(apis::Error::ResponseError(content)) if content.status == 404 => {
+                self.inner.clone().load_tabular(identifier).await
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+    async fn create_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        mut create_table: CreateTable,
+    ) -> Result<Table, Error> {
+        let mut properties: HashMap<_, _> = self.table_properties("table-default.").collect();
+        properties.extend(create_table.properties.take().unwrap_or_default());
+        properties.extend(self.table_properties("table-override."));
+        create_table.properties = (!properties.is_empty()).then_some(properties);
+
+        let configuration = self.client.configuration().await?;
+        let response = catalog_api_api::create_table(
+            &configuration,
+            self.client.prefix.as_deref(),
+            &identifier.namespace().to_string(),
+            create_table,
+            Some(ACCESS_DELEGATION),
+        )
+        .await?;
+
+        self.table(identifier, response).await
+    }
+    async fn create_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateView<Option<()>>,
+    ) -> Result<View, Error> {
+        self.inner
+            .clone()
+            .create_view(identifier, create_view)
+            .await
+    }
+    async fn create_materialized_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateMaterializedView,
+    ) -> Result<MaterializedView, Error> {
+        self.inner
+            .clone()
+            .create_materialized_view(identifier, create_view)
+            .await
+    }
+    async fn update_table(self: Arc<Self>, commit: CommitTable) -> Result<Table, Error> {
+        let configuration = self.client.configuration().await?;
+        let identifier = commit.identifier.clone();
+        let response = catalog_api_api::update_table(
+            &configuration,
+            self.client.prefix.as_deref(),
+            &identifier.namespace().to_string(),
+            identifier.name(),
+            commit,
+        )
+        .await?;
+
+        let cached = self
+            .cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&identifier)
+            .cloned();
+        match cached {
+            Some(object_store) => {
+                Table::new(identifier, self, object_store, response.metadata).await
+            }
+            // The table was committed without loading it first, the object store is built from
+            // the credentials vended for the table
+            None => {
+                let response = self.client.load_table(&identifier).await?;
+                self.table(identifier, response).await
+            }
+        }
+    }
+    async fn update_view(self: Arc<Self>, commit: CommitView<Option<()>>) -> Result<View, Error> {
+        self.inner.clone().update_view(commit).await
+    }
+    async fn update_materialized_view(
+        self: Arc<Self>,
+        commit: CommitView<Identifier>,
+    ) -> Result<MaterializedView, Error> {
+        self.inner.clone().update_materialized_view(commit).await
+    }
+    async fn register_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        metadata_location: &str,
+    ) -> Result<Table, Error> {
+        let configuration = self.client.configuration().await?;
+        let request = models::RegisterTableRequest::new(
+            identifier.name().to_owned(),
+            metadata_location.to_owned(),
+        );
+        let response = catalog_api_api::register_table(
+            &configuration,
+            self.client.prefix.as_deref(),
+            &identifier.namespace().to_string(),
+            request,
+        )
+        .await?;
+
+        self.table(identifier, response).await
+    }
+}
+
+/// Storage configuration of a table
+///
+/// The storage credential with the longest prefix of the table location takes precedence over the
+/// table config.
+fn storage_config(response: &LoadTableResult) -> HashMap<String, String> {
+    let location = &response.metadata.location;
+    let mut config = response.config.clone().unwrap_or_default();
+    if let Some(credential) = response
+        .storage_credentials
+        .iter()
+        .flatten()
+        .filter(|credential| location.starts_with(&credential.prefix))
+        .max_by_key(|credential| credential.prefix.len())
+    {
+        config.extend(credential.config.clone());
+    }
+    config
+}
+
+/// S3 credentials vended by the REST catalog for a table
+///
+/// The credentials are reloaded from the catalog before they expire, requests keep using the
+/// cached credentials while they are reloaded.
+#[derive(Debug, Clone)]
+struct VendedCredentialProvider {
+    client: Arc<RestClient>,
+    identifier: Identifier,
+    cache: CredentialCache<AwsCredential>,
+}
+
+impl VendedCredentialProvider {
+    fn new(
+        client: Arc<RestClient>,
+        identifier: Identifier,
+        config: &HashMap<String, String>,
+    ) -> Result<Self, Error> {
+        let (credential, expiry) = vended_credential(config)?;
+        Ok(Self {
+            client,
+            identifier,
+            cache: CredentialCache::with_credential(credential, expiry),
+        })
+    }
+}
+
+/// S3 credential of a table config and its expiry
+fn vended_credential(
+    config: &HashMap<String, String>,
+) -> Result<(AwsCredential, Option<SystemTime>), Error> {
+    let get = |key: &str| {
+        config
+            .get(key)
+            .cloned()
+            .ok_or(Error::NotFound(format!("Vended credential {key}")))
+    };
+    let expiry = config
+        .get("s3.session-token-expires-at-ms")
+        .map(|x| {
+            x.parse::<u64>()
+                .map(|ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms))
+                .map_err(|_| Error::InvalidFormat(format!("Credential expiry {x}")))
+        })
+        .transpose()?;
+    let credential = AwsCredential {
+        key_id: get("s3.access-key-id")?,
+        secret_key: get("s3.secret-access-key")?,
+        token: config.get("s3.session-token").cloned(),
+    };
+    Ok((credential, expiry))
+}
+
+#[async_trait]
+impl FetchCredential for VendedCredentialProvider {
+    type Credential = AwsCredential;
+
+    const STORE: &'static str = "s3";
+
+    fn cache(&self) -> &CredentialCache<AwsCredential> {
+        &self.cache
+    }
+
+    async fn fetch(&self) -> Result<(AwsCredential, Option<SystemTime>), ObjectStoreError> {
+        tracing::debug!("Reloading vended credentials of table {}", self.identifier);
+        let external = |err: Error| ObjectStoreError::Generic {
+            store: Self::STORE,
+            source: Box::new(err),
+        };
+        let response = self
+            .client
+            .load_table(&self.identifier)
+            .await
+            .map_err(|err| external(err.into()))?;
+        vended_credential(&storage_config(&response)).map_err(external)
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for VendedCredentialProvider {
+    type Credential = AwsCredential;
+
+    async fn get_credential(&self) -> Result<Arc<AwsCredential>, ObjectStoreError> {
+        self.credential().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use axum::{
+        extract::{Query, State},
+        http::HeaderMap,
+        routing::get,
+        Json, Router,
+    };
+    use iceberg_rest_catalog::{
+        apis::configuration::Configuration,
+        models::{CommitTableResponse, LoadTableResult, StorageCredential},
+    };
+    use iceberg_rust::{
+        catalog::{commit::CommitTable, identifier::Identifier, tabular::Tabular, CatalogList},
+        spec::{
+            schema::Schema,
+            table_metadata::{TableMetadata, TableMetadataBuilder},
+            types::{PrimitiveType, StructField, Type},
+        },
+    };
+    use object_store::CredentialProvider;
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    use crate::storage::StorageRouter;
+
+    use super::{
+        catalog_properties, storage_config, RestClient, RestConfigCatalogList,
+        VendedCredentialProvider,
+    };
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    fn metadata() -> TableMetadata {
+        let schema = Schema::builder()
+            .with_struct_field(StructField::new(
+                1,
+                "id",
+                true,
+                Type::Primitive(PrimitiveType::Long),
+                None,
+            ))
+            .build()
+            .unwrap();
+        TableMetadataBuilder::default()
+            .location("s3://bucket/db/t")
+            .with_schema((0, schema))
+            .current_schema_id(0)
+            .build()
+            .unwrap()
+    }
+
+    async fn config(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+        Json(json!({
+            "defaults": {
+                "prefix": query.get("warehouse").map_or("default", String::as_str),
+                "warehouse": "default",
+                "table-default.format-version": "1",
+            },
+            "overrides": {"table-default.format-version": "2"},
+        }))
+    }
+
+    /// The first load vends expired credentials, every load vends a new access key
+    async fn load_table(State(requests): State<Requests>, headers: HeaderMap) -> Json<Value> {
+        let delegation = headers["X-Iceberg-Access-Delegation"].to_str().unwrap();
+        let loads = {
+            let mut requests = requests.lock().unwrap();
+            requests.push(format!("load {delegation}"));
+            requests.iter().filter(|x| x.starts_with("load")).count()
+        };
+        let expiry = if loads == 1 {
+            SystemTime::now() - Duration::from_secs(60)
+        } else {
+            SystemTime::now() + Duration::from_secs(3600)
+        };
+        let expiry_ms = expiry
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let mut response = LoadTableResult::new(metadata());
+        response.config = Some(HashMap::from([(
+            "s3.access-key-id".to_owned(),
+            "table".to_owned(),
+        )]));
+        response.storage_credentials = Some(vec![
+            StorageCredential::new(
+                "s3://".to_owned(),
+                HashMap::from([("s3.access-key-id".to_owned(), "bucket".to_owned())]),
+            ),
+            StorageCredential::new(
+                "s3://bucket/db".to_owned(),
+                HashMap::from([
+                    ("s3.access-key-id".to_owned(), format!("key{loads}")),
+                    ("s3.secret-access-key".to_owned(), "secret".to_owned()),
+                    ("s3.session-token".to_owned(), "token".to_owned()),
+                    (
+                        "s3.session-token-expires-at-ms".to_owned(),
+                        expiry_ms.to_string(),
+                    ),
+                ]),
+            ),
+        ]);
+        Json(serde_json::to_value(response).unwrap())
+    }
+
+    async fn update_table(State(requests): State<Requests>) -> Json<Value> {
+        requests.lock().unwrap().push("update".to_owned());
+        let response = CommitTableResponse::new(
+            "s3://bucket/db/t/metadata/00001.metadata.json".to_owned(),
+            metadata(),
+        );
+        Json(serde_json::to_value(response).unwrap())
+    }
+
+    /// Mock of the REST catalog and the requests it received
+    async fn serve() -> (Configuration, Requests) {
+        let requests = Requests::default();
+        let app = Router::new()
+            .route("/v1/config", get(config))
+            .route(
+                "/v1/lake/namespaces/db/tables/t",
+                get(load_table).post(update_table),
+            )
+            .with_state(requests.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let configuration = Configuration {
+            base_path: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (configuration, requests)
+    }
+
+    fn identifier() -> Identifier {
+        Identifier::new(&["db".to_owned()], "t")
+    }
+
+    #[tokio::test]
+    async fn catalog_properties_merge_the_config() {
+        let (configuration, _) = serve().await;
+
+        let properties = catalog_properties(&configuration, &None, Some("lake"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            properties,
+            HashMap::from([
+                ("prefix".to_owned(), "lake".to_owned()),
+                ("warehouse".to_owned(), "lake".to_owned()),
+                ("table-default.format-version".to_owned(), "2".to_owned()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn vended_credentials_are_reloaded() {
+        let (configuration, requests) = serve().await;
+        let client = Arc::new(RestClient {
+            prefix: Some("lake".to_owned()),
+            configuration,
+            rewriter: None,
+        });
+
+        let response = client.load_table(&identifier()).await.unwrap();
+        let config = storage_config(&response);
+        assert_eq!(config["s3.access-key-id"], "key1");
+
+        let provider = VendedCredentialProvider::new(client, identifier(), &config).unwrap();
+        let credential = provider.get_credential().await.unwrap();
+        assert_eq!(credential.key_id, "key2");
+        assert_eq!(credential.token.as_deref(), Some("token"));
+        let credential = provider.get_credential().await.unwrap();
+        assert_eq!(credential.key_id, "key2");
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "load vended-credentials".to_owned(),
+                "load vended-credentials".to_owned()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tables_are_updated_by_other_catalog_instances() {
+        let (configuration, requests) = serve().await;
+        let storage = Arc::new(StorageRouter::new(
+            None,
+            &Default::default(),
+            &Default::default(),
+        ));
+        let properties = HashMap::from([("prefix".to_owned(), "lake".to_owned())]);
+        let catalog_list =
+            RestConfigCatalogList::new(Some("iceberg"), properties, configuration, None, storage);
+        let commit = || CommitTable {
+            identifier: identifier(),
+            requirements: vec![],
+            updates: vec![],
+        };
+
+        // The table is loaded if it wasn't loaded before
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        catalog.update_table(commit()).await.unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["update".to_owned(), "load vended-credentials".to_owned()]
+        );
+
+        requests.lock().unwrap().clear();
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        let Tabular::Table(_) = catalog.load_tabular(&identifier()).await.unwrap() else {
+            panic!("Tabular is not a table")
+        };
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        let table = catalog.update_table(commit()).await.unwrap();
+        assert_eq!(table.metadata().location, "s3://bucket/db/t");
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["load vended-credentials".to_owned(), "update".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    sync::Arc,
+    time::Instant,
+};
+
+use datafusion::{config::SqlParserOptions, error::DataFusionError};
+use datafusion_cli::{
+    cli_context::CliSessionContext, exec, print_format::PrintFormat, print_options::PrintOptions,
+};
+use iceberg_rust::error::Error;
+use serde::Serialize;
+
+use crate::{
+    procedures::CallProcedure,
+    sql::{rewrite_table_references, split_statements},
+};
+
+/// Behavior of a non-interactive run when a statement fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// Skip the remaining statements
+    #[default]
+    Stop,
+    /// Execute the remaining statements
+    Continue,
+}
+
+/// Statement of a `-c` command or a `-f` file
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub location: Location,
+    pub sql: String,
+}
+
+/// Where a statement was given
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Location {
+    /// Position of the command, starting at 1
+    Command { command: usize },
+    /// Path of the file and the line the statement starts on
+    File { file: String, line: usize },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Command { command } => write!(f, "command {command}"),
+            Location::File { file, line } => write!(f, "{file}:{line}"),
+        }
+    }
+}
+
+/// Statements of the `-c` commands, the statements of a command stop at the first that fails
+pub fn statements_from_commands(commands: Vec<String>) -> Vec<Statement> {
+    commands
+        .into_iter()
+        .enumerate()
+        .map(|(i, sql)| Statement {
+            location: Location::Command { command: i + 1 },
+            sql,
+        })
+        .collect()
+}
+
+/// Statements of the `-f` files
+///
+/// The files are split like `datafusion-cli` does: lines starting with `--` or `#!` are skipped
+/// and a statement ends with a line that ends with `;`.
+pub fn statements_from_files(files: &[String]) -> Result<Vec<Statement>, Error> {
+    let mut statements = Vec::new();
+    for path in files {
+        let file = File::open(path)
+            .map_err(|err| Error::InvalidFormat(format!("File {path} can't be opened: {err}")))?;
+        let mut sql = String::new();
+        let mut start = 0;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|err| Error::External(Box::new(err)))?;
+            if line.starts_with("#!") || line.starts_with("--") {
+                continue;
+            }
+            let line = line.trim_end();
+            if sql.is_empty() {
+                start = i + 1;
+            }
+            sql.push_str(line);
+            if line.ends_with(';') {
+                statements.push(Statement {
+                    location: Location::File {
+                        file: path.clone(),
+                        line: start,
+                    },
+                    sql: std::mem::take(&mut sql),
+                });
+            } else {
+                sql.push('\n');
+            }
+        }
+        if sql.contains(|c| c != '\n') {
+            statements.push(Statement {
+                location: Location::File {
+                    file: path.clone(),
+                    line: start,
+                },
+                sql,
+            });
+        }
+    }
+    Ok(statements)
+}
+
+/// Execute the statements of the SQL with the extensions of frostbow and print their results
+///
+/// `CALL` statements of the maintenance procedures are planned by frostbow, all other
+/// statements by `datafusion-cli`. Stops at the first statement that fails.
+pub async fn exec_sql(
+    ctx: &dyn CliSessionContext,
+    sql: &str,
+    options: &SqlParserOptions,
+    print_options: &PrintOptions,
+) -> Result<(), DataFusionError> {
+    for statement in split_statements(sql, options) {
+        exec_statement(ctx, &statement, options, print_options).await?;
+    }
+    Ok(())
+}
+
+async fn exec_statement(
+    ctx: &dyn CliSessionContext,
+    sql: &str,
+    options: &SqlParserOptions,
+    print_options: &PrintOptions,
+) -> Result<(), DataFusionError> {
+    let now = Instant::now();
+    let Some(plan) = CallProcedure::plan(&ctx.session_state(), sql)? else {
+        let sql = rewrite_table_references(sql, options)?;
+        return exec::exec_from_commands(ctx, vec![sql], print_options).await;
+    };
+    let df = ctx.execute_logical_plan(plan).await?;
+    let schema = Arc::new(df.schema().as_arrow().clone());
+    let batches = df.collect().await?;
+    let row_count = batches.iter().map(|batch| batch.num_rows()).sum();
+    let mut print_options = print_options.clone();
+    if print_options.format == PrintFormat::Automatic {
+        print_options.format = PrintFormat::Table;
+    }
+    print_options.print_batches(
+        schema,
+        &batches,
+        now,
+        row_count,
+        &ctx.task_ctx().session_config().options().format,
+    )
+}
+
+/// Execute the statements and print their results
+///
+/// Errors are printed when they occur. Returns the failed statements if there are any.
+pub async fn exec_statements(
+    ctx: &dyn CliSessionContext,
+    statements: Vec<Statement>,
+    print_options: &PrintOptions,
+    on_error: OnError,
+) -> Result<(), ScriptError> {
+    let total = statements.len();
+    let mut executed = 0;
+    let mut errors = Vec::new();
+    let options = ctx.task_ctx().session_config().options().sql_parser.clone();
+    for statement in statements {
+        executed += 1;
+        let result = exec_sql(ctx, &statement.sql, &options, print_options).await;
+        if let Err(err) = result {
+            let message = err.strip_backtrace();
+            eprintln!("Error at {}: {}", statement.location, message);
+            errors.push(StatementError {
+                location: statement.location,
+                kind: ErrorKind::from_error(&err),
+                message,
+            });
+            if on_error == OnError::Stop {
+                break;
+            }
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(ScriptError {
+        statements: total,
+        succeeded: executed - errors.len(),
+        failed: errors.len(),
+        skipped: total - executed,
+        errors,
+    })
+}
+
+/// Class of a statement error, determines the exit code of the cli
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    Parse,
+    Planning,
+    Execution,
+    Catalog,
+}
+
+impl ErrorKind {
+    /// Classify an error by the first error of its chain that isn't a wrapper
+    pub fn from_error(err: &DataFusionError) -> Self {
+        let mut current: &(dyn StdError + 'static) = err;
+        loop {
+            if let Some(err) = current.downcast_ref::<DataFusionError>() {
+                match err {
+                    DataFusionError::SQL(..) => return ErrorKind::Parse,
+                    DataFusionError::Plan(_)
+                    | DataFusionError::SchemaError(..)
+                    | DataFusionError::NotImplemented(_)
+                    | DataFusionError::Configuration(_) => return ErrorKind::Planning,
+                    DataFusionError::External(_)
+                    | DataFusionError::Context(..)
+                    | DataFusionError::Diagnostic(..)
+                    | DataFusionError::Shared(_)
+                    | DataFusionError::Collection(_) => (),
+                    _ => return ErrorKind::Execution,
+                }
+            } else if let Some(err) = current.downcast_ref::<datafusion_iceberg::error::Error>() {
+                // The errors of datafusion_iceberg are transparent, so their source skips the
+                // wrapped error
+                match err {
+                    datafusion_iceberg::error::Error::Datafusion(err) => {
+                        return Self::from_error(err)
+                    }
+                    datafusion_iceberg::error::Error::SQLParser(_) => return ErrorKind::Parse,
+                    datafusion_iceberg::error::Error::Iceberg(_)
+                    | datafusion_iceberg::error::Error::IcebergSpec(_) => {
+                        return ErrorKind::Catalog
+                    }
+                    _ => return ErrorKind::Execution,
+                }
+            } else if current.is::<Error>() || current.is::<iceberg_rust::spec::error::Error>() {
+                return ErrorKind::Catalog;
+            }
+            match current.source() {
+                Some(source) => current = source,
+                None => return ErrorKind::Execution,
+            }
+        }
+    }
+
+    /// Exit code of the cli if a statement failed with this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorKind::Parse => 3,
+            ErrorKind::Planning => 4,
+            ErrorKind::Execution => 5,
+            ErrorKind::Catalog => 6,
+        }
+    }
+}
+
+/// Failed statement
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementError {
+    #[serde(flatten)]
+    pub location: Location,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// Summary of a non-interactive run with failed statements
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptError {
+    pub statements: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub errors: Vec<StatementError>,
+}
+
+impl ScriptError {
+    /// Exit code of the first failed statement
+    pub fn exit_code(&self) -> u8 {
+        self.errors
+            .first()
+            .map(|err| err.kind.exit_code())
+            .unwrap_or(1)
+    }
+
+    /// Summary as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} statements failed",
+            self.failed, self.statements
+        )
+    }
+}
+
+impl StdError for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{arrow::error::ArrowError, sql::parser::DFParser};
+
+    use super::*;
+
+    #[test]
+    fn error_kinds() {
+        let parse = DFParser::parse_sql("SELEC 1").unwrap_err();
+        let plan = || DataFusionError::Plan("table not found".to_owned());
+        let catalog = || Error::NotFound("table db.t".to_owned());
+        let cases = [
+            (parse, ErrorKind::Parse),
+            (plan(), ErrorKind::Planning),
+            (plan().context("while planning"), ErrorKind::Planning),
+            (
+                DataFusionError::ArrowError(Box::new(ArrowError::DivideByZero), None),
+                ErrorKind::Execution,
+            ),
+            (
+                DataFusionError::External(Box::new(catalog())),
+                ErrorKind::Catalog,
+            ),
+            (
+                DataFusionError::External(Box::new(Error::External(Box::new(plan())))),
+                ErrorKind::Catalog,
+            ),
+            (
+                datafusion_iceberg::error::Error::Datafusion(plan()).into(),
+                ErrorKind::Planning,
+            ),
+            (
+                datafusion_iceberg::error::Error::Iceberg(catalog()).into(),
+                ErrorKind::Catalog,
+            ),
+            (
+                DataFusionError::External(Box::new(std::io::Error::other("broken pipe"))),
+                ErrorKind::Execution,
+            ),
+        ];
+        for (err, kind) in cases {
+            assert_eq!(ErrorKind::from_error(&err), kind, "{err}");
+        }
+    }
+
+    #[test]
+    fn exit_code_of_first_error() {
+        let error = |kind| StatementError {
+            location: Location::Command { command: 1 },
+            kind,
+            message: String::new(),
+        };
+        let script = ScriptError {
+            statements: 3,
+            succeeded: 1,
+            failed: 2,
+            skipped: 0,
+            errors: vec![error(ErrorKind::Catalog), error(ErrorKind::Parse)],
+        };
+        assert_eq!(script.exit_code(), 6);
+        assert_eq!(
+            [
+                ErrorKind::Parse,
+                ErrorKind::Planning,
+                ErrorKind::Execution,
+                ErrorKind::Catalog
+            ]
+            .map(|kind| kind.exit_code()),
+            [3, 4, 5, 6]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use futures::future::{try_join_all, BoxFuture};
+use iceberg_rust::{catalog::CatalogList, error::Error};
+use secrecy::SecretString;
+
+use crate::IcebergContext;
+
+pub mod flight_sql;
+pub mod http;
+pub mod pg;
+#[cfg(feature = "rest")]
+pub mod rest_catalog;
+
+/// Endpoints of `frostbow serve`
+#[derive(Debug, Clone, clap::Args)]
+#[clap(group(clap::ArgGroup::new("endpoints").required(true).multiple(true)))]
+pub struct ServeArgs {
+    /// Address of the Arrow Flight SQL endpoint, for example 0.0.0.0:50051
+    #[clap(long = "flight-sql", value_name = "ADDR", group = "endpoints")]
+    pub flight_sql: Option<SocketAddr>,
+    /// Address of the HTTP query API, for example 0.0.0.0:8080
+    #[clap(long = "http", value_name = "ADDR", group = "endpoints")]
+    pub http: Option<SocketAddr>,
+    /// Address of the PostgreSQL wire protocol endpoint, for example 0.0.0.0:5432
+    #[clap(long = "pg", value_name = "ADDR", group = "endpoints")]
+    pub pg: Option<SocketAddr>,
+    /// Password of the PostgreSQL endpoint, clients are trusted if not set
+    #[clap(
+        long = "pg-password",
+        env = "FROSTBOW_PG_PASSWORD",
+        hide_env_values = true
+    )]
+    pub pg_password: Option<String>,
+    /// Address of the Iceberg REST catalog endpoint, for example 0.0.0.0:8181
+    #[cfg(feature = "rest")]
+    #[clap(long = "rest-catalog", value_name = "ADDR", group = "endpoints")]
+    pub rest_catalog: Option<SocketAddr>,
+}
+
+/// Serve the session and the catalogs on all configured endpoints until one of them fails
+pub async fn serve(
+    ctx: IcebergContext,
+    catalog_list: Arc<dyn CatalogList>,
+    args: &ServeArgs,
+) -> Result<(), Error> {
+    let ctx = Arc::new(ctx);
+
+    let mut servers: Vec<BoxFuture<'static, Result<(), Error>>> = Vec::new();
+    if let Some(addr) = args.flight_sql {
+        servers.push(Box::pin(flight_sql::serve(ctx.clone(), addr)));
+    }
+    if let Some(addr) = args.http {
+        servers.push(Box::pin(http::serve(ctx.clone(), addr)));
+    }
+    if let Some(addr) = args.pg {
+        let password = args.pg_password.as_deref().map(SecretString::from);
+        servers.push(Box::pin(pg::serve(ctx.clone(), addr, password)));
+    }
+    #[cfg(feature = "rest")]
+    if let Some(addr) = args.rest_catalog {
+        servers.push(Box::pin(rest_catalog::serve(catalog_list, addr)));
+    }
+
+    try_join_all(servers).await?;
+    Ok(())
+}
@@ -0,0 +1,681 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
+    flight_service_server::{FlightService, FlightServiceServer},
+    sql::{
+        metadata::{SqlInfoData, SqlInfoDataBuilder},
+        server::{FlightSqlService, PeekableFlightDataStream},
+        ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
+        ActionCreatePreparedStatementResult, CommandGetCatalogs, CommandGetDbSchemas,
+        CommandGetSqlInfo, CommandGetTableTypes, CommandGetTables, CommandPreparedStatementQuery,
+        CommandPreparedStatementUpdate, CommandStatementQuery, CommandStatementUpdate,
+        ProstMessageExt, SqlInfo, TicketStatementQuery,
+    },
+    FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest, HandshakeResponse, IpcMessage,
+    SchemaAsIpc, Ticket,
+};
+use datafusion::{
+    arrow::{
+        array::{StringArray, UInt64Array},
+        compute::like,
+        datatypes::{Schema, SchemaRef},
+        error::ArrowError,
+        ipc::writer::IpcWriteOptions,
+        record_batch::RecordBatch,
+    },
+    datasource::TableType,
+    error::DataFusionError,
+    logical_expr::LogicalPlan,
+};
+use futures::{stream, Stream, TryStreamExt};
+use iceberg_rust::error::Error;
+use prost::Message;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+use crate::IcebergContext;
+
+type DoGetStream = <FlightSqlServer as FlightService>::DoGetStream;
+
+type HandshakeStream = Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>;
+
+const AUTHORIZATION: &str = "authorization";
+
+const BEARER: &str = "Bearer ";
+
+/// Sessions that weren't used for this long are removed at the next handshake
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+static SQL_INFO: LazyLock<SqlInfoData> = LazyLock::new(|| {
+    let mut builder = SqlInfoDataBuilder::new();
+    builder.append(SqlInfo::FlightSqlServerName, "frostbow");
+    builder.append(SqlInfo::FlightSqlServerVersion, env!("CARGO_PKG_VERSION"));
+    builder.append(SqlInfo::FlightSqlServerArrowVersion, "1.3");
+    builder.append(SqlInfo::FlightSqlServerReadOnly, false);
+    builder.build().unwrap()
+});
+
+/// Serve the session over Arrow Flight SQL
+pub async fn serve(ctx: Arc<IcebergContext>, addr: SocketAddr) -> Result<(), Error> {
+    tracing::info!("Serving Arrow Flight SQL on {}", addr);
+    Server::builder()
+        .add_service(FlightServiceServer::new(FlightSqlServer::new(ctx)))
+        .serve(addr)
+        .await
+        .map_err(|err| Error::External(Box::new(err)))
+}
+
+/// Arrow Flight SQL service that executes statements on the frostbow session
+///
+/// Every handshake starts a session that is identified by the bearer token of the response, so
+/// settings changed with `SET` don't affect other clients. Requests without a token are executed
+/// in a session of their own. Clients aren't authenticated. Prepared statements are stateless,
+/// their handle is the SQL text of the statement.
+#[derive(Clone)]
+pub struct FlightSqlServer {
+    ctx: Arc<IcebergContext>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+/// Session of a client that performed a handshake
+struct Session {
+    ctx: Arc<IcebergContext>,
+    last_used: Instant,
+}
+
+impl FlightSqlServer {
+    pub fn new(ctx: Arc<IcebergContext>) -> Self {
+        Self {
+            ctx,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// New session that shares the catalogs and the runtime of the server
+    #[allow(clippy::result_large_err)]
+    fn new_session(&self) -> Result<Arc<IcebergContext>, Status> {
+        Ok(Arc::new(self.ctx.client_session().map_err(to_status)?))
+    }
+
+    /// Start a session and return its token
+    #[allow(clippy::result_large_err)]
+    fn start_session(&self) -> Result<String, Status> {
+        let ctx = self.new_session()?;
+        let token = rand::random::<[u8; 16]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        sessions.retain(|_, session| session.last_used.elapsed() < SESSION_TIMEOUT);
+        sessions.insert(
+            token.clone(),
+            Session {
+                ctx,
+                last_used: Instant::now(),
+            },
+        );
+        Ok(token)
+    }
+
+    /// Session of the bearer token of a request
+    #[allow(clippy::result_large_err)]
+    fn session<T>(&self, request: &Request<T>) -> Result<Arc<IcebergContext>, Status> {
+        let Some(token) = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix(BEARER))
+        else {
+            return self.new_session();
+        };
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let session = sessions.get_mut(token).ok_or_else(|| {
+            Status::unauthenticated("Unknown or expired session, the client has to handshake again")
+        })?;
+        session.last_used = Instant::now();
+        Ok(session.ctx.clone())
+    }
+
+    /// Plan a statement without executing it to get the schema of its result
+    async fn plan(ctx: &IcebergContext, sql: &str) -> Result<LogicalPlan, Status> {
+        ctx.create_logical_plan(sql).await.map_err(to_status)
+    }
+
+    /// Execute a statement and stream its result
+    async fn execute(ctx: &IcebergContext, sql: &str) -> Result<Response<DoGetStream>, Status> {
+        tracing::debug!("Executing statement: {}", sql);
+        let stream = ctx
+            .sql(sql)
+            .await
+            .map_err(to_status)?
+            .execute_stream()
+            .await
+            .map_err(to_status)?;
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(stream.schema())
+            .build(stream.map_err(|err| FlightError::ExternalError(Box::new(err))))
+            .map_err(Status::from);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// Execute a statement and return the number of affected rows
+    async fn update(ctx: &IcebergContext, sql: &str) -> Result<i64, Status> {
+        tracing::debug!("Executing update: {}", sql);
+        let batches = ctx
+            .sql(sql)
+            .await
+            .map_err(to_status)?
+            .collect()
+            .await
+            .map_err(to_status)?;
+        Ok(affected_rows(&batches))
+    }
+}
+
+#[tonic::async_trait]
+impl FlightSqlService for FlightSqlServer {
+    type FlightService = FlightSqlServer;
+
+    /// Start a session for the client
+    ///
+    /// All clients are accepted, authentication is left to the network.
+    async fn do_handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<HandshakeStream>, Status> {
+        let token = self.start_session()?;
+        let authorization = format!("{BEARER}{token}")
+            .parse()
+            .map_err(|_| Status::internal("Invalid session token"))?;
+        let response = HandshakeResponse {
+            protocol_version: 0,
+            payload: token.into_bytes().into(),
+        };
+        let mut response = Response::new(Box::pin(stream::iter([Ok(response)])) as HandshakeStream);
+        response.metadata_mut().insert(AUTHORIZATION, authorization);
+        Ok(response)
+    }
+
+    async fn get_flight_info_statement(
+        &self,
+        query: CommandStatementQuery,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let plan = Self::plan(&*self.session(&request)?, &query.query).await?;
+        let ticket = TicketStatementQuery {
+            statement_handle: query.query.into_bytes().into(),
+        };
+        flight_info(plan.schema().as_arrow(), ticket, request.into_inner())
+    }
+
+    async fn get_flight_info_prepared_statement(
+        &self,
+        query: CommandPreparedStatementQuery,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let ctx = self.session(&request)?;
+        let plan = Self::plan(&ctx, handle_sql(&query.prepared_statement_handle)?).await?;
+        flight_info(plan.schema().as_arrow(), query, request.into_inner())
+    }
+
+    async fn get_flight_info_catalogs(
+        &self,
+        query: CommandGetCatalogs,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let schema = query.into_builder().schema();
+        flight_info(&schema, query, request.into_inner())
+    }
+
+    async fn get_flight_info_schemas(
+        &self,
+        query: CommandGetDbSchemas,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let schema = query.clone().into_builder().schema();
+        flight_info(&schema, query, request.into_inner())
+    }
+
+    async fn get_flight_info_tables(
+        &self,
+        query: CommandGetTables,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let schema = query.clone().into_builder().schema();
+        flight_info(&schema, query, request.into_inner())
+    }
+
+    async fn get_flight_info_table_types(
+        &self,
+        query: CommandGetTableTypes,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let schema = query.into_builder().schema();
+        flight_info(&schema, query, request.into_inner())
+    }
+
+    async fn get_flight_info_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let schema = query.clone().into_builder(&SQL_INFO).schema();
+        flight_info(&schema, query, request.into_inner())
+    }
+
+    async fn do_get_statement(
+        &self,
+        ticket: TicketStatementQuery,
+        request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        Self::execute(
+            &*self.session(&request)?,
+            handle_sql(&ticket.statement_handle)?,
+        )
+        .await
+    }
+
+    async fn do_get_prepared_statement(
+        &self,
+        query: CommandPreparedStatementQuery,
+        request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        Self::execute(
+            &*self.session(&request)?,
+            handle_sql(&query.prepared_statement_handle)?,
+        )
+        .await
+    }
+
+    async fn do_get_catalogs(
+        &self,
+        query: CommandGetCatalogs,
+        _request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        let mut builder = query.into_builder();
+        for catalog in self.ctx.0.catalog_names() {
+            builder.append(catalog);
+        }
+        Ok(batch_response(builder.schema(), builder.build()))
+    }
+
+    async fn do_get_schemas(
+        &self,
+        query: CommandGetDbSchemas,
+        _request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        let mut builder = query.into_builder();
+        for catalog_name in self.ctx.0.catalog_names() {
+            let Some(catalog) = self.ctx.0.catalog(&catalog_name) else {
+                continue;
+            };
+            for schema in catalog.schema_names() {
+                builder.append(&catalog_name, schema);
+            }
+        }
+        Ok(batch_response(builder.schema(), builder.build()))
+    }
+
+    async fn do_get_tables(
+        &self,
+        query: CommandGetTables,
+        _request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        let include_schema = query.include_schema;
+        // The builder filters the tables when it is built, but the tables have to be loaded to
+        // get their schema
+        let catalog_filter = query.catalog.clone();
+        let db_schema_filter_pattern = query.db_schema_filter_pattern.clone();
+        let table_name_filter_pattern = query.table_name_filter_pattern.clone();
+        let table_types = query.table_types.clone();
+        let mut builder = query.into_builder();
+        for catalog_name in self.ctx.0.catalog_names() {
+            if catalog_filter
+                .as_ref()
+                .is_some_and(|catalog| *catalog != catalog_name)
+            {
+                continue;
+            }
+            let Some(catalog) = self.ctx.0.catalog(&catalog_name) else {
+                continue;
+            };
+            for schema_name in catalog.schema_names() {
+                if !matches_pattern(db_schema_filter_pattern.as_deref(), &schema_name)? {
+                    continue;
+                }
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+                for table_name in schema.table_names() {
+                    if !matches_pattern(table_name_filter_pattern.as_deref(), &table_name)? {
+                        continue;
+                    }
+                    let Some(table_type) =
+                        schema.table_type(&table_name).await.map_err(to_status)?
+                    else {
+                        continue;
+                    };
+                    if !table_types.is_empty()
+                        && !table_types
+                            .iter()
+                            .any(|name| name == table_type_name(table_type))
+                    {
+                        continue;
+                    }
+                    // Only load the table if its schema is requested
+                    let table_schema = if include_schema {
+                        match schema.table(&table_name).await.map_err(to_status)? {
+                            Some(table) => table.schema(),
+                            None => continue,
+                        }
+                    } else {
+                        Arc::new(Schema::empty())
+                    };
+                    builder
+                        .append(
+                            &catalog_name,
+                            &schema_name,
+                            &table_name,
+                            table_type_name(table_type),
+                            &table_schema,
+                        )
+                        .map_err(Status::from)?;
+                }
+            }
+        }
+        Ok(batch_response(builder.schema(), builder.build()))
+    }
+
+    async fn do_get_table_types(
+        &self,
+        query: CommandGetTableTypes,
+        _request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        let mut builder = query.into_builder();
+        for table_type in [TableType::Base, TableType::View, TableType::Temporary] {
+            builder.append(table_type_name(table_type));
+        }
+        Ok(batch_response(builder.schema(), builder.build()))
+    }
+
+    async fn do_get_sql_info(
+        &self,
+        query: CommandGetSqlInfo,
+        _request: Request<Ticket>,
+    ) -> Result<Response<DoGetStream>, Status> {
+        let builder = query.into_builder(&SQL_INFO);
+        Ok(batch_response(builder.schema(), builder.build()))
+    }
+
+    async fn do_put_statement_update(
+        &self,
+        query: CommandStatementUpdate,
+        request: Request<PeekableFlightDataStream>,
+    ) -> Result<i64, Status> {
+        Self::update(&*self.session(&request)?, &query.query).await
+    }
+
+    async fn do_put_prepared_statement_update(
+        &self,
+        query: CommandPreparedStatementUpdate,
+        request: Request<PeekableFlightDataStream>,
+    ) -> Result<i64, Status> {
+        Self::update(
+            &*self.session(&request)?,
+            handle_sql(&query.prepared_statement_handle)?,
+        )
+        .await
+    }
+
+    async fn do_action_create_prepared_statement(
+        &self,
+        query: ActionCreatePreparedStatementRequest,
+        request: Request<arrow_flight::Action>,
+    ) -> Result<ActionCreatePreparedStatementResult, Status> {
+        let plan = Self::plan(&*self.session(&request)?, &query.query).await?;
+        let IpcMessage(dataset_schema) =
+            SchemaAsIpc::new(plan.schema().as_arrow(), &IpcWriteOptions::default())
+                .try_into()
+                .map_err(|err: ArrowError| Status::internal(err.to_string()))?;
+        Ok(ActionCreatePreparedStatementResult {
+            prepared_statement_handle: query.query.into_bytes().into(),
+            dataset_schema,
+            parameter_schema: Default::default(),
+        })
+    }
+
+    async fn do_action_close_prepared_statement(
+        &self,
+        _query: ActionClosePreparedStatementRequest,
+        _request: Request<arrow_flight::Action>,
+    ) -> Result<(), Status> {
+        Ok(())
+    }
+
+    async fn register_sql_info(&self, _id: i32, _result: &SqlInfo) {}
+}
+
+/// SQL text of a statement handle
+#[allow(clippy::result_large_err)]
+fn handle_sql(handle: &[u8]) -> Result<&str, Status> {
+    std::str::from_utf8(handle)
+        .map_err(|err| Status::invalid_argument(format!("Invalid statement handle: {err}")))
+}
+
+/// Flight info with a single endpoint that is fetched with the given ticket
+#[allow(clippy::result_large_err)]
+fn flight_info(
+    schema: &Schema,
+    ticket: impl ProstMessageExt,
+    descriptor: FlightDescriptor,
+) -> Result<Response<FlightInfo>, Status> {
+    let info = FlightInfo::new()
+        .try_with_schema(schema)
+        .map_err(|err| Status::internal(err.to_string()))?
+        .with_endpoint(
+            FlightEndpoint::new().with_ticket(Ticket::new(ticket.as_any().encode_to_vec())),
+        )
+        .with_descriptor(descriptor);
+    Ok(Response::new(info))
+}
+
+fn batch_response(
+    schema: SchemaRef,
+    batch: Result<RecordBatch, FlightError>,
+) -> Response<DoGetStream> {
+    let stream = FlightDataEncoderBuilder::new()
+        .with_schema(schema)
+        .build(stream::once(async { batch }))
+        .map_err(Status::from);
+    Response::new(Box::pin(stream))
+}
+
+/// Number of rows affected by a DML statement, DataFusion returns them in the `count` column
+fn affected_rows(batches: &[RecordBatch]) -> i64 {
+    batches
+        .iter()
+        .filter_map(|batch| {
+            batch
+                .column_by_name("count")?
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .map(|count| count.iter().flatten().sum::<u64>())
+        })
+        .sum::<u64>() as i64
+}
+
+/// Whether the name matches the `LIKE` pattern of a metadata request, the same way the builders
+/// of the responses filter
+#[allow(clippy::result_large_err)]
+fn matches_pattern(pattern: Option<&str>, name: &str) -> Result<bool, Status> {
+    let Some(pattern) = pattern else {
+        return Ok(true);
+    };
+    let matches = like(
+        &StringArray::from(vec![name]),
+        &StringArray::new_scalar(pattern),
+    )
+    .map_err(|err| Status::from(FlightError::Arrow(err)))?;
+    Ok(matches.value(0))
+}
+
+fn table_type_name(table_type: TableType) -> &'static str {
+    match table_type {
+        TableType::Base => "TABLE",
+        TableType::View => "VIEW",
+        TableType::Temporary => "LOCAL TEMPORARY",
+    }
+}
+
+fn to_status(err: DataFusionError) -> Status {
+    // Planning errors are usually wrapped in a diagnostic or context
+    match err.find_root() {
+        DataFusionError::SQL(..) | DataFusionError::Plan(_) | DataFusionError::SchemaError(..) => {
+            Status::invalid_argument(err.strip_backtrace())
+        }
+        _ => Status::internal(err.strip_backtrace()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use arrow_flight::decode::FlightRecordBatchStream;
+    use async_trait::async_trait;
+    use datafusion::{
+        catalog::{MemorySchemaProvider, SchemaProvider, TableProvider},
+        datasource::MemTable,
+        execution::context::SessionContext,
+    };
+
+    use super::*;
+
+    /// Schema with the table `broken` that fails to load
+    #[derive(Debug)]
+    struct BrokenSchema(MemorySchemaProvider);
+
+    #[async_trait]
+    impl SchemaProvider for BrokenSchema {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn table_names(&self) -> Vec<String> {
+            let mut names = self.0.table_names();
+            names.push("broken".to_owned());
+            names
+        }
+
+        async fn table(
+            &self,
+            name: &str,
+        ) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+            if name == "broken" {
+                return Err(DataFusionError::Execution(
+                    "broken can't be loaded".to_owned(),
+                ));
+            }
+            self.0.table(name).await
+        }
+
+        async fn table_type(&self, name: &str) -> Result<Option<TableType>, DataFusionError> {
+            if name == "broken" {
+                return Ok(Some(TableType::Base));
+            }
+            self.0.table_type(name).await
+        }
+
+        fn table_exist(&self, name: &str) -> bool {
+            name == "broken" || self.0.table_exist(name)
+        }
+    }
+
+    fn request(token: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(token) = token {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION, format!("{BEARER}{token}").parse().unwrap());
+        }
+        request
+    }
+
+    fn batch_size(ctx: &IcebergContext) -> usize {
+        ctx.0.state().config().options().execution.batch_size
+    }
+
+    #[tokio::test]
+    async fn sessions_are_isolated() {
+        let server = FlightSqlServer::new(Arc::new(IcebergContext(SessionContext::new())));
+        let first = server.start_session().unwrap();
+        let second = server.start_session().unwrap();
+
+        let ctx = server.session(&request(Some(&first))).unwrap();
+        FlightSqlServer::update(&ctx, "SET datafusion.execution.batch_size = 7")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            batch_size(&server.session(&request(Some(&first))).unwrap()),
+            7
+        );
+        assert_ne!(
+            batch_size(&server.session(&request(Some(&second))).unwrap()),
+            7
+        );
+        assert_ne!(batch_size(&server.session(&request(None)).unwrap()), 7);
+        assert_ne!(batch_size(&server.ctx), 7);
+        let Err(err) = server.session(&request(Some("unknown"))) else {
+            panic!("unknown sessions are rejected")
+        };
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn tables_are_filtered_before_they_are_loaded() {
+        let ctx = SessionContext::new();
+        let schema = MemorySchemaProvider::new();
+        let table = MemTable::try_new(Arc::new(Schema::empty()), vec![vec![]]).unwrap();
+        schema
+            .register_table("orders".to_owned(), Arc::new(table))
+            .unwrap();
+        ctx.catalog("datafusion")
+            .unwrap()
+            .register_schema("sales", Arc::new(BrokenSchema(schema)))
+            .unwrap();
+        let server = FlightSqlServer::new(Arc::new(IcebergContext(ctx)));
+
+        let tables = |table_name_filter_pattern: Option<&str>| {
+            let query = CommandGetTables {
+                catalog: Some("datafusion".to_owned()),
+                db_schema_filter_pattern: Some("sal%".to_owned()),
+                table_name_filter_pattern: table_name_filter_pattern.map(ToOwned::to_owned),
+                table_types: Vec::new(),
+                include_schema: true,
+            };
+            let server = server.clone();
+            async move {
+                let response = server
+                    .do_get_tables(query, Request::new(Ticket::default()))
+                    .await?;
+                let batches: Vec<_> = FlightRecordBatchStream::new_from_flight_data(
+                    response.into_inner().map_err(FlightError::from),
+                )
+                .try_collect()
+                .await?;
+                Ok::<_, Status>(batches.iter().map(RecordBatch::num_rows).sum::<usize>())
+            }
+        };
+
+        assert_eq!(tables(Some("ord%")).await.unwrap(), 1);
+        assert!(tables(None).await.is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use datafusion::{
+    arrow::{
+        csv, datatypes::SchemaRef, ipc::writer::StreamWriter, json::LineDelimitedWriter,
+        record_batch::RecordBatch,
+    },
+    error::DataFusionError,
+    parquet::arrow::ArrowWriter,
+};
+use futures::{stream, StreamExt};
+use iceberg_rust::error::Error;
+use serde::Serialize;
+use tokio::net::TcpListener;
+
+use crate::IcebergContext;
+
+/// Serve the session over HTTP
+///
+/// `POST /query` executes the SQL statement in the request body and streams the result in the
+/// format requested with the `Accept` header. Dropping the response stream, for example because
+/// the client disconnected, cancels the query.
+pub async fn serve(ctx: Arc<IcebergContext>, addr: SocketAddr) -> Result<(), Error> {
+    let app = Router::new().route("/query", post(query)).with_state(ctx);
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| Error::External(Box::new(err)))?;
+    tracing::info!("Serving HTTP query API on {}", addr);
+    axum::serve(listener, app)
+        .await
+        .map_err(|err| Error::External(Box::new(err)))
+}
+
+async fn query(
+    State(ctx): State<Arc<IcebergContext>>,
+    headers: HeaderMap,
+    sql: String,
+) -> Result<Response, HttpError> {
+    let format = ResultFormat::from_accept(headers.get(header::ACCEPT))?;
+    tracing::debug!("Executing query: {}", sql);
+
+    // Every request is a session of its own on the shared memory pool, like the clients of the
+    // other servers
+    let stream = ctx
+        .client_session()?
+        .sql(&sql)
+        .await?
+        .execute_stream()
+        .await?;
+    let encoder = ResultEncoder::try_new(format, stream.schema())?;
+
+    let body = stream::unfold(Some((stream, encoder)), |state| async move {
+        let (mut stream, mut encoder) = state?;
+        match stream.next().await {
+            Some(Ok(batch)) => Some((encoder.write(&batch), Some((stream, encoder)))),
+            Some(Err(err)) => Some((Err(err), None)),
+            None => Some((encoder.finish(), None)),
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type())],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Formats of the query result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultFormat {
+    Json,
+    Csv,
+    Arrow,
+    Parquet,
+}
+
+impl ResultFormat {
+    /// Choose the supported media type of the `Accept` header with the highest quality value,
+    /// defaults to JSON lines
+    ///
+    /// Media types with the same quality value are preferred in the order they are listed.
+    fn from_accept(accept: Option<&HeaderValue>) -> Result<Self, HttpError> {
+        let Some(accept) = accept else {
+            return Ok(ResultFormat::Json);
+        };
+        let accept = accept.to_str().map_err(|_| {
+            HttpError::new(StatusCode::BAD_REQUEST, "Accept header is not valid ASCII.")
+        })?;
+        let mut formats = Vec::new();
+        for media_range in accept.split(',') {
+            let mut parameters = media_range.split(';');
+            let media_type = parameters.next().unwrap_or_default().trim();
+            let quality = match parameters
+                .filter_map(|parameter| parameter.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            {
+                Some((_, quality)) => quality.trim().parse::<f32>().map_err(|_| {
+                    HttpError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid quality value in media range {media_range}."),
+                    )
+                })?,
+                None => 1.0,
+            };
+            let format = match media_type.to_ascii_lowercase().as_str() {
+                "application/x-ndjson"
+                | "application/jsonl"
+                | "application/json"
+                | "application/*"
+                | "*/*" => ResultFormat::Json,
+                "text/csv" | "text/*" => ResultFormat::Csv,
+                "application/vnd.apache.arrow.stream" => ResultFormat::Arrow,
+                "application/vnd.apache.parquet" | "application/x-parquet" => ResultFormat::Parquet,
+                _ => continue,
+            };
+            // A quality value of 0 marks the media type as not acceptable
+            if quality > 0.0 {
+                formats.push((format, quality));
+            }
+        }
+        // The sort is stable, so the first listed of the media types with the same quality wins
+        formats.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        formats.first().map(|(format, _)| *format).ok_or_else(|| {
+            HttpError::new(
+                StatusCode::NOT_ACCEPTABLE,
+                format!("None of the media types {accept} is supported."),
+            )
+        })
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ResultFormat::Json => "application/x-ndjson",
+            ResultFormat::Csv => "text/csv",
+            ResultFormat::Arrow => "application/vnd.apache.arrow.stream",
+            ResultFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// Encodes the record batches of the result into chunks of the response body
+enum ResultEncoder {
+    Json,
+    Csv { schema: SchemaRef, header: bool },
+    Arrow(StreamWriter<Vec<u8>>),
+    Parquet(Box<ArrowWriter<Vec<u8>>>),
+}
+
+impl ResultEncoder {
+    fn try_new(format: ResultFormat, schema: SchemaRef) -> Result<Self, DataFusionError> {
+        Ok(match format {
+            ResultFormat::Json => ResultEncoder::Json,
+            ResultFormat::Csv => ResultEncoder::Csv {
+                schema,
+                header: true,
+            },
+            ResultFormat::Arrow => {
+                ResultEncoder::Arrow(StreamWriter::try_new(Vec::new(), &schema)?)
+            }
+            ResultFormat::Parquet => {
+                ResultEncoder::Parquet(Box::new(ArrowWriter::try_new(Vec::new(), schema, None)?))
+            }
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<Bytes, DataFusionError> {
+        match self {
+            ResultEncoder::Json => {
+                let mut writer = LineDelimitedWriter::new(Vec::new());
+                writer.write(batch)?;
+                writer.finish()?;
+                Ok(writer.into_inner().into())
+            }
+            ResultEncoder::Csv { header, .. } => {
+                let mut writer = csv::WriterBuilder::new()
+                    .with_header(*header)
+                    .build(Vec::new());
+                writer.write(batch)?;
+                *header = false;
+                Ok(writer.into_inner().into())
+            }
+            // The writers only track the number of bytes written, so the buffered bytes can be
+            // taken out after every batch
+            ResultEncoder::Arrow(writer) => {
+                writer.write(batch)?;
+                Ok(std::mem::take(writer.get_mut()).into())
+            }
+            ResultEncoder::Parquet(writer) => {
+                writer.write(batch)?;
+                Ok(std::mem::take(writer.inner_mut()).into())
+            }
+        }
+    }
+
+    fn finish(mut self) -> Result<Bytes, DataFusionError> {
+        match self {
+            ResultEncoder::Json | ResultEncoder::Csv { header: false, .. } => Ok(Bytes::new()),
+            // Write the header of empty results
+            ResultEncoder::Csv { ref schema, .. } => {
+                let batch = RecordBatch::new_empty(schema.clone());
+                self.write(&batch)
+            }
+            ResultEncoder::Arrow(writer) => Ok(writer.into_inner()?.into()),
+            ResultEncoder::Parquet(writer) => Ok(writer.into_inner()?.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Error response with a JSON body
+#[derive(Debug)]
+struct HttpError {
+    status: StatusCode,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<DataFusionError> for HttpError {
+    fn from(err: DataFusionError) -> Self {
+        // Planning errors are usually wrapped in a diagnostic or context
+        let status = match err.find_root() {
+            DataFusionError::SQL(..)
+            | DataFusionError::Plan(_)
+            | DataFusionError::SchemaError(..) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        HttpError::new(status, err.strip_backtrace())
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(ErrorBody {
+                error: self.message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(accept: &str) -> Result<ResultFormat, StatusCode> {
+        ResultFormat::from_accept(Some(&HeaderValue::from_str(accept).unwrap()))
+            .map_err(|err| err.status)
+    }
+
+    #[test]
+    fn accept_prefers_highest_quality() {
+        assert_eq!(ResultFormat::from_accept(None).unwrap(), ResultFormat::Json);
+        assert_eq!(format("text/csv"), Ok(ResultFormat::Csv));
+        assert_eq!(
+            format("text/csv;q=0.1, application/json"),
+            Ok(ResultFormat::Json)
+        );
+        assert_eq!(
+            format("application/json;q=0.5, application/vnd.apache.parquet;q=0.9"),
+            Ok(ResultFormat::Parquet)
+        );
+        assert_eq!(
+            format("text/html, application/vnd.apache.arrow.stream, text/csv"),
+            Ok(ResultFormat::Arrow)
+        );
+        assert_eq!(format("text/csv;q=0, */*;q=0.1"), Ok(ResultFormat::Json));
+        assert_eq!(format("text/csv;q=0"), Err(StatusCode::NOT_ACCEPTABLE));
+        assert_eq!(format("text/html"), Err(StatusCode::NOT_ACCEPTABLE));
+        assert_eq!(format("text/csv;q=high"), Err(StatusCode::BAD_REQUEST));
+    }
+}
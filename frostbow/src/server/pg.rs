@@ -0,0 +1,471 @@
+use std::{fmt::Debug, net::SocketAddr, sync::Arc};
+
+use async_trait::async_trait;
+use datafusion::{
+    arrow::array::UInt64Array,
+    common::ParamValues,
+    error::DataFusionError,
+    execution::context::SessionContext,
+    logical_expr::{DdlStatement, LogicalPlan, WriteOp},
+    sql::{
+        parser::{DFParser, Statement as DFStatement},
+        sqlparser::{ast::Statement, dialect::dialect_from_str},
+    },
+};
+use datafusion_cli::cli_context::CliSessionContext;
+use datafusion_postgres::{
+    arrow_pg::encode_dataframe,
+    datafusion_pg_catalog::{pg_catalog::context::EmptyContextProvider, setup_pg_catalog},
+    hooks::{
+        cursor::CursorStatementHook, set_show::SetShowHook, transactions::TransactionStatementHook,
+        HookClient,
+    },
+    pgwire::{
+        api::{
+            auth::{
+                md5pass::{hash_md5_password, Md5PasswordAuthStartupHandler},
+                noop::NoopStartupHandler,
+                AuthSource, DefaultServerParameterProvider, LoginInfo, Password, StartupHandler,
+            },
+            portal::{Format, Portal},
+            query::{ExtendedQueryHandler, SimpleQueryHandler},
+            results::{FieldInfo, Response, Tag},
+            stmt::QueryParser,
+            store::PortalStore,
+            ClientInfo, ClientPortalStore, PgWireServerHandlers, Type,
+        },
+        error::{PgWireError, PgWireResult},
+        messages::{PgWireBackendMessage, PgWireFrontendMessage},
+        tokio::process_socket,
+        types::format::FormatOptions,
+    },
+    DfSessionService, Parser, QueryHook,
+};
+use futures::Sink;
+use iceberg_rust::error::Error;
+use secrecy::{ExposeSecret, SecretString};
+use tokio::net::TcpListener;
+
+use crate::{procedures::CallProcedure, sql::rewrite_table_references, IcebergContext};
+
+/// Serve the session over the PostgreSQL wire protocol
+///
+/// Every connection gets its own session that shares the catalogs and the runtime of `ctx`.
+/// Clients have to authenticate with `password` if it is set, otherwise they are trusted.
+pub async fn serve(
+    ctx: Arc<IcebergContext>,
+    addr: SocketAddr,
+    password: Option<SecretString>,
+) -> Result<(), Error> {
+    let default_catalog = ctx
+        .0
+        .state()
+        .config()
+        .options()
+        .catalog
+        .default_catalog
+        .clone();
+    setup_pg_catalog(&ctx.0, &default_catalog, EmptyContextProvider)
+        .map_err(|err| Error::External(err))?;
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| Error::External(Box::new(err)))?;
+    tracing::info!("Serving PostgreSQL wire protocol on {}", addr);
+
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                tracing::warn!("Failed to accept PostgreSQL connection: {}", err);
+                continue;
+            }
+        };
+        let session = match ctx.client_session() {
+            Ok(session) => session,
+            Err(err) => {
+                tracing::warn!("Failed to create session for {}: {}", peer, err);
+                continue;
+            }
+        };
+        let handlers = PgHandlers::new(session.0, password.clone());
+        tokio::spawn(async move {
+            if let Err(err) = process_socket(socket, None, handlers).await {
+                tracing::warn!("PostgreSQL connection from {} failed: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Handlers of a single PostgreSQL connection
+struct PgHandlers {
+    session: Arc<IcebergSessionService>,
+    startup: Arc<PgStartupHandler>,
+}
+
+impl PgHandlers {
+    fn new(session: SessionContext, password: Option<SecretString>) -> Self {
+        let startup = match password {
+            Some(password) => PgStartupHandler::Password(Md5PasswordAuthStartupHandler::new(
+                Arc::new(PasswordAuthSource { password }),
+                Arc::new(DefaultServerParameterProvider::default()),
+            )),
+            None => PgStartupHandler::Trust(TrustStartupHandler),
+        };
+        Self {
+            session: Arc::new(IcebergSessionService::new(session)),
+            startup: Arc::new(startup),
+        }
+    }
+}
+
+impl PgWireServerHandlers for PgHandlers {
+    fn simple_query_handler(&self) -> Arc<impl SimpleQueryHandler> {
+        self.session.clone()
+    }
+
+    fn extended_query_handler(&self) -> Arc<impl ExtendedQueryHandler> {
+        self.session.clone()
+    }
+
+    fn startup_handler(&self) -> Arc<impl StartupHandler> {
+        self.startup.clone()
+    }
+}
+
+enum PgStartupHandler {
+    Trust(TrustStartupHandler),
+    Password(Md5PasswordAuthStartupHandler<PasswordAuthSource, DefaultServerParameterProvider>),
+}
+
+#[async_trait]
+impl StartupHandler for PgStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match self {
+            PgStartupHandler::Trust(handler) => handler.on_startup(client, message).await,
+            PgStartupHandler::Password(handler) => handler.on_startup(client, message).await,
+        }
+    }
+}
+
+/// Accepts all clients without a password
+struct TrustStartupHandler;
+
+impl NoopStartupHandler for TrustStartupHandler {}
+
+/// Password shared by all users, sent md5 hashed with a random salt
+#[derive(Debug)]
+struct PasswordAuthSource {
+    password: SecretString,
+}
+
+#[async_trait]
+impl AuthSource for PasswordAuthSource {
+    async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
+        let salt = rand::random::<[u8; 4]>().to_vec();
+        let hash = hash_md5_password(
+            login.user().unwrap_or_default(),
+            self.password.expose_secret(),
+            &salt,
+        );
+        Ok(Password::new(Some(salt), hash.into_bytes()))
+    }
+}
+
+/// Session of a connection that plans and executes all statements with the Iceberg extensions
+///
+/// The table references of frostbow are rewritten before the statements are parsed. Statements
+/// of DataFusion, like `CREATE EXTERNAL TABLE`, are executed directly, all other statements are
+/// passed on to `datafusion-postgres`. There `SET`, `SHOW`, transaction and cursor statements are
+/// handled by its hooks and the remaining statements are planned and executed by the
+/// [`IcebergContext`].
+struct IcebergSessionService {
+    ctx: IcebergContext,
+    inner: DfSessionService,
+    parser: Arc<IcebergQueryParser>,
+}
+
+impl IcebergSessionService {
+    fn new(session: SessionContext) -> Self {
+        let hooks: Vec<Arc<dyn QueryHook>> = vec![
+            Arc::new(CursorStatementHook),
+            Arc::new(SetShowHook),
+            Arc::new(TransactionStatementHook),
+            Arc::new(IcebergHook),
+        ];
+        let inner = DfSessionService::new_with_hooks(Arc::new(session.clone()), hooks);
+        let parser = Arc::new(IcebergQueryParser {
+            ctx: IcebergContext(session.clone()),
+            inner: inner.query_parser(),
+        });
+        Self {
+            ctx: IcebergContext(session),
+            inner,
+            parser,
+        }
+    }
+}
+
+#[async_trait]
+impl SimpleQueryHandler for IcebergSessionService {
+    async fn do_query<C>(&self, client: &mut C, query: &str) -> PgWireResult<Vec<Response>>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let query = rewrite_query(&self.ctx, query)?;
+        // Queries that DataFusion can't parse are left to the PostgreSQL compatibility parser
+        let Ok(statements) = parse_query(&self.ctx, &query) else {
+            return SimpleQueryHandler::do_query(&self.inner, client, &query).await;
+        };
+        if statements.is_empty() {
+            return Ok(vec![Response::EmptyQuery]);
+        }
+
+        let mut responses = Vec::with_capacity(statements.len());
+        for statement in statements {
+            match statement {
+                DFStatement::Statement(statement) => responses.extend(
+                    SimpleQueryHandler::do_query(&self.inner, client, &statement.to_string())
+                        .await?,
+                ),
+                // Statements of DataFusion, like CREATE EXTERNAL TABLE and COPY, aren't
+                // supported by the PostgreSQL parser. Their table references are already
+                // rewritten.
+                statement => {
+                    let plan = self
+                        .ctx
+                        .0
+                        .state()
+                        .statement_to_plan(statement)
+                        .await
+                        .map_err(api_error)?;
+                    let format_options =
+                        Arc::new(FormatOptions::from_client_metadata(client.metadata()));
+                    responses.push(
+                        execute(&self.ctx, plan, &Format::UnifiedText, format_options).await?,
+                    );
+                }
+            }
+        }
+        Ok(responses)
+    }
+}
+
+#[async_trait]
+impl ExtendedQueryHandler for IcebergSessionService {
+    type Statement = <DfSessionService as ExtendedQueryHandler>::Statement;
+    type QueryParser = IcebergQueryParser;
+
+    fn query_parser(&self) -> Arc<Self::QueryParser> {
+        self.parser.clone()
+    }
+
+    async fn do_query<C>(
+        &self,
+        client: &mut C,
+        portal: &Portal<Self::Statement>,
+        max_rows: usize,
+    ) -> PgWireResult<Response>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        // The Iceberg extensions only differ from DataFusion in the execution of DDL statements
+        // and procedures, all other plans are executed by `datafusion-postgres`
+        if let (_, Some((_, plan))) = &portal.statement.statement {
+            if requires_iceberg_execution(plan) {
+                let format_options =
+                    Arc::new(FormatOptions::from_client_metadata(client.metadata()));
+                return execute(
+                    &self.ctx,
+                    plan.clone(),
+                    &portal.result_column_format,
+                    format_options,
+                )
+                .await;
+            }
+        }
+        ExtendedQueryHandler::do_query(&self.inner, client, portal, max_rows).await
+    }
+}
+
+/// Parser of the extended query protocol that accepts the table references of frostbow
+struct IcebergQueryParser {
+    ctx: IcebergContext,
+    inner: Arc<Parser>,
+}
+
+#[async_trait]
+impl QueryParser for IcebergQueryParser {
+    type Statement = <Parser as QueryParser>::Statement;
+
+    async fn parse_sql<C>(
+        &self,
+        client: &C,
+        sql: &str,
+        types: &[Option<Type>],
+    ) -> PgWireResult<Self::Statement>
+    where
+        C: ClientInfo + Unpin + Send + Sync,
+    {
+        let sql = rewrite_query(&self.ctx, sql)?;
+        self.inner.parse_sql(client, &sql, types).await
+    }
+
+    fn get_parameter_types(&self, statement: &Self::Statement) -> PgWireResult<Vec<Type>> {
+        self.inner.get_parameter_types(statement)
+    }
+
+    fn get_result_schema(
+        &self,
+        statement: &Self::Statement,
+        column_format: Option<&Format>,
+    ) -> PgWireResult<Vec<FieldInfo>> {
+        self.inner.get_result_schema(statement, column_format)
+    }
+}
+
+/// Parse a query with the parser of DataFusion
+fn parse_query(ctx: &IcebergContext, query: &str) -> Result<Vec<DFStatement>, DataFusionError> {
+    let state = ctx.0.state();
+    let dialect = &state.config().options().sql_parser.dialect;
+    let dialect = dialect_from_str(dialect)
+        .ok_or_else(|| DataFusionError::Plan(format!("Unsupported SQL dialect: {dialect}")))?;
+    Ok(DFParser::parse_sql_with_dialect(query, dialect.as_ref())?.into())
+}
+
+fn rewrite_query(ctx: &IcebergContext, query: &str) -> PgWireResult<String> {
+    rewrite_table_references(query, &ctx.0.state().config().options().sql_parser).map_err(api_error)
+}
+
+/// Plans and executes the statements that aren't handled by the other hooks with the Iceberg
+/// extensions
+struct IcebergHook;
+
+#[async_trait]
+impl QueryHook for IcebergHook {
+    async fn handle_simple_query(
+        &self,
+        statement: &Statement,
+        session_context: &SessionContext,
+        client: &mut dyn HookClient,
+    ) -> Option<PgWireResult<Response>> {
+        let ctx = IcebergContext(session_context.clone());
+        let plan = match ctx.create_logical_plan(&statement.to_string()).await {
+            Ok(plan) => plan,
+            Err(err) => return Some(Err(api_error(err))),
+        };
+        let format_options = Arc::new(FormatOptions::from_client_metadata(client.metadata()));
+        Some(execute(&ctx, plan, &Format::UnifiedText, format_options).await)
+    }
+
+    async fn handle_extended_parse_query(
+        &self,
+        statement: &Statement,
+        session_context: &SessionContext,
+        _client: &(dyn ClientInfo + Send + Sync),
+    ) -> Option<PgWireResult<LogicalPlan>> {
+        Some(
+            IcebergContext(session_context.clone())
+                .create_logical_plan(&statement.to_string())
+                .await
+                .map_err(api_error),
+        )
+    }
+
+    async fn handle_extended_query(
+        &self,
+        _statement: &Statement,
+        _logical_plan: &LogicalPlan,
+        _params: &ParamValues,
+        _session_context: &SessionContext,
+        _client: &mut dyn HookClient,
+    ) -> Option<PgWireResult<Response>> {
+        None
+    }
+}
+
+/// Whether the plan has to be executed by the [`IcebergContext`]
+fn requires_iceberg_execution(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::Ddl(_) => true,
+        LogicalPlan::Extension(extension) => extension
+            .node
+            .as_any()
+            .downcast_ref::<CallProcedure>()
+            .is_some(),
+        _ => false,
+    }
+}
+
+/// Execute a plan with the Iceberg extensions and encode the result
+async fn execute(
+    ctx: &IcebergContext,
+    plan: LogicalPlan,
+    format: &Format,
+    format_options: Arc<FormatOptions>,
+) -> PgWireResult<Response> {
+    let tag = command_tag(&plan);
+    let df = ctx.execute_logical_plan(plan).await.map_err(api_error)?;
+    match tag {
+        Some(tag) if tag == "INSERT" => {
+            let batches = df.collect().await.map_err(api_error)?;
+            let rows = batches
+                .first()
+                .and_then(|batch| batch.column_by_name("count"))
+                .and_then(|count| count.as_any().downcast_ref::<UInt64Array>())
+                .map_or(0, |count| count.value(0) as usize);
+            Ok(Response::Execution(
+                Tag::new("INSERT").with_oid(0).with_rows(rows),
+            ))
+        }
+        Some(tag) => {
+            df.collect().await.map_err(api_error)?;
+            Ok(Response::Execution(Tag::new(&tag)))
+        }
+        None => Ok(Response::Query(
+            encode_dataframe(df, format, Some(format_options)).await?,
+        )),
+    }
+}
+
+/// Command tag that PostgreSQL returns for a statement without a result set
+fn command_tag(plan: &LogicalPlan) -> Option<String> {
+    let tag = match plan {
+        LogicalPlan::Dml(dml) if matches!(dml.op, WriteOp::Insert(_)) => "INSERT",
+        LogicalPlan::Ddl(ddl) => match ddl {
+            DdlStatement::CreateExternalTable(_) | DdlStatement::CreateMemoryTable(_) => {
+                "CREATE TABLE"
+            }
+            DdlStatement::CreateView(_) => "CREATE VIEW",
+            DdlStatement::CreateCatalogSchema(_) => "CREATE SCHEMA",
+            DdlStatement::CreateCatalog(_) => "CREATE DATABASE",
+            DdlStatement::CreateIndex(_) => "CREATE INDEX",
+            DdlStatement::DropTable(_) => "DROP TABLE",
+            DdlStatement::DropView(_) => "DROP VIEW",
+            DdlStatement::DropCatalogSchema(_) => "DROP SCHEMA",
+            DdlStatement::CreateFunction(_) => "CREATE FUNCTION",
+            DdlStatement::DropFunction(_) => "DROP FUNCTION",
+        },
+        _ => return None,
+    };
+    Some(tag.to_owned())
+}
+
+fn api_error(err: DataFusionError) -> PgWireError {
+    PgWireError::ApiError(Box::new(err))
+}
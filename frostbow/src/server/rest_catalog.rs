@@ -0,0 +1,843 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures::{future::try_join_all, TryStreamExt};
+use iceberg_rest_catalog::models::{
+    CatalogConfig, CommitTableResponse, CreateNamespaceRequest, CreateNamespaceResponse,
+    ErrorModel, GetNamespaceResponse, IcebergErrorResponse, ListNamespacesResponse,
+    ListTablesResponse, LoadTableResult, RegisterTableRequest, UpdateNamespacePropertiesRequest,
+    UpdateNamespacePropertiesResponse,
+};
+use iceberg_rust::{
+    catalog::{
+        commit::{check_table_requirements, CommitTable, TableRequirement, TableUpdate},
+        create::CreateTable,
+        identifier::Identifier,
+        namespace::Namespace,
+        tabular::Tabular,
+        Catalog, CatalogList,
+    },
+    error::Error,
+    object_store::store::IcebergStore,
+    spec::{tabular::TabularMetadata, util::strip_prefix},
+    table::Table,
+};
+use serde::Deserialize;
+use tokio::net::TcpListener;
+
+/// Separator of the levels of a namespace in paths and query parameters
+const NAMESPACE_SEPARATOR: char = '\u{1F}';
+
+/// Endpoints announced by the config endpoint
+const ENDPOINTS: [&str; 14] = [
+    "GET /v1/{prefix}/namespaces",
+    "POST /v1/{prefix}/namespaces",
+    "GET /v1/{prefix}/namespaces/{namespace}",
+    "HEAD /v1/{prefix}/namespaces/{namespace}",
+    "DELETE /v1/{prefix}/namespaces/{namespace}",
+    "POST /v1/{prefix}/namespaces/{namespace}/properties",
+    "GET /v1/{prefix}/namespaces/{namespace}/tables",
+    "POST /v1/{prefix}/namespaces/{namespace}/tables",
+    "GET /v1/{prefix}/namespaces/{namespace}/tables/{table}",
+    "HEAD /v1/{prefix}/namespaces/{namespace}/tables/{table}",
+    "POST /v1/{prefix}/namespaces/{namespace}/tables/{table}",
+    "DELETE /v1/{prefix}/namespaces/{namespace}/tables/{table}",
+    "POST /v1/{prefix}/namespaces/{namespace}/register",
+    "GET /v1/config",
+];
+
+/// Serve the catalogs as an Iceberg REST catalog
+///
+/// Every catalog of `catalog_list` is exposed under its name as prefix, clients select it with
+/// the `warehouse` parameter of the config endpoint.
+pub async fn serve(catalog_list: Arc<dyn CatalogList>, addr: SocketAddr) -> Result<(), Error> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| Error::External(Box::new(err)))?;
+    tracing::info!("Serving Iceberg REST catalog on {}", addr);
+    axum::serve(listener, router(catalog_list))
+        .await
+        .map_err(|err| Error::External(Box::new(err)))
+}
+
+fn router(catalog_list: Arc<dyn CatalogList>) -> Router {
+    Router::new()
+        .route("/v1/config", get(config))
+        .route(
+            "/v1/{prefix}/namespaces",
+            get(list_namespaces).post(create_namespace),
+        )
+        .route(
+            "/v1/{prefix}/namespaces/{namespace}",
+            get(load_namespace)
+                .head(namespace_exists)
+                .delete(drop_namespace),
+        )
+        .route(
+            "/v1/{prefix}/namespaces/{namespace}/properties",
+            post(update_namespace_properties),
+        )
+        .route(
+            "/v1/{prefix}/namespaces/{namespace}/tables",
+            get(list_tables).post(create_table),
+        )
+        .route(
+            "/v1/{prefix}/namespaces/{namespace}/tables/{table}",
+            get(load_table)
+                .head(table_exists)
+                .post(commit_table)
+                .delete(drop_table),
+        )
+        .route(
+            "/v1/{prefix}/namespaces/{namespace}/register",
+            post(register_table),
+        )
+        .route("/v1/{prefix}/namespaces/{namespace}/views", get(list_views))
+        .with_state(catalog_list)
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigQuery {
+    warehouse: Option<String>,
+}
+
+/// Returns the name of the requested catalog as prefix, the warehouse can be omitted if only a
+/// single catalog is served
+async fn config(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Query(query): Query<ConfigQuery>,
+) -> Result<Json<CatalogConfig>, RestError> {
+    let prefix = match query.warehouse {
+        Some(warehouse) => {
+            catalog(catalog_list.as_ref(), &warehouse)?;
+            warehouse
+        }
+        None => {
+            let mut catalogs = catalog_list.list_catalogs().await;
+            match catalogs.len() {
+                1 => catalogs.remove(0),
+                0 => {
+                    return Err(RestError::bad_request("Warehouse is required.".to_owned()));
+                }
+                _ => {
+                    return Err(RestError::bad_request(format!(
+                        "Warehouse is required to select one of the catalogs {}.",
+                        catalogs.join(", ")
+                    )));
+                }
+            }
+        }
+    };
+    let mut config = CatalogConfig::new(
+        HashMap::from([("prefix".to_owned(), prefix)]),
+        HashMap::new(),
+    );
+    config.endpoints = Some(ENDPOINTS.iter().map(ToString::to_string).collect());
+    Ok(Json(config))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListNamespacesQuery {
+    parent: Option<String>,
+}
+
+/// Lists the direct children of the parent namespace, or the top-level namespaces
+///
+/// The catalogs don't filter by parent consistently, so all namespaces are listed and filtered
+/// here.
+async fn list_namespaces(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path(prefix): Path<String>,
+    Query(query): Query<ListNamespacesQuery>,
+) -> Result<Json<ListNamespacesResponse>, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let parent = match query.parent {
+        Some(parent) => existing_namespace(catalog.as_ref(), &parent)
+            .await?
+            .to_vec(),
+        None => Vec::new(),
+    };
+
+    let mut seen = HashSet::new();
+    let namespaces = catalog
+        .list_namespaces(None)
+        .await?
+        .into_iter()
+        .filter(|namespace| namespace.len() > parent.len() && namespace.starts_with(&parent))
+        .map(|namespace| namespace[..parent.len() + 1].to_vec())
+        .filter(|namespace| seen.insert(namespace.clone()))
+        .collect();
+
+    let mut response = ListNamespacesResponse::new();
+    response.namespaces = Some(namespaces);
+    Ok(Json(response))
+}
+
+async fn create_namespace(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path(prefix): Path<String>,
+    request: Result<Json<CreateNamespaceRequest>, JsonRejection>,
+) -> Result<Json<CreateNamespaceResponse>, RestError> {
+    let Json(request) = request?;
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = try_namespace(&request.namespace)?;
+    if has_namespace(catalog.as_ref(), &namespace).await? {
+        return Err(RestError::already_exists(format!(
+            "Namespace {namespace} already exists."
+        )));
+    }
+    let properties = catalog
+        .create_namespace(&namespace, request.properties)
+        .await?;
+
+    let mut response = CreateNamespaceResponse::new(namespace.to_vec());
+    response.properties = Some(properties);
+    Ok(Json(response))
+}
+
+async fn load_namespace(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+) -> Result<Json<GetNamespaceResponse>, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    let properties = supported(catalog.load_namespace(&namespace).await)?.unwrap_or_default();
+
+    let mut response = GetNamespaceResponse::new(namespace.to_vec());
+    response.properties = Some(properties);
+    Ok(Json(response))
+}
+
+async fn namespace_exists(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+) -> Result<StatusCode, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    existing_namespace(catalog.as_ref(), &namespace).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn drop_namespace(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+) -> Result<StatusCode, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    if !catalog.list_tabulars(&namespace).await?.is_empty() {
+        return Err(RestError::new(
+            StatusCode::CONFLICT,
+            "NamespaceNotEmptyException",
+            format!("Namespace {namespace} is not empty."),
+        ));
+    }
+    supported(catalog.drop_namespace(&namespace).await)?
+        .ok_or_else(|| not_supported("dropping namespaces", catalog.as_ref()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn update_namespace_properties(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+    request: Result<Json<UpdateNamespacePropertiesRequest>, JsonRejection>,
+) -> Result<Json<UpdateNamespacePropertiesResponse>, RestError> {
+    let Json(request) = request?;
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+
+    let updates = request.updates.unwrap_or_default();
+    let removals = request.removals.unwrap_or_default();
+    if let Some(key) = removals.iter().find(|key| updates.contains_key(*key)) {
+        return Err(RestError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "UnprocessableEntityException",
+            format!("Property {key} is both updated and removed."),
+        ));
+    }
+
+    let properties = supported(catalog.load_namespace(&namespace).await)?.unwrap_or_default();
+    let (removed, missing): (Vec<_>, Vec<_>) = removals
+        .into_iter()
+        .partition(|key| properties.contains_key(key));
+    let updated = updates.keys().cloned().collect();
+    supported(
+        catalog
+            .update_namespace(&namespace, Some(updates), Some(removed.clone()))
+            .await,
+    )?
+    .ok_or_else(|| not_supported("updating namespace properties", catalog.as_ref()))?;
+
+    let mut response = UpdateNamespacePropertiesResponse::new(updated, removed);
+    response.missing = Some(missing);
+    Ok(Json(response))
+}
+
+async fn list_tables(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+) -> Result<Json<ListTablesResponse>, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    let (tables, _) = list_tabulars(catalog, &namespace).await?;
+
+    let mut response = ListTablesResponse::new();
+    response.identifiers = Some(tables);
+    Ok(Json(response))
+}
+
+/// Lists the views and materialized views, which can't be loaded over this endpoint yet
+async fn list_views(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+) -> Result<Json<ListTablesResponse>, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    let (_, views) = list_tabulars(catalog, &namespace).await?;
+
+    let mut response = ListTablesResponse::new();
+    response.identifiers = Some(views);
+    Ok(Json(response))
+}
+
+async fn create_table(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+    request: Result<Json<CreateTable>, JsonRejection>,
+) -> Result<Json<LoadTableResult>, RestError> {
+    let Json(request) = request?;
+    if request.stage_create == Some(true) {
+        return Err(RestError::new(
+            StatusCode::NOT_ACCEPTABLE,
+            "UnsupportedOperationException",
+            "Staged table creation is not supported.",
+        ));
+    }
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    let identifier = Identifier::new(&namespace, &request.name);
+    if catalog.tabular_exists(&identifier).await? {
+        return Err(RestError::already_exists(format!(
+            "Table {identifier} already exists."
+        )));
+    }
+    let table = catalog.create_table(identifier, request).await?;
+
+    Ok(Json(load_table_result(table).await))
+}
+
+async fn register_table(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace)): Path<(String, String)>,
+    request: Result<Json<RegisterTableRequest>, JsonRejection>,
+) -> Result<Json<LoadTableResult>, RestError> {
+    let Json(request) = request?;
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let namespace = existing_namespace(catalog.as_ref(), &namespace).await?;
+    let identifier = Identifier::new(&namespace, &request.name);
+    if catalog.tabular_exists(&identifier).await? {
+        return Err(RestError::already_exists(format!(
+            "Table {identifier} already exists."
+        )));
+    }
+    let table = supported(
+        catalog
+            .clone()
+            .register_table(identifier, &request.metadata_location)
+            .await,
+    )?
+    .ok_or_else(|| not_supported("registering tables", catalog.as_ref()))?;
+
+    let mut result = LoadTableResult::new(table.into_metadata());
+    result.metadata_location = Some(request.metadata_location);
+    Ok(Json(result))
+}
+
+async fn load_table(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace, table)): Path<(String, String, String)>,
+) -> Result<Json<LoadTableResult>, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let table = existing_table(catalog, &namespace, &table).await?;
+    Ok(Json(load_table_result(table).await))
+}
+
+async fn table_exists(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace, table)): Path<(String, String, String)>,
+) -> Result<StatusCode, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let identifier = Identifier::new(&parse_namespace(&namespace)?, &table);
+    if catalog.tabular_exists(&identifier).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(RestError::no_such_table(&identifier))
+    }
+}
+
+async fn drop_table(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace, table)): Path<(String, String, String)>,
+) -> Result<StatusCode, RestError> {
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    let identifier = Identifier::new(&parse_namespace(&namespace)?, &table);
+    if !catalog.tabular_exists(&identifier).await? {
+        return Err(RestError::no_such_table(&identifier));
+    }
+    supported(catalog.drop_table(&identifier).await)?
+        .ok_or_else(|| not_supported("dropping tables", catalog.as_ref()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Body of a table commit, the identifier is optional because it is part of the path
+#[derive(Debug, Deserialize)]
+struct CommitTableRequest {
+    identifier: Option<Identifier>,
+    requirements: Vec<TableRequirement>,
+    updates: Vec<TableUpdate>,
+}
+
+/// Applies the updates to the table if the requirements are met by its current metadata
+async fn commit_table(
+    State(catalog_list): State<Arc<dyn CatalogList>>,
+    Path((prefix, namespace, name)): Path<(String, String, String)>,
+    request: Result<Json<CommitTableRequest>, JsonRejection>,
+) -> Result<Json<CommitTableResponse>, RestError> {
+    let Json(request) = request?;
+    let catalog = catalog(catalog_list.as_ref(), &prefix)?;
+    // Loading the table also caches its metadata, which the catalogs require for updates
+    let table = existing_table(catalog.clone(), &namespace, &name).await?;
+    let identifier = table.identifier().clone();
+    if request
+        .identifier
+        .is_some_and(|request_identifier| request_identifier != identifier)
+    {
+        return Err(RestError::bad_request(format!(
+            "Identifier of the commit doesn't match table {identifier}."
+        )));
+    }
+    if !check_table_requirements(&request.requirements, table.metadata()) {
+        return Err(RestError::commit_failed(&identifier));
+    }
+
+    let commit = CommitTable {
+        identifier: identifier.clone(),
+        requirements: request.requirements.clone(),
+        updates: request.updates,
+    };
+    let table = match catalog.clone().update_table(commit).await {
+        Ok(table) => table,
+        // A commit that lands between the check above and the update fails the requirements
+        // check of the catalog, which is reported as a conflict if the requirements are no
+        // longer met
+        Err(err) => {
+            let table = existing_table(catalog, &namespace, &name).await?;
+            if !check_table_requirements(&request.requirements, table.metadata()) {
+                return Err(RestError::commit_failed(&identifier));
+            }
+            return Err(err.into());
+        }
+    };
+
+    let result = load_table_result(table).await;
+    let metadata_location = result.metadata_location.ok_or_else(|| {
+        RestError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ServiceFailureException",
+            format!("Metadata location of table {identifier} is unknown."),
+        )
+    })?;
+    Ok(Json(CommitTableResponse::new(
+        metadata_location,
+        result.metadata,
+    )))
+}
+
+fn catalog(catalog_list: &dyn CatalogList, prefix: &str) -> Result<Arc<dyn Catalog>, RestError> {
+    catalog_list.catalog(prefix).ok_or_else(|| {
+        RestError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchWarehouseException",
+            format!("Catalog {prefix} not found."),
+        )
+    })
+}
+
+/// Parse a namespace whose levels are separated by the unit separator
+fn parse_namespace(namespace: &str) -> Result<Namespace, RestError> {
+    let levels: Vec<String> = namespace
+        .split(NAMESPACE_SEPARATOR)
+        .map(ToString::to_string)
+        .collect();
+    try_namespace(&levels)
+}
+
+fn try_namespace(levels: &[String]) -> Result<Namespace, RestError> {
+    Namespace::try_new(levels)
+        .map_err(|err| RestError::bad_request(format!("Invalid namespace: {err}")))
+}
+
+async fn existing_namespace(
+    catalog: &dyn Catalog,
+    namespace: &str,
+) -> Result<Namespace, RestError> {
+    let namespace = parse_namespace(namespace)?;
+    if has_namespace(catalog, &namespace).await? {
+        Ok(namespace)
+    } else {
+        Err(RestError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchNamespaceException",
+            format!("Namespace {namespace} not found."),
+        ))
+    }
+}
+
+/// Tables and views of the namespace
+///
+/// The catalogs list tables and views together, so every entry is loaded to tell them apart.
+async fn list_tabulars(
+    catalog: Arc<dyn Catalog>,
+    namespace: &Namespace,
+) -> Result<(Vec<Identifier>, Vec<Identifier>), Error> {
+    let identifiers = catalog.list_tabulars(namespace).await?;
+    let tabulars = try_join_all(
+        identifiers
+            .iter()
+            .map(|identifier| catalog.clone().load_tabular(identifier)),
+    )
+    .await?;
+    let (tables, views): (Vec<_>, Vec<_>) = identifiers
+        .into_iter()
+        .zip(tabulars)
+        .partition(|(_, tabular)| matches!(tabular, Tabular::Table(_)));
+    Ok((
+        tables
+            .into_iter()
+            .map(|(identifier, _)| identifier)
+            .collect(),
+        views
+            .into_iter()
+            .map(|(identifier, _)| identifier)
+            .collect(),
+    ))
+}
+
+/// Checks if the namespace exists, by listing the namespaces if the catalog doesn't implement
+/// the check
+async fn has_namespace(catalog: &dyn Catalog, namespace: &Namespace) -> Result<bool, Error> {
+    match supported(catalog.namespace_exists(namespace).await)? {
+        Some(exists) => Ok(exists),
+        None => Ok(catalog.list_namespaces(None).await?.contains(namespace)),
+    }
+}
+
+/// Result of an operation that some of the bundled catalogs don't support
+///
+/// Returns `None` if the catalog doesn't support the operation, so that the endpoint can fall
+/// back or respond with an unsupported operation.
+fn supported<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::NotSupported(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn not_supported(operation: &str, catalog: &dyn Catalog) -> RestError {
+    RestError::new(
+        StatusCode::NOT_ACCEPTABLE,
+        "UnsupportedOperationException",
+        format!("Catalog {} doesn't support {operation}.", catalog.name()),
+    )
+}
+
+async fn existing_table(
+    catalog: Arc<dyn Catalog>,
+    namespace: &str,
+    table: &str,
+) -> Result<Table, RestError> {
+    let namespace = existing_namespace(catalog.as_ref(), namespace).await?;
+    let identifier = Identifier::new(&namespace, table);
+    if !catalog.tabular_exists(&identifier).await? {
+        return Err(RestError::no_such_table(&identifier));
+    }
+    match catalog.load_tabular(&identifier).await? {
+        Tabular::Table(table) => Ok(table),
+        _ => Err(RestError::no_such_table(&identifier)),
+    }
+}
+
+async fn load_table_result(table: Table) -> LoadTableResult {
+    let metadata_location = metadata_location(&table).await;
+    let mut result = LoadTableResult::new(table.into_metadata());
+    result.metadata_location = metadata_location;
+    result
+}
+
+/// Location of the metadata file of the table
+///
+/// The catalog API doesn't expose the location, so the metadata file is looked up among the
+/// metadata files of the table by its content, starting with the newest. Unlike the version hint,
+/// this isn't affected by commits that land after the table was loaded.
+async fn metadata_location(table: &Table) -> Option<String> {
+    let location = format!("{}/metadata", table.metadata().location);
+    let object_store = table.object_store();
+    let mut files: Vec<_> = object_store
+        .list(Some(&strip_prefix(&location).into()))
+        .try_collect()
+        .await
+        .ok()?;
+    files.sort_by_key(|file| Reverse(file.last_modified));
+    for file in files {
+        let Some(name) = file
+            .location
+            .filename()
+            .filter(|name| name.ends_with(".metadata.json"))
+        else {
+            continue;
+        };
+        let metadata_location = format!("{location}/{name}");
+        if let Ok(TabularMetadata::Table(metadata)) =
+            object_store.get_metadata(&metadata_location).await
+        {
+            if &metadata == table.metadata() {
+                return Some(metadata_location);
+            }
+        }
+    }
+    None
+}
+
+/// Error response of the REST catalog specification
+#[derive(Debug)]
+struct RestError {
+    status: StatusCode,
+    r#type: &'static str,
+    message: String,
+}
+
+impl RestError {
+    fn new(status: StatusCode, r#type: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            r#type,
+            message: message.into(),
+        }
+    }
+
+    fn bad_request(message: String) -> Self {
+        RestError::new(StatusCode::BAD_REQUEST, "BadRequestException", message)
+    }
+
+    fn already_exists(message: String) -> Self {
+        RestError::new(StatusCode::CONFLICT, "AlreadyExistsException", message)
+    }
+
+    fn commit_failed(identifier: &Identifier) -> Self {
+        RestError::new(
+            StatusCode::CONFLICT,
+            "CommitFailedException",
+            format!("Requirements of the commit to table {identifier} are not met."),
+        )
+    }
+
+    fn no_such_table(identifier: &Identifier) -> Self {
+        RestError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchTableException",
+            format!("Table {identifier} not found."),
+        )
+    }
+}
+
+impl From<Error> for RestError {
+    fn from(err: Error) -> Self {
+        let (status, r#type) = match err {
+            Error::InvalidFormat(_) => (StatusCode::BAD_REQUEST, "BadRequestException"),
+            Error::NotSupported(_) => (StatusCode::NOT_ACCEPTABLE, "UnsupportedOperationException"),
+            Error::NotFound(_) => (StatusCode::NOT_FOUND, "NotFoundException"),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "ServiceFailureException"),
+        };
+        RestError::new(status, r#type, err.to_string())
+    }
+}
+
+impl From<JsonRejection> for RestError {
+    fn from(rejection: JsonRejection) -> Self {
+        RestError::bad_request(rejection.body_text())
+    }
+}
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        let error = ErrorModel::new(
+            self.message,
+            self.r#type.to_owned(),
+            self.status.as_u16().into(),
+        );
+        (self.status, Json(IcebergErrorResponse::new(error))).into_response()
+    }
+}
+
+#[cfg(all(test, feature = "sql"))]
+mod tests {
+    use std::sync::Arc;
+
+    use iceberg_rust::{
+        catalog::CatalogList,
+        object_store::{store::IcebergStore, ObjectStoreBuilder},
+        spec::tabular::TabularMetadata,
+    };
+    use iceberg_sql_catalog::SqlCatalogList;
+    use object_store::memory::InMemory;
+    use reqwest::{Client, StatusCode};
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    use super::router;
+    use crate::{
+        catalog::SupportedCatalogList,
+        registry::{sql::SqlCatalogFactory, CatalogFactory},
+    };
+
+    /// REST catalog of the catalog `iceberg` with the namespace `db`, the URL of its namespace
+    /// and the object store of its tables
+    async fn serve() -> (String, Arc<InMemory>) {
+        let object_store = Arc::new(InMemory::new());
+        let sql_catalog_list = SqlCatalogList::new(
+            "sqlite://",
+            ObjectStoreBuilder::Memory(object_store.clone()),
+        )
+        .await
+        .unwrap();
+        let catalog_list: Arc<dyn CatalogList> = Arc::new(SupportedCatalogList::new(
+            Arc::new(sql_catalog_list),
+            SqlCatalogFactory.unsupported(),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/iceberg", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(catalog_list)).await });
+
+        let (status, _) = request(
+            Client::new()
+                .post(format!("{url}/namespaces"))
+                .json(&json!({"namespace": ["db"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        (format!("{url}/namespaces/db"), object_store)
+    }
+
+    async fn request(request: reqwest::RequestBuilder) -> (StatusCode, Value) {
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn create_table(client: &Client, url: &str) -> Value {
+        let (status, body) = request(client.post(format!("{url}/tables")).json(&json!({
+            "name": "t",
+            "location": "/warehouse/db/t",
+            "schema": {
+                "type": "struct",
+                "schema-id": 0,
+                "fields": [{"id": 1, "name": "id", "required": true, "type": "long"}],
+            },
+        })))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    /// Asserts that the metadata location of a response refers to the metadata of the response
+    async fn assert_metadata_location(object_store: &InMemory, body: &Value) {
+        let location = body["metadata-location"].as_str().unwrap();
+        let TabularMetadata::Table(metadata) = object_store.get_metadata(location).await.unwrap()
+        else {
+            panic!("Metadata {location} is not the metadata of a table")
+        };
+        assert_eq!(serde_json::to_value(metadata).unwrap(), body["metadata"]);
+    }
+
+    #[tokio::test]
+    async fn tables_are_created_and_loaded() {
+        let (url, object_store) = serve().await;
+        let client = Client::new();
+
+        let created = create_table(&client, &url).await;
+        assert_eq!(created["metadata"]["location"], "/warehouse/db/t");
+        assert_metadata_location(&object_store, &created).await;
+
+        let (status, loaded) = request(client.get(format!("{url}/tables/t"))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(loaded["metadata-location"], created["metadata-location"]);
+        assert_eq!(
+            loaded["metadata"]["table-uuid"],
+            created["metadata"]["table-uuid"]
+        );
+
+        let (status, body) = request(client.get(format!("{url}/tables/missing"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["type"], "NoSuchTableException");
+    }
+
+    #[tokio::test]
+    async fn commits_with_unmet_requirements_conflict() {
+        let (url, object_store) = serve().await;
+        let client = Client::new();
+        let created = create_table(&client, &url).await;
+        let commit = |uuid: &Value| {
+            json!({
+                "requirements": [{"type": "assert-table-uuid", "uuid": uuid}],
+                "updates": [{"action": "set-properties", "updates": {"owner": "frostbow"}}],
+            })
+        };
+
+        let (status, body) = request(
+            client
+                .post(format!("{url}/tables/t"))
+                .json(&commit(&json!("00000000-0000-0000-0000-000000000000"))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["type"], "CommitFailedException");
+
+        let (status, committed) = request(
+            client
+                .post(format!("{url}/tables/t"))
+                .json(&commit(&created["metadata"]["table-uuid"])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(committed["metadata"]["properties"]["owner"], "frostbow");
+        assert_ne!(committed["metadata-location"], created["metadata-location"]);
+        assert_metadata_location(&object_store, &committed).await;
+    }
+
+    #[tokio::test]
+    async fn tables_are_dropped() {
+        let (url, _) = serve().await;
+        let client = Client::new();
+        create_table(&client, &url).await;
+
+        let (status, _) = request(client.delete(format!("{url}/tables/t"))).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, body) = request(client.get(format!("{url}/tables/t"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["type"], "NoSuchTableException");
+        let (status, _) = request(client.delete(format!("{url}/tables/t"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
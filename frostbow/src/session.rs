@@ -0,0 +1,154 @@
+use std::{collections::HashMap, sync::Arc};
+
+use datafusion::{
+    execution::{context::SessionContext, runtime_env::RuntimeEnvBuilder, SessionStateBuilder},
+    logical_expr::ScalarUDF,
+    prelude::SessionConfig,
+};
+use datafusion_cli::{
+    object_storage::{instrumented::InstrumentedObjectStoreRegistry, AwsOptions},
+    print_format::PrintFormat,
+    print_options::{MaxRows, PrintOptions},
+};
+use datafusion_iceberg::{
+    catalog::catalog_list::IcebergCatalogList,
+    error::Error,
+    planner::{IcebergQueryPlanner, RefreshMaterializedView},
+};
+use iceberg_rust::catalog::CatalogList;
+
+use crate::{
+    catalog::FrostbowCatalogList,
+    credentials::AwsConfigOptions,
+    memory::MemoryOptions,
+    storage::{StorageOptions, StorageRegistry, StorageRouter},
+    Args, IcebergContext,
+};
+
+/// Builds the DataFusion session used by the frostbow cli
+pub struct FrostbowSessionBuilder {
+    catalog_list: Arc<dyn CatalogList>,
+    memory: MemoryOptions,
+    options: HashMap<String, String>,
+    storage_options: StorageOptions,
+    aws: AwsConfigOptions,
+    storage: Option<Arc<StorageRouter>>,
+}
+
+impl FrostbowSessionBuilder {
+    /// Create a new session builder for the given catalog list
+    pub fn new(catalog_list: Arc<dyn CatalogList>) -> Self {
+        Self {
+            catalog_list,
+            memory: MemoryOptions::default(),
+            options: HashMap::new(),
+            storage_options: StorageOptions::default(),
+            aws: AwsConfigOptions::default(),
+            storage: None,
+        }
+    }
+
+    /// Apply the session settings from the command line arguments
+    pub fn with_args(self, args: &Args) -> Self {
+        self.with_memory(args.memory.clone())
+            .with_options(args.datafusion.clone())
+            .with_storage_options(args.storage_options.clone())
+            .with_aws_options(args.aws.clone())
+            .with_storage(Arc::new(StorageRouter::from_args(args)))
+    }
+
+    /// Options of the memory pool and the spill files
+    pub fn with_memory(mut self, memory: MemoryOptions) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// DataFusion configuration options like `datafusion.execution.batch_size`
+    pub fn with_options(mut self, options: HashMap<String, String>) -> Self {
+        self.options.extend(options);
+        self
+    }
+
+    /// Options of the storage backends used for external tables
+    pub fn with_storage_options(mut self, storage_options: StorageOptions) -> Self {
+        self.storage_options = storage_options;
+        self
+    }
+
+    /// Options of the AWS Sdk used for external tables on S3
+    pub fn with_aws_options(mut self, aws: AwsConfigOptions) -> Self {
+        self.aws = aws;
+        self
+    }
+
+    /// Storage backends of the session, pass the storage router of the catalogs to share their
+    /// object stores with the session
+    ///
+    /// Defaults to a storage router of the storage and AWS options.
+    pub fn with_storage(mut self, storage: Arc<StorageRouter>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Build the session context
+    pub async fn build(self) -> Result<IcebergContext, Error> {
+        let catalog_list = Arc::new(IcebergCatalogList::new(self.catalog_list.clone()).await?);
+
+        if let Some(dir) = &self.memory.spill_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let memory_pool = self.memory.memory_pool();
+        let storage = self.storage.clone().unwrap_or_else(|| {
+            Arc::new(StorageRouter::new(None, &self.storage_options, &self.aws))
+        });
+        let runtime_env = RuntimeEnvBuilder::new()
+            .with_object_store_registry(Arc::new(StorageRegistry::new(
+                &self.storage_options,
+                storage,
+            )))
+            .with_memory_pool(memory_pool.clone())
+            .with_disk_manager_builder(self.memory.disk_manager_builder())
+            .build_arc()?;
+        memory_pool.set_disk_manager(runtime_env.disk_manager.clone());
+
+        tracing::info!("Initializing DataFusion session");
+        let config = SessionConfig::from_string_hash_map(&self.options)?
+            .with_information_schema(true)
+            .with_extension(Arc::new(self.memory))
+            .with_extension(memory_pool);
+        let catalog_list =
+            FrostbowCatalogList::new(catalog_list, &config.options().catalog.default_catalog);
+        let state = SessionStateBuilder::new()
+            .with_default_features()
+            .with_config(config)
+            .with_runtime_env(runtime_env)
+            .with_catalog_list(Arc::new(catalog_list))
+            .with_query_planner(Arc::new(IcebergQueryPlanner::new()))
+            .build();
+
+        let ctx = SessionContext::new_with_state(state);
+
+        ctx.register_table_options_extension(AwsOptions {
+            region: self.aws.region.clone(),
+            ..Default::default()
+        });
+        ctx.register_table_options_extension(self.storage_options.azure.clone());
+
+        ctx.register_udf(ScalarUDF::from(RefreshMaterializedView::new(
+            self.catalog_list,
+        )));
+
+        Ok(IcebergContext(ctx))
+    }
+}
+
+/// Print options used by the frostbow cli
+pub fn print_options() -> PrintOptions {
+    PrintOptions {
+        format: PrintFormat::Automatic,
+        quiet: true,
+        maxrows: MaxRows::Limited(10000),
+        color: true,
+        instrumented_registry: Arc::new(InstrumentedObjectStoreRegistry::new()),
+    }
+}
@@ -0,0 +1,411 @@
+use datafusion::{
+    config::SqlParserOptions,
+    error::DataFusionError,
+    sql::sqlparser::{
+        dialect::dialect_from_str,
+        keywords::{Keyword, RESERVED_FOR_TABLE_ALIAS},
+        tokenizer::{Location, Token, TokenWithSpan, Tokenizer, Word},
+    },
+};
+
+use crate::{metadata_tables::MetadataTable, time_travel::TableVersion};
+
+/// Rewrite the table references of frostbow into table names that DataFusion can parse
+///
+/// Metadata tables referenced with four parts, like `catalog.db.t.snapshots`, become
+/// `catalog.db."t$snapshots" AS snapshots`. The table of `SELECT * FROM db.t FOR SYSTEM_VERSION
+/// AS OF 42` becomes the versioned name of [`TableVersion::table_name`] with the alias `t`.
+/// Both are resolved by [`FrostbowCatalogList`](crate::catalog::FrostbowCatalogList). Statements
+/// that can't be tokenized are returned unchanged so the parser reports the error.
+pub fn rewrite_table_references(
+    sql: &str,
+    options: &SqlParserOptions,
+) -> Result<String, DataFusionError> {
+    let sql = rewrite_metadata_tables(sql, options);
+    rewrite_time_travel(&sql, options)
+}
+
+/// Split SQL into its statements at the semicolons outside of quotes and comments
+///
+/// The statements keep their terminating semicolon, statements without tokens besides comments
+/// are skipped. SQL that can't be tokenized is returned as one statement so the parser reports
+/// the error.
+pub fn split_statements(sql: &str, options: &SqlParserOptions) -> Vec<String> {
+    let tokens = dialect_from_str(options.dialect).and_then(|dialect| {
+        Tokenizer::new(dialect.as_ref(), sql)
+            .with_unescape(false)
+            .tokenize_with_location()
+            .ok()
+    });
+    let Some(tokens) = tokens else {
+        return vec![sql.to_owned()];
+    };
+
+    let offsets = LineOffsets::new(sql);
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut empty = true;
+    for token in tokens {
+        match token.token {
+            Token::SemiColon => {
+                let end = offsets.offset(token.span.end);
+                if !empty {
+                    statements.push(sql[start..end].trim().to_owned());
+                }
+                start = end;
+                empty = true;
+            }
+            Token::Whitespace(_) | Token::EOF => (),
+            _ => empty = false,
+        }
+    }
+    if !empty {
+        statements.push(sql[start..].trim().to_owned());
+    }
+    statements
+}
+
+/// Whether the first token of the statement, after whitespace and comments, is the keyword
+pub fn starts_with_keyword(sql: &str, options: &SqlParserOptions, keyword: Keyword) -> bool {
+    tokenize(sql, options).is_some_and(|tokens| {
+        matches!(
+            tokens.first().map(|token| &token.token),
+            Some(Token::Word(word)) if word.quote_style.is_none() && word.keyword == keyword
+        )
+    })
+}
+
+fn tokenize(sql: &str, options: &SqlParserOptions) -> Option<Vec<TokenWithSpan>> {
+    let dialect = dialect_from_str(options.dialect)?;
+    let tokens = Tokenizer::new(dialect.as_ref(), sql)
+        .with_unescape(false)
+        .tokenize_with_location()
+        .ok()?;
+    Some(
+        tokens
+            .into_iter()
+            .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+            .collect(),
+    )
+}
+
+/// Identifier as it is resolved by DataFusion
+fn normalize(word: &Word, options: &SqlParserOptions) -> String {
+    if word.quote_style.is_none() && options.enable_ident_normalization {
+        word.value.to_lowercase()
+    } else {
+        word.value.clone()
+    }
+}
+
+/// Rewrite the metadata tables referenced with four parts after `FROM` or `JOIN`
+fn rewrite_metadata_tables(sql: &str, options: &SqlParserOptions) -> String {
+    let Some(tokens) = tokenize(sql, options) else {
+        return sql.to_owned();
+    };
+    let word = |i: usize| match tokens.get(i).map(|token| &token.token) {
+        Some(Token::Word(word)) => Some(word),
+        _ => None,
+    };
+    let period = |i: usize| matches!(tokens.get(i).map(|token| &token.token), Some(Token::Period));
+
+    let offsets = LineOffsets::new(sql);
+    let mut rewritten = String::with_capacity(sql.len());
+    let mut copied = 0;
+    for i in 1..tokens.len() {
+        let follows_from = word(i - 1).is_some_and(|word| {
+            word.quote_style.is_none() && matches!(word.keyword, Keyword::FROM | Keyword::JOIN)
+        });
+        let reference = follows_from
+            && [i, i + 2, i + 4, i + 6]
+                .into_iter()
+                .all(|j| word(j).is_some())
+            && [i + 1, i + 3, i + 5].into_iter().all(period)
+            && !period(i + 7);
+        if !reference {
+            continue;
+        }
+        let (Some(table), Some(metadata_table)) = (word(i + 4), word(i + 6)) else {
+            continue;
+        };
+        let Some(metadata_table) = MetadataTable::from_name(&normalize(metadata_table, options))
+        else {
+            continue;
+        };
+        let start = offsets.offset(tokens[i + 4].span.start);
+        let end = offsets.offset(tokens[i + 6].span.end);
+        rewritten.push_str(&sql[copied..start]);
+        rewritten.push_str(&quote_ident(
+            &metadata_table.table_name(&normalize(table, options)),
+        ));
+        // The alias is added by the time travel rewrite if a version follows
+        let versioned = word(i + 7)
+            .is_some_and(|word| word.quote_style.is_none() && word.keyword == Keyword::FOR);
+        if !versioned
+            && !tokens
+                .get(i + 7)
+                .is_some_and(|token| is_alias(&token.token))
+        {
+            rewritten.push_str(" AS ");
+            rewritten.push_str(&quote_ident(metadata_table.name()));
+        }
+        copied = end;
+    }
+    rewritten.push_str(&sql[copied..]);
+    rewritten
+}
+
+/// Rewrite the `FOR ... AS OF` clauses of table references into versioned table names
+fn rewrite_time_travel(sql: &str, options: &SqlParserOptions) -> Result<String, DataFusionError> {
+    let Some(tokens) = tokenize(sql, options) else {
+        return Ok(sql.to_owned());
+    };
+
+    let offsets = LineOffsets::new(sql);
+    let mut rewritten = String::with_capacity(sql.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some((version, len)) = parse_version_clause(&tokens[i..])? else {
+            i += 1;
+            continue;
+        };
+        let Some(Token::Word(table)) = i.checked_sub(1).map(|j| &tokens[j].token) else {
+            return Err(DataFusionError::Plan(
+                "FOR ... AS OF has to follow a table name.".to_owned(),
+            ));
+        };
+        let value = normalize(table, options);
+        let start = offsets.offset(tokens[i - 1].span.start);
+        let end = offsets.offset(tokens[i + len - 1].span.end);
+        rewritten.push_str(&sql[copied..start]);
+        rewritten.push_str(&quote_ident(&version.table_name(&value)));
+        if !tokens
+            .get(i + len)
+            .is_some_and(|token| is_alias(&token.token))
+        {
+            rewritten.push_str(" AS ");
+            rewritten.push_str(&quote_ident(&value));
+        }
+        copied = end;
+        i += len;
+    }
+    rewritten.push_str(&sql[copied..]);
+    Ok(rewritten)
+}
+
+/// Parse a `FOR SYSTEM_VERSION | SYSTEM_TIME | VERSION AS OF <value>` clause at the start of
+/// the tokens, returns the version and the number of tokens of the clause
+fn parse_version_clause(
+    tokens: &[TokenWithSpan],
+) -> Result<Option<(TableVersion, usize)>, DataFusionError> {
+    let word = |i: usize, expected: &str| match tokens.get(i).map(|token| &token.token) {
+        Some(Token::Word(Word {
+            value,
+            quote_style: None,
+            ..
+        })) => value.eq_ignore_ascii_case(expected),
+        _ => false,
+    };
+    if !(word(0, "FOR") && word(2, "AS") && word(3, "OF")) {
+        return Ok(None);
+    }
+    let kind = if word(1, "SYSTEM_VERSION") {
+        "SYSTEM_VERSION"
+    } else if word(1, "SYSTEM_TIME") {
+        "SYSTEM_TIME"
+    } else if word(1, "VERSION") {
+        "VERSION"
+    } else {
+        return Ok(None);
+    };
+    // `FOR SYSTEM_TIME AS OF TIMESTAMP '...'` is accepted as well
+    let (value, len) = match tokens.get(4).map(|token| &token.token) {
+        Some(Token::Word(_)) if kind == "SYSTEM_TIME" && word(4, "TIMESTAMP") => {
+            (tokens.get(5).map(|token| &token.token), 6)
+        }
+        value => (value, 5),
+    };
+    let version = match (kind, value) {
+        ("SYSTEM_VERSION" | "VERSION", Some(Token::Number(id, _))) => {
+            id.parse().ok().map(TableVersion::Snapshot)
+        }
+        ("SYSTEM_TIME", Some(Token::SingleQuotedString(time))) => {
+            Some(TableVersion::Time(time.replace("''", "'")))
+        }
+        ("VERSION", Some(Token::SingleQuotedString(name))) => {
+            Some(TableVersion::Ref(name.replace("''", "'")))
+        }
+        _ => None,
+    };
+    match version {
+        Some(version) => Ok(Some((version, len))),
+        None => Err(DataFusionError::Plan(match kind {
+            "SYSTEM_VERSION" => "FOR SYSTEM_VERSION AS OF requires a snapshot id.".to_owned(),
+            "SYSTEM_TIME" => "FOR SYSTEM_TIME AS OF requires a timestamp string.".to_owned(),
+            _ => "FOR VERSION AS OF requires a snapshot id or the name of a branch or tag."
+                .to_owned(),
+        })),
+    }
+}
+
+/// Whether the token starts the alias of a table reference
+fn is_alias(token: &Token) -> bool {
+    match token {
+        Token::Word(word) => {
+            word.quote_style.is_some()
+                || word.keyword == Keyword::AS
+                || !RESERVED_FOR_TABLE_ALIAS.contains(&word.keyword)
+        }
+        _ => false,
+    }
+}
+
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Byte offsets of the lines of a statement, to map the locations of tokens to the statement
+struct LineOffsets<'a> {
+    sql: &'a str,
+    lines: Vec<usize>,
+}
+
+impl<'a> LineOffsets<'a> {
+    fn new(sql: &'a str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(sql.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { sql, lines }
+    }
+
+    fn offset(&self, location: Location) -> usize {
+        let start = self.lines[(location.line as usize).saturating_sub(1)];
+        self.sql[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(std::iter::once(self.sql.len()))
+            .nth((location.column as usize).saturating_sub(1))
+            .unwrap_or(self.sql.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(sql: &str) -> String {
+        rewrite_table_references(sql, &SqlParserOptions::default()).unwrap()
+    }
+
+    fn version(sql: &str) -> Result<Option<(TableVersion, usize)>, DataFusionError> {
+        parse_version_clause(&tokenize(sql, &SqlParserOptions::default()).unwrap())
+    }
+
+    #[test]
+    fn rewrites_metadata_tables() {
+        assert_eq!(
+            rewrite("SELECT * FROM iceberg.db.Orders.snapshots"),
+            "SELECT * FROM iceberg.db.\"orders$snapshots\" AS \"snapshots\""
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM iceberg.db.t.files f JOIN iceberg.db.t.refs AS r ON true"),
+            "SELECT * FROM iceberg.db.\"t$files\" f JOIN iceberg.db.\"t$refs\" AS r ON true"
+        );
+        // Not a metadata table or not a table reference
+        for sql in [
+            "SELECT * FROM iceberg.db.t.columns",
+            "SELECT a.b.c.snapshots FROM t",
+            "SELECT * FROM db.t",
+        ] {
+            assert_eq!(rewrite(sql), sql);
+        }
+    }
+
+    #[test]
+    fn rewrites_time_travel() {
+        let versioned =
+            |version: TableVersion, table: &str| quote_ident(&version.table_name(table));
+        assert_eq!(
+            rewrite("SELECT * FROM db.T FOR SYSTEM_VERSION AS OF 42 WHERE id = 1"),
+            format!(
+                "SELECT * FROM db.{} AS \"t\" WHERE id = 1",
+                versioned(TableVersion::Snapshot(42), "t")
+            )
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM \"T\" FOR VERSION AS OF 'main' x"),
+            format!(
+                "SELECT * FROM {} x",
+                versioned(TableVersion::Ref("main".into()), "T")
+            )
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM db.t.snapshots FOR SYSTEM_TIME AS OF '2024-01-01'"),
+            format!(
+                "SELECT * FROM db.t.{} AS \"snapshots\"",
+                versioned(TableVersion::Time("2024-01-01".into()), "snapshots")
+            )
+        );
+        assert!(rewrite_table_references(
+            "SELECT * FROM t FOR SYSTEM_TIME AS OF 42",
+            &SqlParserOptions::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn splits_statements() {
+        let split = |sql: &str| split_statements(sql, &SqlParserOptions::default());
+        assert_eq!(
+            split("SELECT 1; -- a; comment\nCALL system.expire_snapshots('t')"),
+            [
+                "SELECT 1;",
+                "-- a; comment\nCALL system.expire_snapshots('t')"
+            ]
+        );
+        assert_eq!(
+            split("SELECT ';' AS \"a;b\"; /* ; */ ;\n"),
+            ["SELECT ';' AS \"a;b\";"]
+        );
+        assert_eq!(
+            split("SELECT 'unterminated; 1"),
+            ["SELECT 'unterminated; 1"]
+        );
+    }
+
+    #[test]
+    fn detects_keywords_after_comments() {
+        let call =
+            |sql: &str| starts_with_keyword(sql, &SqlParserOptions::default(), Keyword::CALL);
+        assert!(call("call system.expire_snapshots('t')"));
+        assert!(call("-- comment\nCALL system.expire_snapshots('t')"));
+        assert!(call("/* comment */ CALL system.expire_snapshots('t')"));
+        assert!(!call("SELECT 1; CALL system.expire_snapshots('t')"));
+        assert!(!call("\"CALL\""));
+    }
+
+    #[test]
+    fn parses_version_clauses() {
+        assert_eq!(
+            version("FOR SYSTEM_VERSION AS OF 42").unwrap(),
+            Some((TableVersion::Snapshot(42), 5))
+        );
+        assert_eq!(
+            version("for version as of 42").unwrap(),
+            Some((TableVersion::Snapshot(42), 5))
+        );
+        assert_eq!(
+            version("FOR VERSION AS OF 'it''s'").unwrap(),
+            Some((TableVersion::Ref("it's".into()), 5))
+        );
+        assert_eq!(
+            version("FOR SYSTEM_TIME AS OF TIMESTAMP '2024-01-01 00:00:00' AS t").unwrap(),
+            Some((TableVersion::Time("2024-01-01 00:00:00".into()), 6))
+        );
+        assert_eq!(version("FOR UPDATE").unwrap(), None);
+        assert_eq!(version("\"FOR\" SYSTEM_VERSION AS OF 42").unwrap(), None);
+        assert!(version("FOR SYSTEM_VERSION AS OF 'main'").is_err());
+        assert!(version("FOR SYSTEM_TIME AS OF 42").is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use iceberg_rust::{
+    catalog::{
+        commit::{CommitTable, CommitView},
+        create::{CreateMaterializedView, CreateTable, CreateView},
+        identifier::Identifier,
+        namespace::Namespace,
+        tabular::Tabular,
+        Catalog, CatalogList,
+    },
+    error::Error,
+    materialized_view::MaterializedView,
+    object_store::ObjectStoreBuilder,
+    table::Table,
+    view::View,
+};
+use iceberg_sql_catalog::SqlCatalogList;
+use object_store::memory::InMemory;
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions},
+    AnyPool, Row,
+};
+
+use crate::storage::StorageRouter;
+
+/// SQL catalogs whose tables are stored in different storage backends
+///
+/// The SQL catalog builds the object stores of all tables with a single object store builder.
+/// Therefore every storage backend gets its own SQL catalog and each table is handled by the
+/// catalog of the storage backend of its metadata location. Namespaces are handled by a catalog
+/// that doesn't access the storage. As every catalog opens its own connection pool, the database
+/// can't be in-memory.
+#[derive(Debug)]
+pub struct SqlStorageCatalogList {
+    shared: Arc<Shared>,
+    metadata: SqlCatalogList,
+}
+
+/// State shared by the catalogs of the catalog list
+#[derive(Debug)]
+struct Shared {
+    url: String,
+    pool: AnyPool,
+    storage: Arc<StorageRouter>,
+    catalog_lists: Mutex<HashMap<String, Arc<SqlCatalogList>>>,
+}
+
+impl SqlStorageCatalogList {
+    pub async fn new(url: &str, storage: Arc<StorageRouter>) -> Result<Self, Error> {
+        if is_in_memory(url) {
+            return Err(Error::NotSupported(format!(
+                "In-memory database {url} without a storage backend, set the storage backend with -s"
+            )));
+        }
+
+        let metadata =
+            SqlCatalogList::new(url, ObjectStoreBuilder::Memory(Arc::new(InMemory::new())))
+                .await
+                .map_err(Error::from)?;
+
+        install_default_drivers();
+        let mut pool_options = AnyPoolOptions::new();
+        if url.starts_with("sqlite") {
+            pool_options = pool_options.max_connections(1);
+        }
+        let pool = pool_options
+            .connect(url)
+            .await
+            .map_err(|err| Error::External(Box::new(err)))?;
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                url: url.to_owned(),
+                pool,
+                storage,
+                catalog_lists: Mutex::new(HashMap::new()),
+            }),
+            metadata,
+        })
+    }
+}
+
+#[async_trait]
+impl CatalogList for SqlStorageCatalogList {
+    fn catalog(&self, name: &str) -> Option<Arc<dyn Catalog>> {
+        Some(Arc::new(SqlStorageCatalog {
+            name: name.to_owned(),
+            shared: self.shared.clone(),
+            metadata: self.metadata.catalog(name)?,
+            catalogs: RwLock::new(HashMap::new()),
+        }))
+    }
+
+    async fn list_catalogs(&self) -> Vec<String> {
+        self.metadata.list_catalogs().await
+    }
+}
+
+/// SQL catalog that handles each table with the catalog of its storage backend
+#[derive(Debug)]
+struct SqlStorageCatalog {
+    name: String,
+    shared: Arc<Shared>,
+    metadata: Arc<dyn Catalog>,
+    catalogs: RwLock<HashMap<String, Arc<dyn Catalog>>>,
+}
+
+impl SqlStorageCatalog {
+    /// Catalog of the storage backend of a location
+    ///
+    /// The catalogs are kept for the lifetime of this catalog, so that tables are committed by
+    /// the catalog that loaded them.
+    async fn storage_catalog(&self, location: &str) -> Result<Arc<dyn Catalog>, Error> {
+        let storage = self.shared.storage.storage(location)?;
+        if let Some(catalog) = self
+            .catalogs
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(storage)
+        {
+            return Ok(catalog.clone());
+        }
+
+        let catalog_list = {
+            let mut catalog_lists = self.shared.catalog_lists.lock().await;
+            match catalog_lists.get(storage) {
+                Some(catalog_list) => catalog_list.clone(),
+                None => {
+                    let builder = self.shared.storage.builder(storage).await?;
+                    let catalog_list = Arc::new(
+                        SqlCatalogList::new(&self.shared.url, builder)
+                            .await
+                            .map_err(Error::from)?,
+                    );
+                    catalog_lists.insert(storage.to_owned(), catalog_list.clone());
+                    catalog_list
+                }
+            }
+        };
+        let catalog = catalog_list
+            .catalog(&self.name)
+            .ok_or(Error::CatalogNotFound)?;
+
+        Ok(self
+            .catalogs
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(storage.to_owned())
+            .or_insert(catalog)
+            .clone())
+    }
+
+    /// Catalog of the storage backend of an existing tabular
+    async fn tabular_catalog(&self, identifier: &Identifier) -> Result<Arc<dyn Catalog>, Error> {
+        // Postgres doesn't accept the placeholders of sqlite and mysql
+        let query = if self.shared.url.starts_with("postgres") {
+            "select metadata_location from iceberg_tables where catalog_name = $1 and table_namespace = $2 and table_name = $3;"
+        } else {
+            "select metadata_location from iceberg_tables where catalog_name = ? and table_namespace = ? and table_name = ?;"
+        };
+        let row = sqlx::query(query)
+            .bind(&self.name)
+            .bind(identifier.namespace().to_string())
+            .bind(identifier.name())
+            .fetch_optional(&self.shared.pool)
+            .await
+            .map_err(|err| Error::External(Box::new(err)))?
+            // Missing tabulars are reported like the SQL catalog does
+            .ok_or(Error::CatalogNotFound)?;
+        let location: String = row
+            .try_get(0)
+            .map_err(|err| Error::External(Box::new(err)))?;
+        self.storage_catalog(&location).await
+    }
+}
+
+/// Whether a URL refers to an in-memory sqlite database, which is separate for every pool
+fn is_in_memory(url: &str) -> bool {
+    let Some(database) = url.strip_prefix("sqlite:") else {
+        return false;
+    };
+    let (path, query) = database.split_once('?').unwrap_or((database, ""));
+    let path = path.trim_start_matches('/');
+    path.is_empty() || path == ":memory:" || query.split('&').any(|x| x == "mode=memory")
+}
+
+#[async_trait]
+impl Catalog for SqlStorageCatalog {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn create_namespace(
+        &self,
+        namespace: &Namespace,
+        properties: Option<HashMap<String, String>>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.metadata.create_namespace(namespace, properties).await
+    }
+    async fn drop_namespace(&self, namespace: &Namespace) -> Result<(), Error> {
+        self.metadata.drop_namespace(namespace).await
+    }
+    async fn load_namespace(
+        &self,
+        namespace: &Namespace,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.metadata.load_namespace(namespace).await
+    }
+    async fn update_namespace(
+        &self,
+        namespace: &Namespace,
+        updates: Option<HashMap<String, String>>,
+        removals: Option<Vec<String>>,
+    ) -> Result<(), Error> {
+        self.metadata
+            .update_namespace(namespace, updates, removals)
+            .await
+    }
+    async fn namespace_exists(&self, namespace: &Namespace) -> Result<bool, Error> {
+        self.metadata.namespace_exists(namespace).await
+    }
+    async fn list_tabulars(&self, namespace: &Namespace) -> Result<Vec<Identifier>, Error> {
+        self.metadata.list_tabulars(namespace).await
+    }
+    async fn list_namespaces(&self, parent: Option<&str>) -> Result<Vec<Namespace>, Error> {
+        self.metadata.list_namespaces(parent).await
+    }
+    async fn tabular_exists(&self, identifier: &Identifier) -> Result<bool, Error> {
+        self.metadata.tabular_exists(identifier).await
+    }
+    async fn drop_table(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.metadata.drop_table(identifier).await
+    }
+    async fn drop_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.metadata.drop_view(identifier).await
+    }
+    async fn drop_materialized_view(&self, identifier: &Identifier) -> Result<(), Error> {
+        self.metadata.drop_materialized_view(identifier).await
+    }
+    async fn load_tabular(self: Arc<Self>, identifier: &Identifier) -> Result<Tabular, Error> {
+        self.tabular_catalog(identifier)
+            .await?
+            .load_tabular(identifier)
+            .await
+    }
+    async fn create_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_table: CreateTable,
+    ) -> Result<Table, Error> {
+        let location = create_table
+            .location
+            .clone()
+            .ok_or(Error::NotFound(format!("Location of table {identifier}")))?;
+        self.storage_catalog(&location)
+            .await?
+            .create_table(identifier, create_table)
+            .await
+    }
+    async fn create_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateView<Option<()>>,
+    ) -> Result<View, Error> {
+        let location = create_view
+            .location
+            .clone()
+            .ok_or(Error::NotFound(format!("Location of view {identifier}")))?;
+        self.storage_catalog(&location)
+            .await?
+            .create_view(identifier, create_view)
+            .await
+    }
+    async fn create_materialized_view(
+        self: Arc<Self>,
+        identifier: Identifier,
+        create_view: CreateMaterializedView,
+    ) -> Result<MaterializedView, Error> {
+        let location = create_view.location.clone().ok_or(Error::NotFound(format!(
+            "Location of materialized view {identifier}"
+        )))?;
+        self.storage_catalog(&location)
+            .await?
+            .create_materialized_view(identifier, create_view)
+            .await
+    }
+    async fn update_table(self: Arc<Self>, commit: CommitTable) -> Result<Table, Error> {
+        self.tabular_catalog(&commit.identifier)
+            .await?
+            .update_table(commit)
+            .await
+    }
+    async fn update_view(self: Arc<Self>, commit: CommitView<Option<()>>) -> Result<View, Error> {
+        self.tabular_catalog(&commit.identifier)
+            .await?
+            .update_view(commit)
+            .await
+    }
+    async fn update_materialized_view(
+        self: Arc<Self>,
+        commit: CommitView<Identifier>,
+    ) -> Result<MaterializedView, Error> {
+        self.tabular_catalog(&commit.identifier)
+            .await?
+            .update_materialized_view(commit)
+            .await
+    }
+    async fn register_table(
+        self: Arc<Self>,
+        identifier: Identifier,
+        metadata_location: &str,
+    ) -> Result<Table, Error> {
+        self.storage_catalog(metadata_location)
+            .await?
+            .register_table(identifier, metadata_location)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use iceberg_rust::{
+        catalog::{identifier::Identifier, namespace::Namespace, tabular::Tabular, CatalogList},
+        error::Error,
+        spec::{
+            schema::Schema,
+            types::{PrimitiveType, StructField, Type},
+        },
+        table::Table,
+    };
+
+    use crate::storage::StorageRouter;
+
+    use super::{is_in_memory, SqlStorageCatalogList};
+
+    fn storage() -> Arc<StorageRouter> {
+        Arc::new(StorageRouter::new(
+            None,
+            &Default::default(),
+            &Default::default(),
+        ))
+    }
+
+    #[test]
+    fn in_memory_urls_are_detected() {
+        assert!(is_in_memory("sqlite::memory:"));
+        assert!(is_in_memory("sqlite://"));
+        assert!(is_in_memory("sqlite://:memory:"));
+        assert!(is_in_memory("sqlite://catalog?mode=memory&cache=shared"));
+        assert!(!is_in_memory("sqlite:catalog.db"));
+        assert!(!is_in_memory(
+            "sqlite:///var/lib/frostbow/catalog.db?mode=rwc"
+        ));
+        assert!(!is_in_memory("postgres://localhost/memory"));
+    }
+
+    #[tokio::test]
+    async fn in_memory_database_is_rejected() {
+        let result = SqlStorageCatalogList::new("sqlite::memory:", storage()).await;
+        assert!(matches!(result, Err(Error::NotSupported(_))));
+    }
+
+    #[tokio::test]
+    async fn tables_are_loaded_by_the_catalog_of_their_storage() {
+        let dir = std::env::temp_dir().join(format!("frostbow-{:x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = format!("sqlite://{}/catalog.db?mode=rwc", dir.display());
+        let catalog_list = SqlStorageCatalogList::new(&url, storage()).await.unwrap();
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        catalog
+            .create_namespace(&Namespace::try_new(&["db".to_owned()]).unwrap(), None)
+            .await
+            .unwrap();
+        let schema = Schema::builder()
+            .with_struct_field(StructField::new(
+                1,
+                "id",
+                true,
+                Type::Primitive(PrimitiveType::Long),
+                None,
+            ))
+            .build()
+            .unwrap();
+        // Backslashes are not escaped in the query
+        Table::builder()
+            .with_name("t\\")
+            .with_location(dir.join("db/t").to_str().unwrap())
+            .with_schema(schema)
+            .build(&["db".to_owned()], catalog.clone())
+            .await
+            .unwrap();
+
+        let catalog = catalog_list.catalog("iceberg").unwrap();
+        let tabular = catalog
+            .clone()
+            .load_tabular(&Identifier::new(&["db".to_owned()], "t\\"))
+            .await
+            .unwrap();
+        assert!(matches!(tabular, Tabular::Table(_)));
+        let missing = catalog
+            .load_tabular(&Identifier::new(&["db".to_owned()], "t'"))
+            .await;
+        assert!(matches!(missing, Err(Error::CatalogNotFound)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
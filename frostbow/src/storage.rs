@@ -0,0 +1,795 @@
+use std::{any::Any, collections::HashMap, fmt::Display, sync::Arc};
+
+use datafusion::{
+    common::{
+        config::{ConfigEntry, ConfigExtension, ConfigField, ExtensionOptions, Visit},
+        config_err,
+    },
+    datasource::listing::ListingTableUrl,
+    error::{DataFusionError, Result},
+    execution::{
+        context::SessionContext,
+        object_store::{DefaultObjectStoreRegistry, ObjectStoreRegistry},
+    },
+};
+use futures::lock::Mutex;
+use iceberg_rust::{
+    error::Error,
+    object_store::{Bucket, ObjectStoreBuilder},
+};
+use object_store::{
+    aws::{AmazonS3Builder, AmazonS3ConfigKey, S3ConditionalPut},
+    azure::{AzureConfigKey, MicrosoftAzureBuilder},
+    gcp::{GcpCredential, GoogleCloudStorageBuilder},
+    Certificate, ClientConfigKey, ClientOptions, ObjectStore, StaticCredentialProvider,
+};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    credentials::{AwsConfigOptions, GcpCredentialProvider},
+    get_storage, storage_from_location, Args,
+};
+
+/// URL schemes of Azure Blob Storage and ADLS Gen2
+pub static AZURE_SCHEMES: [&str; 5] = ["az", "adl", "azure", "abfs", "abfss"];
+
+/// Options to configure the storage backends
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+pub struct StorageOptions {
+    #[clap(flatten)]
+    #[serde(default)]
+    pub s3: S3Options,
+    #[clap(flatten)]
+    #[serde(default)]
+    pub gcs: GcsOptions,
+    #[clap(flatten)]
+    #[serde(default)]
+    pub azure: AzureOptions,
+}
+
+impl StorageOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: StorageOptions) -> Self {
+        Self {
+            s3: self.s3.or(other.s3),
+            gcs: self.gcs.or(other.gcs),
+            azure: self.azure.or(other.azure),
+        }
+    }
+}
+
+/// Addressing style of S3 requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum S3AddressingStyle {
+    /// `https://endpoint/bucket/key`
+    Path,
+    /// `https://bucket.endpoint/key`
+    Virtual,
+}
+
+/// Options for S3 and S3-compatible object stores like MinIO or Ceph
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "S3")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct S3Options {
+    /// Endpoint URL of the S3-compatible object store
+    #[clap(id = "s3-endpoint", long = "s3-endpoint")]
+    pub endpoint: Option<String>,
+    /// Use path-style or virtual-hosted-style requests
+    #[clap(id = "s3-addressing-style", long = "s3-addressing-style", value_enum)]
+    pub addressing_style: Option<S3AddressingStyle>,
+    /// Allow requests over plain HTTP
+    #[clap(id = "s3-allow-http", long = "s3-allow-http", num_args = 0..=1, default_missing_value = "true")]
+    pub allow_http: Option<bool>,
+    /// Path to a PEM bundle of additional root certificates
+    #[clap(id = "s3-ca-bundle", long = "s3-ca-bundle")]
+    pub ca_bundle: Option<String>,
+    /// Sign requests with `UNSIGNED-PAYLOAD` instead of the SHA-256 hash of the body
+    #[clap(id = "s3-unsigned-payload", long = "s3-unsigned-payload", num_args = 0..=1, default_missing_value = "true")]
+    pub unsigned_payload: Option<bool>,
+    /// Use conditional put requests, disable for stores that don't support them
+    #[clap(id = "s3-conditional-put", long = "s3-conditional-put", num_args = 0..=1, default_missing_value = "true")]
+    pub conditional_put: Option<bool>,
+    /// Seconds before the expiry of the credentials at which they are refreshed
+    #[clap(
+        id = "s3-credentials-refresh-window",
+        long = "s3-credentials-refresh-window"
+    )]
+    pub credentials_refresh_window: Option<u64>,
+}
+
+impl S3Options {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: S3Options) -> Self {
+        Self {
+            endpoint: self.endpoint.or(other.endpoint),
+            addressing_style: self.addressing_style.or(other.addressing_style),
+            allow_http: self.allow_http.or(other.allow_http),
+            ca_bundle: self.ca_bundle.or(other.ca_bundle),
+            unsigned_payload: self.unsigned_payload.or(other.unsigned_payload),
+            conditional_put: self.conditional_put.or(other.conditional_put),
+            credentials_refresh_window: self
+                .credentials_refresh_window
+                .or(other.credentials_refresh_window),
+        }
+    }
+
+    /// Apply the options to an object store builder
+    pub fn apply(&self, mut builder: AmazonS3Builder) -> Result<AmazonS3Builder, Error> {
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let certificates = Certificate::from_pem_bundle(&std::fs::read(ca_bundle)?)?;
+            let client_options = certificates.into_iter().fold(
+                client_options(&builder),
+                ClientOptions::with_root_certificate,
+            );
+            builder = builder.with_client_options(client_options);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+        if let Some(addressing_style) = self.addressing_style {
+            builder = builder
+                .with_virtual_hosted_style_request(addressing_style == S3AddressingStyle::Virtual);
+        }
+        if let Some(allow_http) = self.allow_http {
+            builder = builder.with_allow_http(allow_http);
+        }
+        if let Some(unsigned_payload) = self.unsigned_payload {
+            builder = builder.with_unsigned_payload(unsigned_payload);
+        }
+        if let Some(false) = self.conditional_put {
+            builder = builder.with_conditional_put(S3ConditionalPut::Disabled);
+        }
+        Ok(builder)
+    }
+}
+
+/// Client options of the builder, like the proxy and the timeouts set in the environment
+///
+/// The builder has no getter for its client options, so they are copied by their keys.
+fn client_options(builder: &AmazonS3Builder) -> ClientOptions {
+    [
+        ClientConfigKey::AllowHttp,
+        ClientConfigKey::AllowInvalidCertificates,
+        ClientConfigKey::ConnectTimeout,
+        ClientConfigKey::DefaultContentType,
+        ClientConfigKey::Http1Only,
+        ClientConfigKey::Http2KeepAliveInterval,
+        ClientConfigKey::Http2KeepAliveTimeout,
+        ClientConfigKey::Http2KeepAliveWhileIdle,
+        ClientConfigKey::Http2MaxFrameSize,
+        ClientConfigKey::Http2Only,
+        ClientConfigKey::PoolIdleTimeout,
+        ClientConfigKey::PoolMaxIdlePerHost,
+        ClientConfigKey::ProxyUrl,
+        ClientConfigKey::ProxyCaCertificate,
+        ClientConfigKey::ProxyExcludes,
+        ClientConfigKey::RandomizeAddresses,
+        ClientConfigKey::Timeout,
+        ClientConfigKey::UserAgent,
+    ]
+    .into_iter()
+    .fold(ClientOptions::new(), |options, key| {
+        match builder.get_config_value(&AmazonS3ConfigKey::Client(key)) {
+            Some(value) => options.with_config(key, value),
+            None => options,
+        }
+    })
+}
+
+/// Default endpoint of the fake-gcs-server emulator
+static GCS_EMULATOR_ENDPOINT: &str = "http://localhost:4443";
+
+/// Options for Google Cloud Storage
+///
+/// Without a service account the application default credentials are used.
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "GCS")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GcsOptions {
+    /// Path to a service account key file
+    #[clap(id = "gcs-service-account-path", long = "gcs-service-account-path")]
+    pub service_account_path: Option<String>,
+    /// Service account key in JSON format
+    #[clap(id = "gcs-service-account-key", long = "gcs-service-account-key")]
+    pub service_account_key: Option<String>,
+    /// Custom endpoint of the storage service
+    #[clap(id = "gcs-endpoint", long = "gcs-endpoint")]
+    pub endpoint: Option<String>,
+    /// Use an unauthenticated emulator like fake-gcs-server
+    #[clap(id = "gcs-use-emulator", long = "gcs-use-emulator", num_args = 0..=1, default_missing_value = "true")]
+    pub use_emulator: Option<bool>,
+}
+
+impl GcsOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: GcsOptions) -> Self {
+        Self {
+            service_account_path: self.service_account_path.or(other.service_account_path),
+            service_account_key: self.service_account_key.or(other.service_account_key),
+            endpoint: self.endpoint.or(other.endpoint),
+            use_emulator: self.use_emulator.or(other.use_emulator),
+        }
+    }
+
+    /// Create an object store builder from the environment and the options
+    pub async fn builder(&self) -> Result<GoogleCloudStorageBuilder, Error> {
+        let builder = GoogleCloudStorageBuilder::from_env();
+
+        if let Some(true) = self.use_emulator {
+            let endpoint = self.endpoint.as_deref().unwrap_or(GCS_EMULATOR_ENDPOINT);
+            return Ok(builder
+                .with_base_url(endpoint)
+                .with_client_options(ClientOptions::new().with_allow_http(true))
+                .with_credentials(Arc::new(StaticCredentialProvider::new(GcpCredential {
+                    bearer: String::new(),
+                }))));
+        }
+
+        let credentials = match (&self.service_account_path, &self.service_account_key) {
+            (Some(path), _) => GcpCredentialProvider::from_service_account_file(path),
+            (None, Some(key)) => GcpCredentialProvider::from_service_account_key(key),
+            (None, None) => GcpCredentialProvider::application_default().await,
+        }
+        .map_err(|err| Error::External(Box::new(err)))?;
+
+        let builder = builder.with_credentials(Arc::new(credentials));
+        Ok(match &self.endpoint {
+            Some(endpoint) => builder.with_base_url(endpoint),
+            None => builder,
+        })
+    }
+}
+
+/// Options for Azure Blob Storage and ADLS Gen2
+#[derive(Debug, Default, Clone, clap::Args, Deserialize)]
+#[clap(next_help_heading = "Azure")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AzureOptions {
+    /// Storage account name
+    #[clap(id = "azure-account", long = "azure-account")]
+    pub account: Option<String>,
+    /// Storage account key
+    #[clap(id = "azure-access-key", long = "azure-access-key")]
+    pub access_key: Option<String>,
+    /// Shared access signature
+    #[clap(id = "azure-sas-token", long = "azure-sas-token")]
+    pub sas_token: Option<String>,
+    /// Client id of the service principal
+    #[clap(id = "azure-client-id", long = "azure-client-id")]
+    pub client_id: Option<String>,
+    /// Client secret of the service principal
+    #[clap(id = "azure-client-secret", long = "azure-client-secret")]
+    pub client_secret: Option<String>,
+    /// Tenant id of the service principal
+    #[clap(id = "azure-tenant-id", long = "azure-tenant-id")]
+    pub tenant_id: Option<String>,
+    /// Custom endpoint of the storage account
+    #[clap(id = "azure-endpoint", long = "azure-endpoint")]
+    pub endpoint: Option<String>,
+    /// Use the Azurite storage emulator
+    #[clap(id = "azure-use-emulator", long = "azure-use-emulator", num_args = 0..=1, default_missing_value = "true")]
+    pub use_emulator: Option<bool>,
+}
+
+impl AzureOptions {
+    /// Use the values of `other` for all options that are not set
+    pub fn or(self, other: AzureOptions) -> Self {
+        Self {
+            account: self.account.or(other.account),
+            access_key: self.access_key.or(other.access_key),
+            sas_token: self.sas_token.or(other.sas_token),
+            client_id: self.client_id.or(other.client_id),
+            client_secret: self.client_secret.or(other.client_secret),
+            tenant_id: self.tenant_id.or(other.tenant_id),
+            endpoint: self.endpoint.or(other.endpoint),
+            use_emulator: self.use_emulator.or(other.use_emulator),
+        }
+    }
+
+    /// Create an object store builder from the environment and the options
+    pub fn builder(&self) -> MicrosoftAzureBuilder {
+        let mut builder = MicrosoftAzureBuilder::from_env();
+        if let Some(account) = &self.account {
+            builder = builder.with_account(account);
+        }
+        if let Some(access_key) = &self.access_key {
+            builder = builder.with_access_key(access_key);
+        }
+        if let Some(sas_token) = &self.sas_token {
+            builder = builder.with_config(AzureConfigKey::SasKey, sas_token);
+        }
+        if let Some(client_id) = &self.client_id {
+            builder = builder.with_client_id(client_id);
+        }
+        if let Some(client_secret) = &self.client_secret {
+            builder = builder.with_client_secret(client_secret);
+        }
+        if let Some(tenant_id) = &self.tenant_id {
+            builder = builder.with_tenant_id(tenant_id);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder.with_endpoint(endpoint.clone());
+        }
+        if let Some(use_emulator) = self.use_emulator {
+            builder = builder.with_use_emulator(use_emulator);
+        }
+        builder
+    }
+
+    /// Create an object store for the container of the URL
+    pub fn build(&self, url: &Url) -> Result<Arc<dyn ObjectStore>> {
+        Ok(Arc::new(self.builder().with_url(url.as_str()).build()?))
+    }
+}
+
+impl ExtensionOptions for AzureOptions {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn cloned(&self) -> Box<dyn ExtensionOptions> {
+        Box::new(self.clone())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let (_key, rem) = key.split_once('.').unwrap_or((key, ""));
+        match rem {
+            "account" => self.account.set(rem, value),
+            "access_key" => self.access_key.set(rem, value),
+            "sas_token" => self.sas_token.set(rem, value),
+            "client_id" => self.client_id.set(rem, value),
+            "client_secret" => self.client_secret.set(rem, value),
+            "tenant_id" => self.tenant_id.set(rem, value),
+            "endpoint" => self.endpoint.set(rem, value),
+            "use_emulator" => self.use_emulator.set(rem, value),
+            _ => config_err!("Config value \"{}\" not found on AzureOptions", rem),
+        }
+    }
+
+    fn entries(&self) -> Vec<ConfigEntry> {
+        struct Visitor(Vec<ConfigEntry>);
+
+        impl Visit for Visitor {
+            fn some<V: Display>(&mut self, key: &str, value: V, description: &'static str) {
+                self.0.push(ConfigEntry {
+                    key: key.to_string(),
+                    value: Some(value.to_string()),
+                    description,
+                })
+            }
+
+            fn none(&mut self, key: &str, description: &'static str) {
+                self.0.push(ConfigEntry {
+                    key: key.to_string(),
+                    value: None,
+                    description,
+                })
+            }
+        }
+
+        let mut v = Visitor(vec![]);
+        self.account.visit(&mut v, "account", "");
+        self.access_key.visit(&mut v, "access_key", "");
+        self.sas_token.visit(&mut v, "sas_token", "");
+        self.client_id.visit(&mut v, "client_id", "");
+        self.client_secret.visit(&mut v, "client_secret", "");
+        self.tenant_id.visit(&mut v, "tenant_id", "");
+        self.endpoint.visit(&mut v, "endpoint", "");
+        self.use_emulator.visit(&mut v, "use_emulator", "");
+        v.0
+    }
+}
+
+impl ConfigExtension for AzureOptions {
+    const PREFIX: &'static str = "azure";
+}
+
+/// Object store builders of the storage backends
+///
+/// The storage backend of a table is selected by the scheme of its location, unless a storage
+/// backend is set explicitly. The builders are created on first use.
+#[derive(Debug)]
+pub struct StorageRouter {
+    storage: Option<String>,
+    options: StorageOptions,
+    aws: AwsConfigOptions,
+    builders: Mutex<HashMap<String, ObjectStoreBuilder>>,
+}
+
+impl StorageRouter {
+    pub fn new(storage: Option<&str>, options: &StorageOptions, aws: &AwsConfigOptions) -> Self {
+        Self {
+            storage: storage.map(ToOwned::to_owned),
+            options: options.clone(),
+            aws: aws.clone(),
+            builders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Storage router of the storage backend and the options passed on the command line
+    pub fn from_args(args: &Args) -> Self {
+        Self::new(args.storage.as_deref(), &args.storage_options, &args.aws)
+    }
+
+    /// Explicitly set storage backend that is used for all tables
+    pub fn explicit(&self) -> Option<&str> {
+        self.storage.as_deref()
+    }
+
+    /// Storage backend of a location, the explicitly set storage backend takes precedence
+    pub fn storage<'a>(&'a self, location: &str) -> Result<&'a str, Error> {
+        self.storage
+            .as_deref()
+            .or_else(|| storage_from_location(location))
+            .ok_or(Error::NotFound(format!(
+                "Storage backend for location {location}"
+            )))
+    }
+
+    /// Object store builder of a storage backend
+    pub async fn builder(&self, storage: &str) -> Result<ObjectStoreBuilder, Error> {
+        let mut builders = self.builders.lock().await;
+        if let Some(builder) = builders.get(storage) {
+            return Ok(builder.clone());
+        }
+        tracing::info!("Initializing storage with provider: {}", storage);
+        let builder = get_storage(Some(storage), &self.options, &self.aws).await?;
+        builders.insert(storage.to_owned(), builder.clone());
+        Ok(builder)
+    }
+
+    /// Object store builder for a location
+    pub async fn location_builder(&self, location: &str) -> Result<ObjectStoreBuilder, Error> {
+        self.builder(self.storage(location)?).await
+    }
+
+    /// Object store builder of catalogs that only support one storage backend, like the AWS
+    /// catalogs
+    pub async fn default_builder(&self, storage: &str) -> Result<ObjectStoreBuilder, Error> {
+        self.builder(self.storage.as_deref().unwrap_or(storage))
+            .await
+    }
+
+    /// Object store for a location
+    pub async fn object_store(&self, location: &str) -> Result<Arc<dyn ObjectStore>, Error> {
+        self.location_builder(location)
+            .await?
+            .build(Bucket::from_path(location)?)
+    }
+
+    /// Object store for a location if its storage backend was already initialized, doesn't wait
+    /// for a storage backend that is being initialized
+    fn initialized_object_store(
+        &self,
+        location: &str,
+    ) -> Option<Result<Arc<dyn ObjectStore>, Error>> {
+        let storage = self.storage(location).ok()?;
+        let builder = self.builders.try_lock()?.get(storage)?.clone();
+        Some(Bucket::from_path(location).and_then(|bucket| builder.build(bucket)))
+    }
+}
+
+/// Object store registry that creates the object stores of locations on demand
+///
+/// The object stores are built by the storage backends of the router that the catalogs already
+/// initialized, Azure object stores are created from the Azure options otherwise.
+#[derive(Debug)]
+pub struct StorageRegistry {
+    inner: DefaultObjectStoreRegistry,
+    azure: AzureOptions,
+    storage: Arc<StorageRouter>,
+}
+
+impl StorageRegistry {
+    pub fn new(options: &StorageOptions, storage: Arc<StorageRouter>) -> Self {
+        Self {
+            inner: DefaultObjectStoreRegistry::new(),
+            azure: options.azure.clone(),
+            storage,
+        }
+    }
+}
+
+impl ObjectStoreRegistry for StorageRegistry {
+    fn register_store(
+        &self,
+        url: &Url,
+        store: Arc<dyn ObjectStore>,
+    ) -> Option<Arc<dyn ObjectStore>> {
+        self.inner.register_store(url, store)
+    }
+
+    fn deregister_store(&self, url: &Url) -> Result<Arc<dyn ObjectStore>> {
+        self.inner.deregister_store(url)
+    }
+
+    fn get_store(&self, url: &Url) -> Result<Arc<dyn ObjectStore>> {
+        self.inner.get_store(url).or_else(|err| {
+            let store = match self.storage.initialized_object_store(url.as_str()) {
+                Some(store) => store.map_err(|err| DataFusionError::External(Box::new(err)))?,
+                None if AZURE_SCHEMES.contains(&url.scheme()) => self.azure.build(url)?,
+                None => return Err(err),
+            };
+            self.inner.register_store(url, store.clone());
+            Ok(store)
+        })
+    }
+}
+
+/// Register an Azure object store with the table options of an external table
+pub(crate) fn register_azure_store(
+    ctx: &SessionContext,
+    location: &str,
+    options: &HashMap<String, String>,
+) -> Result<(), DataFusionError> {
+    let prefix = format!("{}.", AzureOptions::PREFIX);
+    if !options.keys().any(|key| key.starts_with(&prefix)) {
+        return Ok(());
+    }
+
+    let table_path = ListingTableUrl::parse(location)?;
+    if !AZURE_SCHEMES.contains(&table_path.scheme()) {
+        return Ok(());
+    }
+
+    let mut azure = ctx
+        .state()
+        .default_table_options()
+        .extensions
+        .get::<AzureOptions>()
+        .cloned()
+        .unwrap_or_default();
+    for (key, value) in options.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+        azure.set(key, value)?;
+    }
+
+    ctx.register_object_store(table_path.as_ref(), azure.build(table_path.as_ref())?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::ObjectStoreExt;
+
+    use super::*;
+
+    #[test]
+    fn client_options_are_kept() {
+        let builder = AmazonS3Builder::new()
+            .with_proxy_url("http://proxy:3128")
+            .with_allow_http(true)
+            .with_config(AmazonS3ConfigKey::Client(ClientConfigKey::Timeout), "5s");
+        let options = client_options(&builder);
+        let copied = AmazonS3Builder::new().with_client_options(options);
+        for key in [
+            ClientConfigKey::ProxyUrl,
+            ClientConfigKey::AllowHttp,
+            ClientConfigKey::Timeout,
+        ] {
+            let key = AmazonS3ConfigKey::Client(key);
+            assert_eq!(
+                copied.get_config_value(&key),
+                builder.get_config_value(&key)
+            );
+        }
+    }
+
+    type Requests = Arc<std::sync::Mutex<Vec<String>>>;
+
+    /// Mock of the token endpoint and the storage service of GCS that records the requests
+    async fn serve_gcs() -> (String, Requests) {
+        use axum::{
+            extract::{Request, State},
+            http::{header, StatusCode},
+            routing::post,
+            Json, Router,
+        };
+
+        async fn token(State(requests): State<Requests>) -> Json<serde_json::Value> {
+            requests.lock().unwrap().push("token".to_owned());
+            Json(serde_json::json!({
+                "access_token": "s4-token",
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            }))
+        }
+        async fn storage(State(requests): State<Requests>, request: Request) -> StatusCode {
+            let authorization = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_owned())
+                .unwrap_or_default();
+            requests
+                .lock()
+                .unwrap()
+                .push(format!("{} {authorization}", request.uri().path()));
+            StatusCode::NOT_FOUND
+        }
+
+        let requests = Requests::default();
+        let app = Router::new()
+            .route("/token", post(token))
+            .fallback(storage)
+            .with_state(requests.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    /// Service account key whose tokens are issued by the token endpoint of the mock
+    fn service_account_key(url: &str) -> String {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let private_key = openssl::pkey::PKey::from_rsa(rsa)
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap();
+        serde_json::json!({
+            "type": "service_account",
+            "project_id": "frostbow",
+            "client_email": "frostbow@frostbow.iam.gserviceaccount.com",
+            "private_key": String::from_utf8(private_key).unwrap(),
+            "token_uri": format!("{url}/token"),
+        })
+        .to_string()
+    }
+
+    /// Requests of a read of `gs://bucket/key` with the GCS options
+    async fn gcs_requests(options: GcsOptions, requests: &Requests) -> Vec<String> {
+        let store = options
+            .builder()
+            .await
+            .unwrap()
+            .with_client_options(ClientOptions::new().with_allow_http(true))
+            .with_bucket_name("bucket")
+            .build()
+            .unwrap();
+        let result = store.get(&"key".into()).await;
+        assert!(matches!(result, Err(object_store::Error::NotFound { .. })));
+        requests.lock().unwrap().drain(..).collect()
+    }
+
+    #[tokio::test]
+    async fn gcs_emulator_is_used_without_credentials() {
+        let (url, requests) = serve_gcs().await;
+        let options = GcsOptions {
+            endpoint: Some(url),
+            use_emulator: Some(true),
+            ..Default::default()
+        };
+        let requests = gcs_requests(options, &requests).await;
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].contains("s4-token"), "{requests:?}");
+    }
+
+    #[tokio::test]
+    async fn gcs_service_accounts_are_used() {
+        let (url, requests) = serve_gcs().await;
+        let key = service_account_key(&url);
+        let path = std::env::temp_dir().join(format!("frostbow-{:x}.json", rand::random::<u64>()));
+        std::fs::write(&path, &key).unwrap();
+
+        let from_key = GcsOptions {
+            service_account_key: Some(key),
+            endpoint: Some(url.clone()),
+            ..Default::default()
+        };
+        let from_path = GcsOptions {
+            service_account_path: Some(path.to_str().unwrap().to_owned()),
+            endpoint: Some(url.clone()),
+            ..Default::default()
+        };
+        for options in [from_key, from_path] {
+            assert_eq!(
+                gcs_requests(options, &requests).await,
+                vec!["token", "/bucket/key Bearer s4-token"]
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn gcs_application_default_credentials_are_used() {
+        let (url, requests) = serve_gcs().await;
+        let path = std::env::temp_dir().join(format!("frostbow-{:x}.json", rand::random::<u64>()));
+        std::fs::write(&path, service_account_key(&url)).unwrap();
+        // No other test reads the application default credentials
+        std::env::set_var("GOOGLE_APPLICATION_CREDENTIALS", &path);
+
+        let options = GcsOptions {
+            endpoint: Some(url),
+            ..Default::default()
+        };
+        let requests = gcs_requests(options, &requests).await;
+        std::env::remove_var("GOOGLE_APPLICATION_CREDENTIALS");
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(requests, vec!["token", "/bucket/key Bearer s4-token"]);
+    }
+
+    #[test]
+    fn azure_options_are_passed_to_the_builder() {
+        let mut options = AzureOptions::default();
+        for (key, value) in [
+            ("azure.account", "account"),
+            ("azure.access_key", "key"),
+            ("azure.sas_token", "sv=2024&sig=x"),
+            ("azure.client_id", "client"),
+            ("azure.client_secret", "secret"),
+            ("azure.tenant_id", "tenant"),
+            ("azure.endpoint", "http://127.0.0.1:10000/account"),
+            ("azure.use_emulator", "true"),
+        ] {
+            options.set(key, value).unwrap();
+        }
+        assert!(options.set("azure.unknown", "x").is_err());
+
+        let builder = options.builder();
+        for (key, value) in [
+            (AzureConfigKey::AccountName, "account"),
+            (AzureConfigKey::AccessKey, "key"),
+            (AzureConfigKey::SasKey, "sv=2024&sig=x"),
+            (AzureConfigKey::ClientId, "client"),
+            (AzureConfigKey::ClientSecret, "secret"),
+            (AzureConfigKey::AuthorityId, "tenant"),
+            (AzureConfigKey::Endpoint, "http://127.0.0.1:10000/account"),
+            (AzureConfigKey::UseEmulator, "true"),
+        ] {
+            assert_eq!(builder.get_config_value(&key).as_deref(), Some(value));
+        }
+        let entries: Vec<_> = options
+            .entries()
+            .into_iter()
+            .filter_map(|entry| entry.value)
+            .collect();
+        assert_eq!(entries.len(), 8);
+    }
+
+    #[test]
+    fn storage_is_selected_by_the_location() {
+        let router = StorageRouter::new(None, &Default::default(), &Default::default());
+        for (location, storage) in [
+            ("s3://bucket/t", "s3"),
+            ("s3a://bucket/t", "s3"),
+            ("gs://bucket/t", "gcs"),
+            ("abfss://container@account.dfs.core.windows.net/t", "azure"),
+            ("az://container/t", "azure"),
+            ("file:///warehouse/t", "file"),
+            ("/warehouse/t", "file"),
+        ] {
+            assert_eq!(router.storage(location).unwrap(), storage, "{location}");
+        }
+        assert!(matches!(
+            router.storage("hdfs://namenode/t"),
+            Err(Error::NotFound(_))
+        ));
+
+        let router = StorageRouter::new(Some("memory"), &Default::default(), &Default::default());
+        assert_eq!(router.explicit(), Some("memory"));
+        assert_eq!(router.storage("s3://bucket/t").unwrap(), "memory");
+        assert_eq!(router.storage("hdfs://namenode/t").unwrap(), "memory");
+    }
+
+    #[tokio::test]
+    async fn storage_builders_are_shared() {
+        let router = StorageRouter::new(Some("memory"), &Default::default(), &Default::default());
+        let written = router.object_store("/warehouse/t").await.unwrap();
+        written.put(&"t/data".into(), "data".into()).await.unwrap();
+
+        let read = router.object_store("/warehouse/t").await.unwrap();
+        let bytes = read
+            .get(&"t/data".into())
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), b"data");
+    }
+}
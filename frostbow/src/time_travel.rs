@@ -0,0 +1,132 @@
+use std::{fmt, str::FromStr};
+
+use datafusion::{
+    arrow::compute::kernels::cast_utils::string_to_timestamp_nanos, error::DataFusionError,
+};
+use iceberg_rust::catalog::tabular::Tabular;
+
+/// Separator of the table and the version in a versioned table name
+///
+/// Starts with a NUL character, which isn't part of the names of real tables and doesn't show up
+/// in messages, so `t@snapshot=42` is printed.
+const VERSION_SEPARATOR: &str = "\0@";
+
+/// Version of a table selected with a `FOR ... AS OF` clause
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableVersion {
+    /// `FOR SYSTEM_VERSION AS OF <snapshot_id>` or `FOR VERSION AS OF <snapshot_id>`
+    Snapshot(i64),
+    /// `FOR SYSTEM_TIME AS OF '<timestamp>'`, the last snapshot committed at or before it
+    Time(String),
+    /// `FOR VERSION AS OF '<branch-or-tag>'`
+    Ref(String),
+}
+
+impl fmt::Display for TableVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableVersion::Snapshot(id) => write!(f, "snapshot={id}"),
+            TableVersion::Time(time) => write!(f, "time={time}"),
+            TableVersion::Ref(name) => write!(f, "ref={name}"),
+        }
+    }
+}
+
+impl FromStr for TableVersion {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some(("snapshot", id)) => id
+                .parse()
+                .map(TableVersion::Snapshot)
+                .map_err(|_| DataFusionError::Plan(format!("Snapshot id {id} is not a number."))),
+            Some(("time", time)) => Ok(TableVersion::Time(time.to_owned())),
+            Some(("ref", name)) => Ok(TableVersion::Ref(name.to_owned())),
+            _ => Err(DataFusionError::Plan(format!(
+                "Table version {s} is not supported."
+            ))),
+        }
+    }
+}
+
+impl TableVersion {
+    /// Name under which the version of the table is resolved by [`FrostbowCatalogList`](crate::catalog::FrostbowCatalogList)
+    ///
+    /// The version is separated by [`VERSION_SEPARATOR`], so the name can't be mistaken for a
+    /// table whose name contains `@snapshot=` or a similar string.
+    pub fn table_name(&self, table: &str) -> String {
+        format!("{table}{VERSION_SEPARATOR}{self}")
+    }
+
+    /// Split a name created with [`TableVersion::table_name`] into the table and its version
+    pub fn from_table_name(name: &str) -> Option<(&str, TableVersion)> {
+        let (table, version) = name.split_once(VERSION_SEPARATOR)?;
+        Some((table, version.parse().ok()?))
+    }
+
+    /// Id of the snapshot of the table that this version refers to
+    pub fn snapshot_id(&self, tabular: &Tabular) -> Result<i64, DataFusionError> {
+        let Tabular::Table(table) = tabular else {
+            return Err(DataFusionError::Plan(format!(
+                "Time travel is only supported for tables, {} is a view.",
+                tabular.identifier()
+            )));
+        };
+        let metadata = table.metadata();
+        let identifier = table.identifier();
+        match self {
+            TableVersion::Snapshot(id) => {
+                if metadata.snapshots.contains_key(id) {
+                    Ok(*id)
+                } else {
+                    Err(DataFusionError::Plan(format!(
+                        "Snapshot {id} of table {identifier} doesn't exist."
+                    )))
+                }
+            }
+            TableVersion::Time(time) => {
+                let timestamp_ms = string_to_timestamp_nanos(time)
+                    .map_err(|_| {
+                        DataFusionError::Plan(format!("Timestamp {time} can't be parsed."))
+                    })?
+                    .div_euclid(1_000_000);
+                metadata
+                    .snapshot_log
+                    .iter()
+                    .filter(|entry| entry.timestamp_ms <= timestamp_ms)
+                    .max_by_key(|entry| entry.timestamp_ms)
+                    .map(|entry| entry.snapshot_id)
+                    .ok_or_else(|| {
+                        DataFusionError::Plan(format!(
+                            "Table {identifier} has no snapshot at or before {time}."
+                        ))
+                    })
+            }
+            TableVersion::Ref(name) => metadata
+                .refs
+                .get(name)
+                .map(|reference| reference.snapshot_id)
+                .ok_or_else(|| {
+                    DataFusionError::Plan(format!(
+                        "Branch or tag {name} of table {identifier} doesn't exist."
+                    ))
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versioned_names_are_not_table_names() {
+        let name = TableVersion::Snapshot(42).table_name("t@snapshot=1");
+        assert_eq!(
+            TableVersion::from_table_name(&name),
+            Some(("t@snapshot=1", TableVersion::Snapshot(42)))
+        );
+        assert_eq!(TableVersion::from_table_name("t@snapshot=1"), None);
+    }
+}